@@ -0,0 +1,353 @@
+use num_traits::{FromPrimitive, ToPrimitive};
+use pathfinding::prelude::astar;
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::{add_to_rect, move_pos, span_rect, tile_center};
+use crate::types::{BeltRoute, Direction, EntityName, EntityType, FactorioEntity, Pos, Rect};
+
+const DEFAULT_BELT_COST: u32 = 10;
+const DEFAULT_TURN_COST: u32 = 2;
+const DEFAULT_UNDERGROUND_COST: u32 = 50;
+// yellow underground belts, the prototypes do not always report it
+const DEFAULT_UNDERGROUND_MAX: u8 = 5;
+// detour room around the ends of a bus, every extra lane adds one tile
+const SEARCH_MARGIN: f64 = 20.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TileKind {
+    Belt,
+    UndergroundInput,
+    UndergroundOutput,
+}
+
+/// start, end, corners and underground ends of the first lane, the other lanes are derived from them
+struct KeyTile {
+    pos: Pos,
+    in_direction: Direction,
+    out_direction: Direction,
+    kind: TileKind,
+}
+
+impl KeyTile {
+    /// lanes are offset to the right, at corners both by the incoming and the outgoing direction
+    fn lane_pos(&self, lane: i32) -> Pos {
+        if self.in_direction == self.out_direction {
+            move_pos(&self.pos, self.out_direction.clockwise(), lane)
+        } else {
+            move_pos(
+                &move_pos(&self.pos, self.in_direction.clockwise(), lane),
+                self.out_direction.clockwise(),
+                lane,
+            )
+        }
+    }
+}
+
+/// routes parallel belts from `from` to `to` as one bus with undergrounds where needed.
+///
+/// A* runs on the first lane, every step checks the tiles of all lanes beside it. turns
+/// are only allowed once every lane is past the last corner, so lanes never cross or touch.
+/// the state is (tile, direction, straight tiles since the last corner or underground exit)
+pub fn route_belts(
+    entity_graph: &EntityGraph,
+    route: &BeltRoute,
+) -> anyhow::Result<Vec<FactorioEntity>> {
+    let lanes = route.lanes.unwrap_or(1).max(1) as i32;
+    let belt_name = route
+        .belt_name
+        .clone()
+        .unwrap_or_else(|| EntityName::TransportBelt.to_string());
+    let underground_name = route
+        .underground_name
+        .clone()
+        .unwrap_or_else(|| EntityName::UndergroundBelt.to_string());
+    let belt_cost = route.belt_cost.unwrap_or(DEFAULT_BELT_COST);
+    let turn_cost = route.turn_cost.unwrap_or(DEFAULT_TURN_COST);
+    let underground_cost = route.underground_cost.unwrap_or(DEFAULT_UNDERGROUND_COST);
+    let avoid_resources = route.avoid_resources.unwrap_or(false);
+    let from_direction = orthogonal_direction(route.from_direction)?;
+    let to_direction = orthogonal_direction(route.to_direction)?;
+    let underground_max = entity_graph
+        .entity_prototypes()
+        .get(&underground_name)
+        .and_then(|prototype| *prototype.max_underground_distance)
+        .unwrap_or(DEFAULT_UNDERGROUND_MAX) as i32;
+
+    let from: Pos = (&route.from).into();
+    let to: Pos = (&route.to).into();
+    let area = span_rect(&route.from, &route.to, SEARCH_MARGIN + lanes as f64);
+    let blocked_tree = entity_graph.blocked_tree();
+    let resource_tree = entity_graph.resource_tree();
+    let is_free = |pos: &Pos| {
+        let center = tile_center(pos);
+        let rect: QuadTreeRect = add_to_rect(&Rect::from_wh(0.5, 0.5), &center).into();
+        area.contains(&center)
+            && blocked_tree.query(rect).is_empty()
+            && (!avoid_resources || resource_tree.query(rect).is_empty())
+    };
+    let lanes_free = |pos: &Pos, direction: Direction| {
+        (0..lanes).all(|lane| is_free(&move_pos(pos, direction.clockwise(), lane)))
+    };
+    // the square all lanes pass through when turning, the outer lanes of left turns
+    // reach beyond the tiles beside the first lane
+    let corner_free = |pos: &Pos, direction: Direction, turned: Direction| {
+        (0..lanes).all(|i| {
+            (0..lanes).all(|j| {
+                is_free(&move_pos(
+                    &move_pos(pos, direction.clockwise(), i),
+                    turned.clockwise(),
+                    j,
+                ))
+            })
+        })
+    };
+
+    if !lanes_free(&from, from_direction) {
+        bail!("start of the belt route is blocked");
+    }
+    if !lanes_free(&to, to_direction) {
+        bail!("end of the belt route is blocked");
+    }
+    if route.merge.unwrap_or(false) {
+        let entity_tree = entity_graph.inner_tree();
+        for lane in 0..lanes {
+            let target = move_pos(
+                &move_pos(&to, to_direction.clockwise(), lane),
+                to_direction,
+                1,
+            );
+            let sideloadable = entity_tree
+                .query(add_to_rect(&Rect::from_wh(0.5, 0.5), &tile_center(&target)).into())
+                .iter()
+                .any(|(entity, _, _)| {
+                    entity.entity_type == EntityType::TransportBelt.to_string()
+                        && entity.direction % 4 != route.to_direction % 4
+                });
+            if !sideloadable {
+                bail!("no belt to sideload into at {:?}", tile_center(&target));
+            }
+        }
+    }
+
+    // the first lane must be this far past a corner before the bus may turn again,
+    // the inner lanes of two turns in the same direction need at least one tile between them
+    let turn_after = (2 * lanes - 1) as u32;
+    let straight_max = turn_after.max(lanes as u32);
+    let cheapest_tile = belt_cost.min(underground_cost / underground_max as u32);
+    let (path, _cost) = astar(
+        &(
+            from.clone(),
+            from_direction.to_u8().unwrap(),
+            // a straight start works like a corner at the tile before it
+            lanes as u32,
+        ),
+        |(pos, direction, straight)| {
+            let direction = Direction::from_u8(*direction).unwrap();
+            let mut successors: Vec<((Pos, u8, u32), u32)> = vec![];
+            let next = move_pos(pos, direction, 1);
+            if lanes_free(&next, direction) {
+                successors.push((
+                    (
+                        next,
+                        direction.to_u8().unwrap(),
+                        (straight + 1).min(straight_max),
+                    ),
+                    belt_cost,
+                ));
+            }
+            if *straight >= turn_after {
+                for turned in &[direction.clockwise(), direction.clockwise().opposite()] {
+                    let next = move_pos(pos, *turned, 1);
+                    if corner_free(pos, direction, *turned) && lanes_free(&next, *turned) {
+                        successors
+                            .push(((next, turned.to_u8().unwrap(), 1), belt_cost + turn_cost));
+                    }
+                }
+            }
+            // the input of every lane has to be past its last corner
+            if *straight >= lanes as u32 {
+                for length in 2..=underground_max {
+                    let exit = move_pos(pos, direction, length);
+                    if lanes_free(&exit, direction) {
+                        successors.push(((exit, direction.to_u8().unwrap(), 0), underground_cost));
+                    }
+                }
+            }
+            successors
+        },
+        |(pos, _, _)| pos.distance(&to) * cheapest_tile,
+        |(pos, direction, straight)| {
+            *pos == to
+                && if lanes == 1 {
+                    // a single belt may turn on its last tile, but not on an underground output
+                    *direction == to_direction.to_u8().unwrap()
+                        || (*straight > 0 && *direction != to_direction.opposite().to_u8().unwrap())
+                } else {
+                    // left turns move the corners of the outer lanes forward
+                    *direction == to_direction.to_u8().unwrap() && *straight >= lanes as u32 - 1
+                }
+        },
+    )
+    .ok_or_else(|| anyhow!("no belt route found"))?;
+
+    let mut key_tiles: Vec<KeyTile> = vec![];
+    for i in 0..path.len() {
+        let (pos, direction, _) = &path[i];
+        let in_direction = Direction::from_u8(*direction).unwrap();
+        let out_direction = match path.get(i + 1) {
+            Some((_, direction, _)) => Direction::from_u8(*direction).unwrap(),
+            None => to_direction,
+        };
+        let kind = if i + 1 < path.len() && pos.distance(&path[i + 1].0) > 1 {
+            TileKind::UndergroundInput
+        } else if i > 0 && pos.distance(&path[i - 1].0) > 1 {
+            TileKind::UndergroundOutput
+        } else {
+            TileKind::Belt
+        };
+        if i == 0 || i + 1 == path.len() || in_direction != out_direction || kind != TileKind::Belt
+        {
+            key_tiles.push(KeyTile {
+                pos: pos.clone(),
+                in_direction,
+                out_direction,
+                kind,
+            });
+        }
+    }
+
+    let belt_type = entity_graph
+        .entity_prototypes()
+        .get(&belt_name)
+        .map(|prototype| prototype.entity_type.clone())
+        .unwrap_or_else(|| EntityType::TransportBelt.to_string());
+    let mut result: Vec<FactorioEntity> = vec![];
+    for lane in 0..lanes {
+        for (i, key_tile) in key_tiles.iter().enumerate() {
+            let pos = key_tile.lane_pos(lane);
+            result.push(match key_tile.kind {
+                TileKind::Belt => belt_entity(&belt_name, &belt_type, &pos, key_tile.out_direction),
+                TileKind::UndergroundInput => belt_entity(
+                    &underground_name,
+                    &EntityType::UndergroundBelt.to_string(),
+                    &pos,
+                    key_tile.out_direction,
+                ),
+                // same as build_entity_path, outputs are placed facing backwards
+                TileKind::UndergroundOutput => belt_entity(
+                    &underground_name,
+                    &EntityType::UndergroundBelt.to_string(),
+                    &pos,
+                    key_tile.in_direction.opposite(),
+                ),
+            });
+            if key_tile.kind == TileKind::UndergroundInput {
+                continue;
+            }
+            if let Some(next_tile) = key_tiles.get(i + 1) {
+                let next = next_tile.lane_pos(lane);
+                let mut pos = move_pos(&pos, key_tile.out_direction, 1);
+                while pos != next {
+                    let remaining = pos.distance(&next);
+                    result.push(belt_entity(
+                        &belt_name,
+                        &belt_type,
+                        &pos,
+                        key_tile.out_direction,
+                    ));
+                    pos = move_pos(&pos, key_tile.out_direction, 1);
+                    if pos.distance(&next) >= remaining {
+                        bail!("lane {} does not line up at {:?}", lane, tile_center(&pos));
+                    }
+                }
+            }
+        }
+    }
+    for entity in &result {
+        if !is_free(&(&entity.position).into()) {
+            bail!("belt route collides at {:?}", entity.position);
+        }
+    }
+    Ok(result)
+}
+
+fn orthogonal_direction(direction: u8) -> anyhow::Result<Direction> {
+    match Direction::from_u8(direction) {
+        Some(direction) if direction.to_u8().unwrap() % 2 == 0 => Ok(direction),
+        _ => Err(anyhow!(
+            "invalid direction {}, expected 0, 2, 4 or 6",
+            direction
+        )),
+    }
+}
+
+fn belt_entity(name: &str, entity_type: &str, pos: &Pos, direction: Direction) -> FactorioEntity {
+    let position = tile_center(pos);
+    FactorioEntity {
+        name: name.into(),
+        entity_type: entity_type.into(),
+        bounding_box: add_to_rect(&Rect::from_wh(0.8, 0.8), &position),
+        position,
+        direction: direction.to_u8().unwrap(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::factorio::tests::fixture_world;
+    use crate::types::Position;
+
+    use super::*;
+
+    fn belt_route(from: Position, to: Position, lanes: u32) -> BeltRoute {
+        BeltRoute {
+            from,
+            from_direction: 2,
+            to,
+            to_direction: 2,
+            lanes: Some(lanes),
+            belt_name: None,
+            underground_name: None,
+            merge: None,
+            avoid_resources: None,
+            belt_cost: None,
+            turn_cost: None,
+            underground_cost: None,
+        }
+    }
+
+    #[test]
+    fn test_route_belts() {
+        let world = fixture_world();
+        // the water from 38,38 to 42,42 is too wide for undergrounds, both lanes go around
+        let route = belt_route(Position::new(30.5, 39.5), Position::new(50.5, 39.5), 2);
+        let entities = route_belts(&world.entity_graph, &route).unwrap();
+        let positions: HashSet<Pos> = entities.iter().map(|e| (&e.position).into()).collect();
+        assert_eq!(positions.len(), entities.len());
+        assert!(positions.contains(&Pos(30, 39)) && positions.contains(&Pos(30, 40)));
+        assert!(positions.contains(&Pos(50, 39)) && positions.contains(&Pos(50, 40)));
+        for pos in &positions {
+            assert!(pos.0 < 38 || pos.0 > 42 || pos.1 < 38 || pos.1 > 42);
+        }
+        assert!(entities
+            .iter()
+            .all(|e| e.entity_type == EntityType::TransportBelt.to_string()));
+
+        // the iron ore around 0,0 can only be avoided by a detour
+        let mut route = belt_route(Position::new(-12.5, 0.5), Position::new(12.5, 0.5), 1);
+        let straight = route_belts(&world.entity_graph, &route).unwrap();
+        route.avoid_resources = Some(true);
+        let detour = route_belts(&world.entity_graph, &route).unwrap();
+        assert!(detour.len() > straight.len());
+        for entity in &detour {
+            let pos: Pos = (&entity.position).into();
+            assert!(pos.0 < -6 || pos.0 > 5 || pos.1 < -6 || pos.1 > 5);
+        }
+
+        route.merge = Some(true);
+        assert!(route_belts(&world.entity_graph, &route).is_err());
+    }
+}
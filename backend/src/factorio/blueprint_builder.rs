@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::factorio::blueprint_transform::encode_blueprint_json;
+use crate::types::{
+    CombinatorSettings, EntityName, EntityType, FactorioEntity, Position, SignalId,
+};
+
+/// blueprint version 1.0.0.0
+const BLUEPRINT_VERSION: u64 = 281_474_976_710_656;
+
+/// collects entities and encodes them as blueprint string.
+/// positions are moved next to the origin by an even offset so rails stay on their grid
+pub struct BlueprintBuilder {
+    label: String,
+    entities: Vec<FactorioEntity>,
+}
+
+impl BlueprintBuilder {
+    pub fn new(label: &str) -> Self {
+        BlueprintBuilder {
+            label: label.into(),
+            entities: vec![],
+        }
+    }
+
+    /// ghosts are added as the entity they stand for, resources, trees, rocks, cliffs, enemies and
+    /// characters are skipped
+    pub fn add_entity(&mut self, entity: &FactorioEntity) -> &mut Self {
+        let mut entity = entity.clone();
+        if entity.entity_type == EntityType::EntityGhost.to_string() {
+            match (entity.ghost_name.take(), entity.ghost_type.take()) {
+                (Some(ghost_name), Some(ghost_type)) => {
+                    entity.name = ghost_name;
+                    entity.entity_type = ghost_type;
+                }
+                _ => return self,
+            }
+        }
+        if entity.is_minable()
+            || entity.is_enemy()
+            || entity.entity_type == EntityType::Cliff.to_string()
+            || entity.entity_type == EntityType::Resource.to_string()
+            || entity.entity_type == EntityType::Character.to_string()
+            || entity.entity_type == EntityType::Fish.to_string()
+            || entity.entity_type == EntityType::ItemEntity.to_string()
+            || entity.entity_type == EntityType::FlyingText.to_string()
+        {
+            return self;
+        }
+        if !self
+            .entities
+            .iter()
+            .any(|other| other.name == entity.name && other.position == entity.position)
+        {
+            self.entities.push(entity);
+        }
+        self
+    }
+
+    pub fn add_entities(&mut self, entities: &[FactorioEntity]) -> &mut Self {
+        for entity in entities {
+            self.add_entity(entity);
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let offset = self.offset();
+        let numbers: HashMap<Position, usize> = self
+            .entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (entity.position.clone(), index + 1))
+            .collect();
+        let underground_types = self.underground_types();
+        let entities: Vec<Value> = self
+            .entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| {
+                let mut value = Map::new();
+                value.insert("entity_number".into(), (index + 1).into());
+                value.insert("name".into(), entity.name.clone().into());
+                value.insert(
+                    "position".into(),
+                    json!({
+                        "x": entity.position.x() - offset.x(),
+                        "y": entity.position.y() - offset.y(),
+                    }),
+                );
+                if entity.direction != 0 {
+                    value.insert("direction".into(), entity.direction.into());
+                }
+                if let Some(recipe) = entity.recipe.as_ref() {
+                    value.insert("recipe".into(), recipe.clone().into());
+                }
+                if let Some(station_name) = entity.station_name.as_ref() {
+                    value.insert("station".into(), station_name.clone().into());
+                }
+                if let Some(underground_type) = entity.belt_to_ground_type.as_ref() {
+                    value.insert("type".into(), underground_type.clone().into());
+                } else if let Some(underground_type) = underground_types.get(&index) {
+                    value.insert("type".into(), (*underground_type).into());
+                }
+                if let Some(settings) = entity.combinator.as_ref() {
+                    value.insert(
+                        "control_behavior".into(),
+                        control_behavior(&entity.entity_type, settings),
+                    );
+                }
+                // only wires between exported entities survive
+                let mut connections = Map::new();
+                for wire in entity.wire_connections.as_ref().unwrap_or(&vec![]) {
+                    if let Some(target) = numbers.get(&wire.target_position) {
+                        let point = connections
+                            .entry(wire.source_circuit_id.to_string())
+                            .or_insert_with(|| json!({}));
+                        let wires = point
+                            .as_object_mut()
+                            .unwrap()
+                            .entry(wire.wire.clone())
+                            .or_insert_with(|| json!([]));
+                        wires.as_array_mut().unwrap().push(json!({
+                            "entity_id": target,
+                            "circuit_id": wire.target_circuit_id,
+                        }));
+                    }
+                }
+                if !connections.is_empty() {
+                    value.insert("connections".into(), Value::Object(connections));
+                }
+                Value::Object(value)
+            })
+            .collect();
+        let icon = match self.entities.first() {
+            Some(entity)
+                if entity.name == EntityName::StraightRail.to_string()
+                    || entity.name == EntityName::CurvedRail.to_string() =>
+            {
+                EntityName::Rail.to_string()
+            }
+            Some(entity) => entity.name.clone(),
+            None => EntityName::TransportBelt.to_string(),
+        };
+        json!({
+            "blueprint": {
+                "item": "blueprint",
+                "label": self.label,
+                "icons": [{"index": 1, "signal": {"type": "item", "name": icon}}],
+                "entities": entities,
+                "version": BLUEPRINT_VERSION,
+            }
+        })
+    }
+
+    pub fn build(&self) -> anyhow::Result<String> {
+        if self.entities.is_empty() {
+            bail!("blueprint has no entities");
+        }
+        encode_blueprint_json(&self.to_json())
+    }
+
+    fn offset(&self) -> Position {
+        let (mut min_x, mut min_y) = (f64::MAX, f64::MAX);
+        let (mut max_x, mut max_y) = (f64::MIN, f64::MIN);
+        for entity in &self.entities {
+            min_x = min_x.min(entity.bounding_box.left_top.x());
+            min_y = min_y.min(entity.bounding_box.left_top.y());
+            max_x = max_x.max(entity.bounding_box.right_bottom.x());
+            max_y = max_y.max(entity.bounding_box.right_bottom.y());
+        }
+        if self.entities.is_empty() {
+            return Position::new(0., 0.);
+        }
+        Position::new(
+            ((min_x + max_x) / 4.).floor() * 2.,
+            ((min_y + max_y) / 4.).floor() * 2.,
+        )
+    }
+
+    /// planned underground belts do not know which end they are, so belts facing the same way
+    /// on one line are paired up along the belt: input, output, input, ...
+    fn underground_types(&self) -> HashMap<usize, &'static str> {
+        let mut lines: HashMap<(String, u8, i64), Vec<(f64, usize)>> = HashMap::new();
+        for (index, entity) in self.entities.iter().enumerate() {
+            if entity.entity_type != EntityType::UndergroundBelt.to_string()
+                || entity.belt_to_ground_type.is_some()
+            {
+                continue;
+            }
+            let (line, along) = match entity.direction {
+                0 => (entity.position.x(), -entity.position.y()),
+                2 => (entity.position.y(), entity.position.x()),
+                4 => (entity.position.x(), entity.position.y()),
+                _ => (entity.position.y(), -entity.position.x()),
+            };
+            lines
+                .entry((entity.name.clone(), entity.direction, line.floor() as i64))
+                .or_insert_with(Vec::new)
+                .push((along, index));
+        }
+        let mut types: HashMap<usize, &'static str> = HashMap::new();
+        for (_, mut belts) in lines {
+            belts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            for (nth, (_, index)) in belts.into_iter().enumerate() {
+                types.insert(index, if nth % 2 == 0 { "input" } else { "output" });
+            }
+        }
+        types
+    }
+}
+
+fn signal_json(signal: &SignalId) -> Value {
+    json!({"type": signal.signal_type, "name": signal.name})
+}
+
+/// combinator settings in the blueprint format
+fn control_behavior(entity_type: &str, settings: &CombinatorSettings) -> Value {
+    if entity_type == EntityType::ConstantCombinator.to_string() {
+        let filters: Vec<Value> = settings
+            .constants
+            .as_ref()
+            .unwrap_or(&vec![])
+            .iter()
+            .enumerate()
+            .map(|(index, constant)| {
+                json!({
+                    "signal": signal_json(&constant.signal),
+                    "count": constant.count,
+                    "index": index + 1,
+                })
+            })
+            .collect();
+        return json!({ "filters": filters });
+    }
+    let mut conditions = Map::new();
+    if let Some(signal) = settings.first_signal.as_ref() {
+        conditions.insert("first_signal".into(), signal_json(signal));
+    }
+    if let Some(signal) = settings.second_signal.as_ref() {
+        conditions.insert("second_signal".into(), signal_json(signal));
+    }
+    if let Some(signal) = settings.output_signal.as_ref() {
+        conditions.insert("output_signal".into(), signal_json(signal));
+    }
+    if entity_type == EntityType::ArithmeticCombinator.to_string() {
+        if let Some(constant) = settings.constant {
+            conditions.insert("second_constant".into(), constant.into());
+        }
+        if let Some(operation) = settings.operation.as_ref() {
+            conditions.insert("operation".into(), operation.clone().into());
+        }
+        json!({ "arithmetic_conditions": conditions })
+    } else {
+        if let Some(constant) = settings.constant {
+            conditions.insert("constant".into(), constant.into());
+        }
+        if let Some(comparator) = settings.operation.as_ref() {
+            conditions.insert("comparator".into(), comparator.clone().into());
+        }
+        if let Some(copy_count_from_input) = settings.copy_count_from_input {
+            conditions.insert("copy_count_from_input".into(), copy_count_from_input.into());
+        }
+        json!({ "decider_conditions": conditions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factorio_blueprint::{BlueprintCodec, Container};
+
+    use crate::factorio::blueprint_transform::decode_blueprint_json;
+    use crate::factorio::tests::fixture_entity;
+    use crate::types::{CircuitSignal, FactorioWireConnection};
+
+    use super::*;
+
+    #[test]
+    fn test_blueprint_builder() {
+        let mut combinator = fixture_entity(
+            "constant-combinator",
+            EntityType::ConstantCombinator,
+            &Position::new(100.5, 50.5),
+            0,
+        );
+        combinator.combinator = Some(CombinatorSettings {
+            constants: Some(vec![CircuitSignal {
+                signal: SignalId {
+                    signal_type: "item".into(),
+                    name: "iron-plate".into(),
+                },
+                count: 50,
+            }]),
+            ..Default::default()
+        });
+        combinator.wire_connections = Some(vec![FactorioWireConnection {
+            wire: "red".into(),
+            target_name: "small-electric-pole".into(),
+            target_position: Position::new(101.5, 50.5),
+            source_circuit_id: 1,
+            target_circuit_id: 1,
+        }]);
+        let entities = vec![
+            combinator,
+            fixture_entity(
+                "small-electric-pole",
+                EntityType::ElectricPole,
+                &Position::new(101.5, 50.5),
+                0,
+            ),
+            fixture_entity(
+                "underground-belt",
+                EntityType::UndergroundBelt,
+                &Position::new(102.5, 50.5),
+                2,
+            ),
+            fixture_entity(
+                "underground-belt",
+                EntityType::UndergroundBelt,
+                &Position::new(105.5, 50.5),
+                2,
+            ),
+            FactorioEntity {
+                belt_to_ground_type: Some("output".into()),
+                ..fixture_entity(
+                    "underground-belt",
+                    EntityType::UndergroundBelt,
+                    &Position::new(106.5, 51.5),
+                    0,
+                )
+            },
+            fixture_entity("tree-01", EntityType::Tree, &Position::new(103.5, 50.5), 0),
+        ];
+        let mut builder = BlueprintBuilder::new("test");
+        builder.add_entities(&entities);
+        let blueprint = builder.build().unwrap();
+
+        let value = decode_blueprint_json(&blueprint).unwrap();
+        let bp_entities = value["blueprint"]["entities"].as_array().unwrap();
+        assert_eq!(bp_entities.len(), 5);
+        assert_eq!(bp_entities[0]["position"], json!({"x": -1.5, "y": 0.5}));
+        assert_eq!(bp_entities[0]["connections"]["1"]["red"][0]["entity_id"], 2);
+        assert_eq!(
+            bp_entities[0]["control_behavior"]["filters"][0]["signal"]["name"],
+            "iron-plate"
+        );
+        assert_eq!(bp_entities[2]["type"], "input");
+        assert_eq!(bp_entities[3]["type"], "output");
+        assert_eq!(bp_entities[4]["type"], "output");
+
+        match BlueprintCodec::decode_string(&blueprint).unwrap() {
+            Container::Blueprint(blueprint) => assert_eq!(blueprint.entities.len(), 5),
+            _ => panic!("expected blueprint"),
+        }
+        assert!(BlueprintBuilder::new("empty").build().is_err());
+    }
+}
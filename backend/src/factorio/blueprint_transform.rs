@@ -0,0 +1,348 @@
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use base64::{decode, encode};
+use dashmap::DashMap;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde_json::Value;
+
+use crate::factorio::util::blueprint_build_area;
+use crate::types::{Direction, EntityName, FactorioEntityPrototype};
+
+/// decodes a blueprint string into its json, every transform works on the json so fields
+/// the factorio_blueprint crate does not know about survive the round trip
+pub fn decode_blueprint_json(blueprint: &str) -> anyhow::Result<Value> {
+    if !blueprint.starts_with('0') {
+        bail!("unsupported blueprint version");
+    }
+    let content = decode(blueprint[1..].trim())?;
+    let mut content = ZlibDecoder::new(&content[..]);
+    let mut s = String::new();
+    content.read_to_string(&mut s)?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+pub fn encode_blueprint_json(value: &Value) -> anyhow::Result<String> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(serde_json::to_string(value)?.as_bytes())?;
+    Ok(format!("0{}", encode(&encoder.finish()?)))
+}
+
+/// rotates the blueprint clockwise so that what faced north now faces the given direction
+pub fn rotate_blueprint(blueprint: &str, direction: u8) -> anyhow::Result<String> {
+    if direction % 2 != 0 || direction > 7 {
+        bail!("only north, east, south and west are supported");
+    }
+    let quarter_turns = direction / 2;
+    transform_blueprint(
+        blueprint,
+        |x, y| match quarter_turns {
+            0 => (x, y),
+            1 => (-y, x),
+            2 => (-x, -y),
+            _ => (y, -x),
+        },
+        |_name, entity_direction| (entity_direction + direction) % 8,
+        false,
+    )
+}
+
+/// mirrors the blueprint left to right, or top to bottom if vertical is set.
+/// splitter priorities swap sides, underground belts and loaders keep their input/output type
+/// as both halves still face along the belt and their positions mirror together
+pub fn mirror_blueprint(blueprint: &str, vertical: bool) -> anyhow::Result<String> {
+    let curved_rail = EntityName::CurvedRail.to_string();
+    transform_blueprint(
+        blueprint,
+        |x, y| if vertical { (x, -y) } else { (-x, y) },
+        |name, entity_direction| {
+            // curved rails use odd directions for their mirrored shape
+            let base = if name == curved_rail { 9 } else { 8 };
+            let base = if vertical { base + 4 } else { base };
+            (base - entity_direction) % 8
+        },
+        true,
+    )
+}
+
+/// repeats the blueprint count times towards direction, each copy is moved by the build area
+/// rounded up to a multiple of grid so rails (grid 2) or chunk aligned builds (grid 32) line up
+pub fn tile_blueprint(
+    entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    blueprint: &str,
+    count: u32,
+    direction: Direction,
+    grid: u32,
+) -> anyhow::Result<String> {
+    if count == 0 {
+        bail!("count must be at least 1");
+    }
+    let (dx, dy) = match direction {
+        Direction::North => (0., -1.),
+        Direction::East => (1., 0.),
+        Direction::South => (0., 1.),
+        Direction::West => (-1., 0.),
+        _ => bail!("only north, east, south and west are supported"),
+    };
+    let build_area = blueprint_build_area(entity_prototypes, blueprint);
+    let size = if dx != 0. {
+        build_area.width()
+    } else {
+        build_area.height()
+    };
+    if size <= 0. {
+        bail!("blueprint has no known entities");
+    }
+    let grid = grid.max(1) as f64;
+    let step = (size / grid).ceil() * grid;
+
+    let mut value = decode_blueprint_json(blueprint)?;
+    let blueprint = blueprint_object(&mut value)?;
+    let entities: Vec<Value> = array_of(blueprint, "entities").clone();
+    let tiles: Vec<Value> = array_of(blueprint, "tiles").clone();
+    let schedules: Vec<Value> = array_of(blueprint, "schedules").clone();
+    let max_number = entities
+        .iter()
+        .filter_map(|entity| entity["entity_number"].as_u64())
+        .max()
+        .unwrap_or(0);
+    let mut all_entities = entities.clone();
+    let mut all_tiles = tiles.clone();
+    let mut all_schedules = schedules.clone();
+    for copy in 1..count {
+        let offset = (dx * step * copy as f64, dy * step * copy as f64);
+        let number_offset = max_number * copy as u64;
+        for entity in &entities {
+            let mut entity = entity.clone();
+            translate(&mut entity["position"], offset);
+            offset_entity_numbers(&mut entity, number_offset);
+            all_entities.push(entity);
+        }
+        for tile in &tiles {
+            let mut tile = tile.clone();
+            translate(&mut tile["position"], offset);
+            all_tiles.push(tile);
+        }
+        for schedule in &schedules {
+            let mut schedule = schedule.clone();
+            if let Some(locomotives) = schedule["locomotives"].as_array_mut() {
+                for locomotive in locomotives {
+                    offset_number(locomotive, number_offset);
+                }
+            }
+            all_schedules.push(schedule);
+        }
+    }
+    set_array(blueprint, "entities", all_entities);
+    set_array(blueprint, "tiles", all_tiles);
+    set_array(blueprint, "schedules", all_schedules);
+    encode_blueprint_json(&value)
+}
+
+fn transform_blueprint(
+    blueprint: &str,
+    transform_position: impl Fn(f64, f64) -> (f64, f64),
+    transform_direction: impl Fn(&str, u8) -> u8,
+    swap_priorities: bool,
+) -> anyhow::Result<String> {
+    let mut value = decode_blueprint_json(blueprint)?;
+    let blueprint = blueprint_object(&mut value)?;
+    let transform_vector = |position: &mut Value| {
+        if let (Some(x), Some(y)) = (position["x"].as_f64(), position["y"].as_f64()) {
+            let (x, y) = transform_position(x, y);
+            position["x"] = x.into();
+            position["y"] = y.into();
+        }
+    };
+    if let Some(entities) = blueprint
+        .get_mut("entities")
+        .and_then(|entities| entities.as_array_mut())
+    {
+        for entity in entities {
+            transform_vector(&mut entity["position"]);
+            // inserter pickup and drop positions are relative to the inserter
+            for key in &["pickup_position", "drop_position"] {
+                if let Some(position) = entity.get_mut(*key) {
+                    transform_vector(position);
+                }
+            }
+            let name = entity["name"].as_str().unwrap_or_default().to_owned();
+            let direction = entity["direction"].as_u64().unwrap_or(0) as u8 % 8;
+            let direction = transform_direction(&name, direction);
+            let entity = entity
+                .as_object_mut()
+                .ok_or_else(|| anyhow!("invalid blueprint entity"))?;
+            if direction != 0 {
+                entity.insert("direction".into(), direction.into());
+            } else {
+                entity.remove("direction");
+            }
+            if swap_priorities {
+                for key in &["input_priority", "output_priority"] {
+                    if let Some(priority) = entity.get_mut(*key) {
+                        let swapped = match priority.as_str() {
+                            Some("left") => "right",
+                            Some("right") => "left",
+                            _ => continue,
+                        };
+                        *priority = swapped.into();
+                    }
+                }
+            }
+        }
+    }
+    if let Some(tiles) = blueprint
+        .get_mut("tiles")
+        .and_then(|tiles| tiles.as_array_mut())
+    {
+        // tile positions are their top left corner, transform the center instead
+        for tile in tiles {
+            translate(&mut tile["position"], (0.5, 0.5));
+            transform_vector(&mut tile["position"]);
+            translate(&mut tile["position"], (-0.5, -0.5));
+        }
+    }
+    encode_blueprint_json(&value)
+}
+
+fn blueprint_object(value: &mut Value) -> anyhow::Result<&mut serde_json::Map<String, Value>> {
+    value
+        .get_mut("blueprint")
+        .and_then(|blueprint| blueprint.as_object_mut())
+        .ok_or_else(|| anyhow!("blueprint books are not supported!"))
+}
+
+fn array_of<'a>(blueprint: &'a serde_json::Map<String, Value>, key: &str) -> &'a Vec<Value> {
+    static EMPTY: Vec<Value> = Vec::new();
+    blueprint
+        .get(key)
+        .and_then(|value| value.as_array())
+        .unwrap_or(&EMPTY)
+}
+
+fn set_array(blueprint: &mut serde_json::Map<String, Value>, key: &str, values: Vec<Value>) {
+    if !values.is_empty() {
+        blueprint.insert(key.into(), Value::Array(values));
+    }
+}
+
+fn translate(position: &mut Value, (dx, dy): (f64, f64)) {
+    if let (Some(x), Some(y)) = (position["x"].as_f64(), position["y"].as_f64()) {
+        position["x"] = (x + dx).into();
+        position["y"] = (y + dy).into();
+    }
+}
+
+fn offset_number(value: &mut Value, offset: u64) {
+    if let Some(number) = value.as_u64() {
+        *value = (number + offset).into();
+    }
+}
+
+/// moves the entity number and all references to other entities of the copy,
+/// wires use entity_id inside connections and power poles list their neighbours
+fn offset_entity_numbers(entity: &mut Value, offset: u64) {
+    fn offset_entity_ids(value: &mut Value, offset: u64) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    if key == "entity_id" {
+                        offset_number(value, offset);
+                    } else {
+                        offset_entity_ids(value, offset);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    offset_entity_ids(value, offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    offset_number(&mut entity["entity_number"], offset);
+    if let Some(connections) = entity.get_mut("connections") {
+        offset_entity_ids(connections, offset);
+    }
+    if let Some(neighbours) = entity
+        .get_mut("neighbours")
+        .and_then(|neighbours| neighbours.as_array_mut())
+    {
+        for neighbour in neighbours {
+            offset_number(neighbour, offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::factorio::tests::fixture_entity_prototypes;
+
+    use super::*;
+
+    fn fixture_blueprint() -> String {
+        encode_blueprint_json(&json!({
+            "blueprint": {
+                "item": "blueprint",
+                "version": 77_310_525_440u64,
+                "entities": [
+                    {"entity_number": 1, "name": "transport-belt", "position": {"x": 0.5, "y": 0.5}},
+                    {"entity_number": 2, "name": "splitter", "position": {"x": 1.0, "y": -0.5},
+                        "input_priority": "left", "output_priority": "right"},
+                    {"entity_number": 3, "name": "transport-belt", "position": {"x": 1.5, "y": 0.5},
+                        "direction": 2, "connections": {"1": {"red": [{"entity_id": 1}]}}},
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rotate_and_mirror_blueprint() {
+        let blueprint = fixture_blueprint();
+        let rotated = rotate_blueprint(&blueprint, 2).unwrap();
+        let value = decode_blueprint_json(&rotated).unwrap();
+        let entities = &value["blueprint"]["entities"];
+        assert_eq!(entities[0]["position"], json!({"x": -0.5, "y": 0.5}));
+        assert_eq!(entities[0]["direction"], 2);
+        assert_eq!(entities[2]["direction"], 4);
+        assert_eq!(entities[1]["input_priority"], "left");
+
+        let mut turned = blueprint.clone();
+        for _ in 0..4 {
+            turned = rotate_blueprint(&turned, 2).unwrap();
+        }
+        assert_eq!(
+            decode_blueprint_json(&turned).unwrap(),
+            decode_blueprint_json(&blueprint).unwrap()
+        );
+
+        let mirrored = mirror_blueprint(&blueprint, false).unwrap();
+        let value = decode_blueprint_json(&mirrored).unwrap();
+        let entities = &value["blueprint"]["entities"];
+        assert_eq!(entities[2]["position"], json!({"x": -1.5, "y": 0.5}));
+        assert_eq!(entities[2]["direction"], 6);
+        assert_eq!(entities[1]["input_priority"], "right");
+        assert_eq!(entities[1]["output_priority"], "left");
+        assert!(rotate_blueprint(&blueprint, 1).is_err());
+    }
+
+    #[test]
+    fn test_tile_blueprint() {
+        let prototypes = Arc::new(fixture_entity_prototypes());
+        let blueprint = fixture_blueprint();
+        let tiled = tile_blueprint(prototypes, &blueprint, 3, Direction::East, 2).unwrap();
+        let value = decode_blueprint_json(&tiled).unwrap();
+        let entities = value["blueprint"]["entities"].as_array().unwrap();
+        assert_eq!(entities.len(), 9);
+        // build area is 2 wide, so every copy moves by 2
+        assert_eq!(entities[3]["position"], json!({"x": 2.5, "y": 0.5}));
+        assert_eq!(entities[8]["entity_number"], 9);
+        assert_eq!(entities[8]["connections"]["1"]["red"][0]["entity_id"], 7);
+    }
+}
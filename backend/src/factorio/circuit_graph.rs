@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+
+use dashmap::lock::{RwLock, RwLockReadGuard};
+use dashmap::DashMap;
+use petgraph::graph::NodeIndex;
+use petgraph::stable_graph::StableGraph;
+use petgraph::visit::EdgeRef;
+use petgraph::Undirected;
+
+use crate::types::{CircuitConnector, CircuitNetwork, FactorioEntity, Position};
+
+pub const WIRE_RED: &str = "red";
+pub const WIRE_GREEN: &str = "green";
+
+/// red and green wires between entities, every connection point of an entity is its own node
+/// so combinator inputs and outputs end up in different networks
+pub struct CircuitGraph {
+    inner: RwLock<CircuitGraphInner>,
+    connectors: DashMap<(Position, u8), NodeIndex>,
+}
+
+impl CircuitGraph {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        CircuitGraph {
+            inner: RwLock::new(CircuitGraphInner::default()),
+            connectors: DashMap::new(),
+        }
+    }
+
+    pub fn inner_graph(&self) -> RwLockReadGuard<CircuitGraphInner> {
+        self.inner.read()
+    }
+
+    pub fn import(&self, other: &CircuitGraph) -> anyhow::Result<()> {
+        *self.inner.write() = other.inner.read().clone();
+        self.connectors.clear();
+        for entry in other.connectors.iter() {
+            self.connectors.insert(entry.key().clone(), *entry.value());
+        }
+        Ok(())
+    }
+
+    /// adds all wires of the entity, both ends report the same wire so duplicates are skipped
+    pub fn add(&self, entity: &FactorioEntity) -> anyhow::Result<()> {
+        if let Some(wires) = entity.wire_connections.as_ref() {
+            for wire in wires {
+                self.connect(
+                    &wire.wire,
+                    CircuitConnector {
+                        entity_name: entity.name.clone(),
+                        position: entity.position.clone(),
+                        circuit_id: wire.source_circuit_id,
+                    },
+                    CircuitConnector {
+                        entity_name: wire.target_name.clone(),
+                        position: wire.target_position.clone(),
+                        circuit_id: wire.target_circuit_id,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn connect(
+        &self,
+        wire: &str,
+        from: CircuitConnector,
+        to: CircuitConnector,
+    ) -> anyhow::Result<()> {
+        if wire != WIRE_RED && wire != WIRE_GREEN {
+            bail!("invalid wire {}, expected red or green", wire);
+        }
+        let from = self.connector_node(from);
+        let to = self.connector_node(to);
+        let mut inner = self.inner.write();
+        let exists = inner
+            .edges(from)
+            .any(|edge| (edge.source() == to || edge.target() == to) && edge.weight() == wire);
+        if !exists {
+            inner.add_edge(from, to, wire.into());
+        }
+        Ok(())
+    }
+
+    fn connector_node(&self, connector: CircuitConnector) -> NodeIndex {
+        let key = (connector.position.clone(), connector.circuit_id);
+        if let Some(node_index) = self.connectors.get(&key) {
+            return *node_index;
+        }
+        let node_index = self.inner.write().add_node(connector);
+        self.connectors.insert(key, node_index);
+        node_index
+    }
+
+    /// removes all connection points of the entity at position together with their wires
+    pub fn remove(&self, position: &Position) {
+        let mut inner = self.inner.write();
+        for circuit_id in 1..=2 {
+            if let Some((_, node_index)) = self.connectors.remove(&(position.clone(), circuit_id)) {
+                inner.remove_node(node_index);
+            }
+        }
+    }
+
+    /// connected components of each wire color, connection points without wires are skipped
+    pub fn networks(&self) -> Vec<CircuitNetwork> {
+        let inner = self.inner.read();
+        let mut networks: Vec<CircuitNetwork> = vec![];
+        for wire in &[WIRE_RED, WIRE_GREEN] {
+            let wire = *wire;
+            let mut visited: HashSet<NodeIndex> = HashSet::new();
+            for start in inner.node_indices() {
+                if visited.contains(&start) || !inner.edges(start).any(|e| e.weight() == wire) {
+                    continue;
+                }
+                visited.insert(start);
+                let mut connectors: Vec<CircuitConnector> = vec![];
+                let mut stack: Vec<NodeIndex> = vec![start];
+                while let Some(node_index) = stack.pop() {
+                    connectors.push(inner.node_weight(node_index).unwrap().clone());
+                    for edge in inner.edges(node_index) {
+                        if edge.weight() != wire {
+                            continue;
+                        }
+                        let next = if edge.source() == node_index {
+                            edge.target()
+                        } else {
+                            edge.source()
+                        };
+                        if visited.insert(next) {
+                            stack.push(next);
+                        }
+                    }
+                }
+                networks.push(CircuitNetwork {
+                    id: networks.len() as u32 + 1,
+                    wire: wire.into(),
+                    connectors,
+                });
+            }
+        }
+        networks
+    }
+
+    pub fn network_at(
+        &self,
+        position: &Position,
+        wire: &str,
+        circuit_id: u8,
+    ) -> Option<CircuitNetwork> {
+        self.networks().into_iter().find(|network| {
+            network.wire == wire
+                && network.connectors.iter().any(|connector| {
+                    connector.position == *position && connector.circuit_id == circuit_id
+                })
+        })
+    }
+}
+
+pub type CircuitGraphInner = StableGraph<CircuitConnector, String, Undirected>;
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_entity;
+    use crate::types::{EntityType, FactorioWireConnection};
+
+    use super::*;
+
+    fn wire(
+        wire: &str,
+        source_circuit_id: u8,
+        target_name: &str,
+        target_position: Position,
+        target_circuit_id: u8,
+    ) -> FactorioWireConnection {
+        FactorioWireConnection {
+            wire: wire.into(),
+            target_name: target_name.into(),
+            target_position,
+            source_circuit_id,
+            target_circuit_id,
+        }
+    }
+
+    #[test]
+    fn test_circuit_networks() {
+        let constant = Position::new(0.5, 0.5);
+        let arithmetic = Position::new(2., 0.5);
+        let pole = Position::new(4.5, 0.5);
+        let graph = CircuitGraph::new();
+        for entity in vec![
+            FactorioEntity {
+                wire_connections: Some(vec![wire(
+                    WIRE_RED,
+                    1,
+                    "arithmetic-combinator",
+                    arithmetic.clone(),
+                    1,
+                )]),
+                ..fixture_entity(
+                    "constant-combinator",
+                    EntityType::ConstantCombinator,
+                    &constant,
+                    0,
+                )
+            },
+            FactorioEntity {
+                wire_connections: Some(vec![
+                    wire(WIRE_RED, 1, "constant-combinator", constant.clone(), 1),
+                    wire(WIRE_GREEN, 2, "small-electric-pole", pole.clone(), 1),
+                ]),
+                ..fixture_entity(
+                    "arithmetic-combinator",
+                    EntityType::ArithmeticCombinator,
+                    &arithmetic,
+                    0,
+                )
+            },
+            FactorioEntity {
+                wire_connections: Some(vec![wire(
+                    WIRE_GREEN,
+                    1,
+                    "arithmetic-combinator",
+                    arithmetic.clone(),
+                    2,
+                )]),
+                ..fixture_entity("small-electric-pole", EntityType::ElectricPole, &pole, 0)
+            },
+        ] {
+            graph.add(&entity).unwrap();
+        }
+        assert_eq!(graph.inner_graph().edge_count(), 2);
+        let networks = graph.networks();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].wire, WIRE_RED);
+        assert_eq!(networks[0].connectors.len(), 2);
+        // combinator input and output are not connected with each other
+        let output = graph.network_at(&arithmetic, WIRE_GREEN, 2).unwrap();
+        assert!(output.connectors.iter().all(|c| c.position != constant));
+        assert!(graph.network_at(&arithmetic, WIRE_GREEN, 1).is_none());
+
+        graph.remove(&arithmetic);
+        assert!(graph.networks().is_empty());
+        assert!(graph
+            .connect(
+                "copper",
+                output.connectors[0].clone(),
+                output.connectors[1].clone()
+            )
+            .is_err());
+    }
+}
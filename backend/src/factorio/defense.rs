@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::add_to_rect;
+use crate::types::{
+    DefensePerimeter, EntityName, EntityType, FactorioEntity, PerimeterRequest, Pos, Position, Rect,
+};
+
+const DEFAULT_MARGIN: u32 = 2;
+const DEFAULT_WALL_THICKNESS: u32 = 2;
+const DEFAULT_TURRET_SPACING: u32 = 8;
+const DEFAULT_AMMO_PER_TURRET: u32 = 20;
+
+/// wall ring around the area with turrets just inside of it.
+///
+/// from the inside out: the area, `margin` free tiles, the turret row, one free tile to walk
+/// through and the wall. turrets sit on every corner and at most `turret_spacing` tiles apart.
+/// tiles blocked by buildings or water are left out, trees and rocks in the way are returned
+/// as obstacles to mine first
+pub fn plan_perimeter(
+    entity_graph: &EntityGraph,
+    request: &PerimeterRequest,
+) -> anyhow::Result<DefensePerimeter> {
+    let turret_name = request
+        .turret_name
+        .clone()
+        .unwrap_or_else(|| EntityName::GunTurret.to_string());
+    let wall_name = request
+        .wall_name
+        .clone()
+        .unwrap_or_else(|| EntityName::StoneWall.to_string());
+    let ammo_name = request
+        .ammo_name
+        .clone()
+        .unwrap_or_else(|| EntityName::FirearmMagazine.to_string());
+    let margin = request.margin.unwrap_or(DEFAULT_MARGIN) as i32;
+    let thickness = request.wall_thickness.unwrap_or(DEFAULT_WALL_THICKNESS) as i32;
+    let spacing = request.turret_spacing.unwrap_or(DEFAULT_TURRET_SPACING) as i32;
+    if thickness == 0 {
+        bail!("wall thickness must be at least 1");
+    }
+    if spacing == 0 {
+        bail!("turret spacing must be at least 1");
+    }
+
+    let prototypes = entity_graph.entity_prototypes();
+    let turret = prototypes
+        .get(&turret_name)
+        .ok_or_else(|| anyhow!("turret {} not found", turret_name))?;
+    let turret_type = turret.entity_type.clone();
+    if turret_type != EntityType::AmmoTurret.to_string()
+        && turret_type != EntityType::ElectricTurret.to_string()
+        && turret_type != EntityType::FluidTurret.to_string()
+    {
+        bail!("{} is not a turret", turret_name);
+    }
+    let turret_box = turret.collision_box.clone();
+    drop(turret);
+    let wall = prototypes
+        .get(&wall_name)
+        .ok_or_else(|| anyhow!("wall {} not found", wall_name))?;
+    if wall.entity_type != EntityType::Wall.to_string() {
+        bail!("{} is not a wall", wall_name);
+    }
+    let wall_box = wall.collision_box.clone();
+    drop(wall);
+    let size = turret_box.width().ceil().max(turret_box.height().ceil()) as i32;
+
+    let left = request.area.left_top.x().floor() as i32;
+    let top = request.area.left_top.y().floor() as i32;
+    let right = request.area.right_bottom.x().ceil() as i32;
+    let bottom = request.area.right_bottom.y().ceil() as i32;
+    // left top corners of the turret row
+    let turret_left = left - margin - size;
+    let turret_top = top - margin - size;
+    let turret_right = right + margin;
+    let turret_bottom = bottom + margin;
+
+    let mut obstacles: Vec<FactorioEntity> = vec![];
+    let mut is_free = |left: i32, top: i32, size: i32| -> bool {
+        let rect = Rect::new(
+            &Position::new(left as f64 + 0.1, top as f64 + 0.1),
+            &Position::new((left + size) as f64 - 0.1, (top + size) as f64 - 0.1),
+        );
+        let query: QuadTreeRect = rect.clone().into();
+        if entity_graph
+            .blocked_tree()
+            .query(query)
+            .iter()
+            .any(|(minable, _, _)| !**minable)
+            || entity_graph
+                .tile_tree()
+                .query(query)
+                .iter()
+                .any(|(tile, _, _)| tile.player_collidable)
+        {
+            return false;
+        }
+        for obstacle in entity_graph.obstacles_in(&rect) {
+            if !obstacles.contains(&obstacle) {
+                obstacles.push(obstacle);
+            }
+        }
+        true
+    };
+
+    let mut entities: Vec<FactorioEntity> = vec![];
+    let mut corners: HashSet<Pos> = HashSet::new();
+    for x in steps(turret_left, turret_right, spacing, size) {
+        corners.insert(Pos(x, turret_top));
+        corners.insert(Pos(x, turret_bottom));
+    }
+    for y in steps(turret_top, turret_bottom, spacing, size) {
+        corners.insert(Pos(turret_left, y));
+        corners.insert(Pos(turret_right, y));
+    }
+    let mut corners: Vec<Pos> = corners.into_iter().collect();
+    corners.sort_by_key(|corner| (corner.1, corner.0));
+    for corner in corners {
+        if !is_free(corner.0, corner.1, size) {
+            continue;
+        }
+        let position = Position::new(
+            corner.0 as f64 + size as f64 / 2.,
+            corner.1 as f64 + size as f64 / 2.,
+        );
+        entities.push(FactorioEntity {
+            name: turret_name.clone(),
+            entity_type: turret_type.clone(),
+            bounding_box: add_to_rect(&turret_box, &position),
+            position,
+            ..Default::default()
+        });
+    }
+    let turrets = entities.len() as u32;
+    if turrets == 0 {
+        bail!("no room for turrets around {:?}", request.area);
+    }
+
+    let inner_left = turret_left - 1;
+    let inner_top = turret_top - 1;
+    let inner_right = turret_right + size + 1;
+    let inner_bottom = turret_bottom + size + 1;
+    let mut walls = 0;
+    for y in inner_top - thickness..inner_bottom + thickness {
+        for x in inner_left - thickness..inner_right + thickness {
+            if (inner_left..inner_right).contains(&x) && (inner_top..inner_bottom).contains(&y) {
+                continue;
+            }
+            if !is_free(x, y, 1) {
+                continue;
+            }
+            let position = Position::new(x as f64 + 0.5, y as f64 + 0.5);
+            entities.push(FactorioEntity {
+                name: wall_name.clone(),
+                entity_type: EntityType::Wall.to_string(),
+                bounding_box: add_to_rect(&wall_box, &position),
+                position,
+                ..Default::default()
+            });
+            walls += 1;
+        }
+    }
+
+    let mut cost: BTreeMap<String, u32> = BTreeMap::new();
+    for entity in &entities {
+        *cost.entry(entity.name.clone()).or_insert(0) += 1;
+    }
+    let mut ammo: BTreeMap<String, u32> = BTreeMap::new();
+    if turret_type == EntityType::AmmoTurret.to_string() {
+        ammo.insert(
+            ammo_name,
+            turrets * request.ammo_per_turret.unwrap_or(DEFAULT_AMMO_PER_TURRET),
+        );
+    }
+    Ok(DefensePerimeter {
+        turrets,
+        walls,
+        entities,
+        obstacles,
+        cost,
+        ammo,
+    })
+}
+
+/// from and to with values spread evenly in between, at most step apart unless that would bring
+/// two of them closer than min_gap
+fn steps(from: i32, to: i32, step: i32, min_gap: i32) -> Vec<i32> {
+    let length = to - from;
+    let segments = ((length + step - 1) / step).min(length / min_gap).max(1);
+    (0..=segments)
+        .map(|i| from + (2 * i * length + segments) / (2 * segments))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::types::Direction;
+
+    use super::*;
+
+    #[test]
+    fn test_plan_perimeter() {
+        let world = fixture_world();
+        world
+            .entity_graph
+            .add(
+                vec![
+                    FactorioEntity::new_stone_furnace(&Position::new(11., 0.), Direction::North),
+                    FactorioEntity::new_tree(&Position::new(-11.5, 0.5)),
+                ],
+                None,
+            )
+            .unwrap();
+        let mut request = PerimeterRequest {
+            area: Rect::new(&Position::new(-5., -5.), &Position::new(5., 5.)),
+            margin: None,
+            wall_thickness: None,
+            turret_spacing: None,
+            turret_name: None,
+            wall_name: None,
+            ammo_name: None,
+            ammo_per_turret: None,
+        };
+        let perimeter = plan_perimeter(&world.entity_graph, &request).unwrap();
+        // 3 turrets on each side of the 18 tiles wide row, sharing the corners
+        assert_eq!(perimeter.turrets, 8);
+        // 24x24 ring around 20x20 without the furnace
+        assert_eq!(perimeter.walls, 24 * 24 - 20 * 20 - 4);
+        assert_eq!(perimeter.cost[&EntityName::GunTurret.to_string()], 8);
+        assert_eq!(
+            perimeter.ammo[&EntityName::FirearmMagazine.to_string()],
+            160
+        );
+        assert_eq!(perimeter.obstacles.len(), 1);
+        for turret in perimeter
+            .entities
+            .iter()
+            .filter(|e| e.name == EntityName::GunTurret.to_string())
+        {
+            assert!(!turret.bounding_box.intersects(&request.area));
+        }
+
+        request.turret_name = Some(EntityName::StoneFurnace.to_string());
+        assert!(plan_perimeter(&world.entity_graph, &request).is_err());
+    }
+
+    #[test]
+    fn test_plan_perimeter_spacing() {
+        let world = fixture_world();
+        // the turret row is 17 tiles wide, one more than two full steps
+        let request = PerimeterRequest {
+            area: Rect::new(&Position::new(-5., -5.), &Position::new(6., 6.)),
+            margin: None,
+            wall_thickness: None,
+            turret_spacing: None,
+            turret_name: None,
+            wall_name: None,
+            ammo_name: None,
+            ammo_per_turret: None,
+        };
+        let perimeter = plan_perimeter(&world.entity_graph, &request).unwrap();
+        assert_eq!(perimeter.turrets, 12);
+        let turrets: Vec<&FactorioEntity> = perimeter
+            .entities
+            .iter()
+            .filter(|e| e.name == EntityName::GunTurret.to_string())
+            .collect();
+        for (index, turret) in turrets.iter().enumerate() {
+            for other in &turrets[index + 1..] {
+                assert!(!turret.bounding_box.intersects(&other.bounding_box));
+            }
+        }
+        assert_eq!(steps(0, 16, 8, 2), vec![0, 8, 16]);
+        assert_eq!(steps(0, 4, 3, 3), vec![0, 4]);
+    }
+}
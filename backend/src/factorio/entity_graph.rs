@@ -1,5 +1,5 @@
 use petgraph::dot::{Config, Dot};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -15,46 +15,69 @@ use petgraph::stable_graph::StableGraph;
 use petgraph::visit::{Bfs, EdgeRef};
 
 use crate::factorio::util::{
-    add_to_rect, bounding_box, format_dotgraph, move_position, rect_fields, rect_floor,
+    add_to_rect, bounding_box, calculate_distance, format_dotgraph, move_position, pad_rect,
+    rect_fields, rect_floor,
 };
-use crate::num_traits::FromPrimitive;
+use crate::num_traits::{FromPrimitive, ToPrimitive};
 use crate::types::{
-    Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioRecipe,
-    FactorioTile, Pos, Position, Rect, ResourcePatch,
+    ChunkPosition, Direction, ElectricNetwork, EnemyBase, EntityName, EntityType, FactorioEntity,
+    FactorioEntityPrototype, FactorioRecipe, FactorioTile, InventoryResponse, Pos, Position, Rect,
+    ResourcePatch,
 };
 
 pub struct EntityGraph {
     entity_graph: RwLock<EntityGraphInner>,
     blocked_tree: RwLock<BlockedQuadTree>,
     entity_tree: RwLock<EntityQuadTree>,
+    // trees and rocks which have to be mined before building
+    obstacle_tree: RwLock<EntityQuadTree>,
+    // spawners and worms of the enemy force
+    enemy_tree: RwLock<EntityQuadTree>,
+    // cliffs, walls, turrets and whatever else blocks building without being part of the graph
+    blocker_tree: RwLock<EntityQuadTree>,
     tile_tree: RwLock<TileQuadTree>,
+    // chunks whose tiles were reported, everything else is unexplored
+    known_chunks: RwLock<HashSet<ChunkPosition>>,
     entity_nodes: DashMap<ItemId, NodeIndex>,
     entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
     recipes: Arc<DashMap<String, FactorioRecipe>>,
     resources: DashMap<String, Vec<Pos>>,
+    resource_amounts: DashMap<Pos, u32>,
     resource_tree: RwLock<ResourceQuadTree>,
+    changes: RwLock<Option<Vec<Position>>>,
+    // built on first use, dropped whenever an entity is added or removed
+    electric_layer: RwLock<Option<Arc<ElectricLayer>>>,
 }
 
+// farthest distance between two connected entities, the longest underground pipe
+const CONNECT_DISTANCE: f64 = 11.;
+// spawners and worms closer than this belong to the same base
+const ENEMY_BASE_DISTANCE: f64 = 16.;
+
 impl EntityGraph {
     #[allow(clippy::new_without_default)]
     pub fn new(
         entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
         recipes: Arc<DashMap<String, FactorioRecipe>>,
     ) -> Self {
-        let max_area = QuadTreeRect::new(
-            TypedPoint2D::new(-5120., -5120.),
-            TypedSize2D::new(10240., 10240.),
-        );
+        let max_area = max_area();
         EntityGraph {
             entity_prototypes,
             recipes,
             entity_graph: RwLock::new(EntityGraphInner::new()),
             entity_tree: RwLock::new(QuadTree::new(max_area, false, 32, 128, 128, 8)),
+            obstacle_tree: RwLock::new(QuadTree::new(max_area, false, 32, 128, 128, 8)),
+            enemy_tree: RwLock::new(QuadTree::new(max_area, false, 32, 128, 128, 8)),
+            blocker_tree: RwLock::new(QuadTree::new(max_area, false, 32, 128, 128, 8)),
             blocked_tree: RwLock::new(QuadTree::new(max_area, true, 8, 64, 1024, 8)),
             resource_tree: RwLock::new(QuadTree::new(max_area, true, 8, 64, 1024, 8)),
             tile_tree: RwLock::new(QuadTree::new(max_area, false, 32, 128, 128, 8)),
+            known_chunks: RwLock::new(HashSet::new()),
             entity_nodes: DashMap::new(),
             resources: DashMap::new(),
+            resource_amounts: DashMap::new(),
+            changes: RwLock::new(None),
+            electric_layer: RwLock::new(None),
         }
     }
     pub fn inner_graph(&self) -> RwLockReadGuard<EntityGraphInner> {
@@ -79,6 +102,36 @@ impl EntityGraph {
         self.recipes.clone()
     }
 
+    /// replaces all contents with a copy of another graph, used to give the planner its own world
+    pub fn import(&self, other: &EntityGraph) -> anyhow::Result<()> {
+        *self.entity_graph.write() = other.entity_graph.read().clone();
+        *self.entity_tree.write() = other.entity_tree.read().clone();
+        *self.obstacle_tree.write() = other.obstacle_tree.read().clone();
+        *self.enemy_tree.write() = other.enemy_tree.read().clone();
+        *self.blocker_tree.write() = other.blocker_tree.read().clone();
+        *self.blocked_tree.write() = other.blocked_tree.read().clone();
+        *self.tile_tree.write() = other.tile_tree.read().clone();
+        *self.known_chunks.write() = other.known_chunks.read().clone();
+        *self.resource_tree.write() = other.resource_tree.read().clone();
+        self.entity_nodes.clear();
+        for entry in other.entity_nodes.iter() {
+            self.entity_nodes.insert(*entry.key(), *entry.value());
+        }
+        self.resources.clear();
+        for entry in other.resources.iter() {
+            self.resources
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        self.resource_amounts.clear();
+        for entry in other.resource_amounts.iter() {
+            self.resource_amounts
+                .insert(entry.key().clone(), *entry.value());
+        }
+        *self.changes.write() = None;
+        *self.electric_layer.write() = None;
+        Ok(())
+    }
+
     pub fn node_by_id(&self, id: &ItemId) -> Option<NodeIndex> {
         self.entity_nodes.get(id).map(|e| *e.value())
     }
@@ -95,13 +148,10 @@ impl EntityGraph {
     pub fn resource_patches(&self, resource_name: &str) -> Vec<ResourcePatch> {
         let mut patches: Vec<ResourcePatch> = vec![];
         let mut positions_by_id: HashMap<Pos, Option<u32>> = HashMap::new();
-        for point in self
-            .resources
-            .get(resource_name)
-            .expect("resource patch not found")
-            .iter()
-        {
-            positions_by_id.insert(point.clone(), None);
+        if let Some(points) = self.resources.get(resource_name) {
+            for point in points.iter() {
+                positions_by_id.insert(point.clone(), None);
+            }
         }
         let mut next_id: u32 = 0;
 
@@ -115,7 +165,7 @@ impl EntityGraph {
                     let other: Pos = (&move_position(&(&pos).into(), direction, 1.0)).into();
                     if let Some(p) = positions_by_id.get(&other) {
                         if p.is_none() {
-                            positions_by_id.insert(pos.clone(), Some(next_id));
+                            positions_by_id.insert(other.clone(), Some(next_id));
                             stack.push(other);
                         }
                     }
@@ -129,15 +179,36 @@ impl EntityGraph {
                     elements.push(k.into());
                 }
             }
+            let amounts: Vec<u32> = elements
+                .iter()
+                .map(|element| {
+                    let pos: Pos = element.into();
+                    self.resource_amounts
+                        .get(&pos)
+                        .map(|amount| *amount)
+                        .unwrap_or(0)
+                })
+                .collect();
             patches.push(ResourcePatch {
                 name: resource_name.into(),
                 rect: bounding_box(&elements).unwrap(),
                 elements,
+                amounts,
                 id,
             });
         }
-        patches.sort_by(|a, b| b.elements.len().cmp(&a.elements.len()));
-        patches
+        // score walks every element, so compute it once per patch
+        let mut scored: Vec<(f64, ResourcePatch)> = patches
+            .into_iter()
+            .map(|patch| (patch.score(), patch))
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .partial_cmp(a_score)
+                .unwrap()
+                .then(b.elements.len().cmp(&a.elements.len()))
+        });
+        scored.into_iter().map(|(_, patch)| patch).collect()
     }
 
     pub fn add_tiles(
@@ -147,7 +218,9 @@ impl EntityGraph {
     ) -> anyhow::Result<()> {
         let mut tree = self.tile_tree.write();
         let mut blocked = self.blocked_tree.write();
+        let mut known_chunks = self.known_chunks.write();
         for tile in tiles {
+            known_chunks.insert(chunk_of(&tile.position));
             let rect: QuadTreeRect = add_to_rect(
                 &Rect::from_wh(1., 1.),
                 &Position::new(tile.position.x() + 0.5, tile.position.y() + 0.5),
@@ -162,6 +235,11 @@ impl EntityGraph {
         Ok(())
     }
 
+    /// whether the tiles of the chunk containing position were reported
+    pub fn is_chunk_known(&self, position: &Position) -> bool {
+        self.known_chunks.read().contains(&chunk_of(position))
+    }
+
     pub fn add_blueprint_entities(&self, str: &str) -> anyhow::Result<()> {
         let decoded = BlueprintCodec::decode_string(str).expect("failed to parse blueprint");
         let mut entities: Vec<FactorioEntity> = vec![];
@@ -187,6 +265,8 @@ impl EntityGraph {
         let mut resource_tree = self.resource_tree.write();
         for entity in &entities {
             if entity.entity_type == EntityType::Resource.to_string() {
+                self.resource_amounts
+                    .insert((&entity.position).into(), entity.amount.unwrap_or(0));
                 match self.resources.get_mut(&entity.name) {
                     Some(mut positions) => {
                         positions.push((&entity.position).into());
@@ -208,10 +288,13 @@ impl EntityGraph {
             }
         }
         let mut blocked = self.blocked_tree.write();
+        let mut added: Vec<(NodeIndex, Rect)> = vec![];
         // println!("inserted {}", blocked.len());
         for mut entity in entities {
             if entity.entity_type == EntityType::FlyingText.to_string()
                 || entity.entity_type == EntityType::Fish.to_string()
+                || entity.entity_type == EntityType::ItemEntity.to_string()
+                || entity.entity_type == EntityType::Unit.to_string()
                 || entity.bounding_box.width() == 0.
             {
                 continue;
@@ -222,6 +305,12 @@ impl EntityGraph {
             {
                 blocked.insert_with_box(entity.is_minable(), entity.bounding_box.clone().into());
             }
+            if entity.is_minable() {
+                self.obstacle_tree.write().insert(entity.clone());
+            }
+            if entity.is_enemy() {
+                self.enemy_tree.write().insert(entity.clone());
+            }
             if entity.name == EntityName::Pumpjack.to_string() {
                 // for some reason pumpjacks report their drop position at their position so we fix it
                 entity.drop_position = Some(entity.position.add(
@@ -251,7 +340,14 @@ impl EntityGraph {
                     | EntityType::Pipe
                     | EntityType::PipeToGround
                     | EntityType::LogisticContainer
-                    | EntityType::AssemblingMachine => {
+                    | EntityType::AssemblingMachine
+                    | EntityType::ElectricPole
+                    | EntityType::Generator
+                    | EntityType::SolarPanel
+                    | EntityType::Accumulator
+                    | EntityType::ConstantCombinator
+                    | EntityType::ArithmeticCombinator
+                    | EntityType::DeciderCombinator => {
                         if let Some(entity_id) = self.entity_at(&entity.position) {
                             let tree = self.entity_tree.read();
                             let block = tree.get(entity_id).unwrap();
@@ -265,6 +361,7 @@ impl EntityGraph {
                             let mut tree = self.entity_tree.write();
                             tree.insert(entity.clone())
                         } {
+                            *self.electric_layer.write() = None;
                             let miner_ore = if entity_type == EntityType::MiningDrill {
                                 let rect = rect_floor(&entity.bounding_box);
                                 let mut miner_ore: Option<String> = None;
@@ -304,21 +401,47 @@ impl EntityGraph {
                             } else {
                                 None
                             };
+                            let bounding_box = entity.bounding_box.clone();
                             let new_node = EntityNode::new(entity.clone(), miner_ore, entity_id);
                             let mut inner = self.entity_graph.write();
                             let new_node_index = inner.add_node(new_node);
                             self.entity_nodes.insert(entity_id, new_node_index);
+                            added.push((new_node_index, bounding_box));
                         } else {
                             warn!("failed to insert entity into quad tree");
                         }
                     }
-                    _ => {}
+                    _ => self.add_blocker(&entity),
                 }
+            } else {
+                self.add_blocker(&entity);
             }
         }
+        drop(blocked);
+        drop(resource_tree);
+        let mut nodes: Vec<NodeIndex> = added.iter().map(|(node_index, _)| *node_index).collect();
+        for (_, bounding_box) in &added {
+            nodes.extend(self.nodes_around(bounding_box));
+        }
+        nodes.sort();
+        nodes.dedup();
+        self.connect_nodes(nodes.clone())?;
+        self.mark_changed(&nodes);
         Ok(())
     }
 
+    fn add_blocker(&self, entity: &FactorioEntity) {
+        if entity.entity_type != EntityType::Resource.to_string()
+            && entity.entity_type != EntityType::StraightRail.to_string()
+            && entity.entity_type != EntityType::CurvedRail.to_string()
+            && entity.entity_type != EntityType::Character.to_string()
+            && !entity.is_minable()
+            && !entity.is_enemy()
+        {
+            self.blocker_tree.write().insert(entity.clone());
+        }
+    }
+
     pub fn condense(&self) -> EntityGraphInner {
         let _started = Instant::now();
         let mut graph = self.entity_graph.read().clone();
@@ -473,9 +596,11 @@ impl EntityGraph {
         for entity_id in entities_to_remove {
             self.entity_nodes.remove(&entity_id);
         }
+        let nodes_removed = !nodes_to_remove.is_empty();
         for node in nodes_to_remove {
             inner.remove_node(node);
         }
+        drop(inner);
 
         let mut blocked_item_ids_to_remove: Vec<ItemId> = vec![];
         let blocked_tree = self.blocked_tree.read();
@@ -501,6 +626,42 @@ impl EntityGraph {
             entity_tree.remove(item_id);
         }
         drop(entity_tree);
+        *self.electric_layer.write() = None;
+        let mut blocker_tree = self.blocker_tree.write();
+        let blocker_item_ids_to_remove: Vec<ItemId> = blocker_tree
+            .query(entity.bounding_box.clone().into())
+            .iter()
+            .filter(|(blocker, _, _)| blocker.name == entity.name)
+            .map(|(_, _, item_id)| *item_id)
+            .collect();
+        for item_id in blocker_item_ids_to_remove {
+            blocker_tree.remove(item_id);
+        }
+        drop(blocker_tree);
+        if entity.is_minable() {
+            let mut obstacle_tree = self.obstacle_tree.write();
+            let obstacle_item_ids_to_remove: Vec<ItemId> = obstacle_tree
+                .query(entity.bounding_box.clone().into())
+                .iter()
+                .filter(|(obstacle, _, _)| obstacle.name == entity.name)
+                .map(|(_, _, item_id)| *item_id)
+                .collect();
+            for item_id in obstacle_item_ids_to_remove {
+                obstacle_tree.remove(item_id);
+            }
+        }
+        if entity.is_enemy() {
+            let mut enemy_tree = self.enemy_tree.write();
+            let enemy_item_ids_to_remove: Vec<ItemId> = enemy_tree
+                .query(entity.bounding_box.clone().into())
+                .iter()
+                .filter(|(enemy, _, _)| enemy.name == entity.name)
+                .map(|(_, _, item_id)| *item_id)
+                .collect();
+            for item_id in enemy_item_ids_to_remove {
+                enemy_tree.remove(item_id);
+            }
+        }
 
         if entity.entity_type == EntityType::Resource.to_string() {
             let mut resource_item_ids_to_remove: Vec<ItemId> = vec![];
@@ -514,22 +675,73 @@ impl EntityGraph {
                 resource_tree.remove(item_id);
             }
             drop(resource_tree);
+            let entity_pos: Pos = (&entity.position).into();
+            self.resource_amounts.remove(&entity_pos);
             if let Some(mut positions) = self.resources.get_mut(&entity.name) {
-                let entity_pos: Pos = (&entity.position).into();
                 if let Some(i) = positions.iter().position(|pos| *pos == entity_pos) {
                     positions.remove(i);
                 }
             }
         }
 
+        if nodes_removed {
+            if let Some(changes) = self.changes.write().as_mut() {
+                changes.push(entity.position.clone());
+            }
+            // neighbours may connect to something else now, e.g. underground belts further away
+            let nodes = self.nodes_around(&entity.bounding_box);
+            self.connect_nodes(nodes.clone())?;
+            self.mark_changed(&nodes);
+        }
         Ok(())
     }
 
+    /// connects all nodes, add and remove already keep edges of their neighbourhood up to date
     pub fn connect(&self) -> anyhow::Result<()> {
+        let nodes: Vec<NodeIndex> = self.entity_graph.read().node_indices().collect();
+        self.connect_nodes(nodes)
+    }
+
+    /// nodes whose edges may change when something is added or removed inside rect
+    fn nodes_around(&self, rect: &Rect) -> Vec<NodeIndex> {
+        let area = pad_rect(
+            rect,
+            CONNECT_DISTANCE,
+            CONNECT_DISTANCE,
+            CONNECT_DISTANCE,
+            CONNECT_DISTANCE,
+        );
+        let tree = self.entity_tree.read();
+        let nodes: Vec<NodeIndex> = tree
+            .query(area.into())
+            .iter()
+            .filter_map(|(_, _, entity_id)| self.node_by_id(entity_id))
+            .collect();
+        nodes
+    }
+
+    fn mark_changed(&self, nodes: &[NodeIndex]) {
+        let inner = self.entity_graph.read();
+        let mut changes = self.changes.write();
+        if let Some(changes) = changes.as_mut() {
+            for node_index in nodes {
+                if let Some(node) = inner.node_weight(*node_index) {
+                    changes.push(node.position.clone());
+                }
+            }
+        }
+    }
+
+    /// positions of entities which were added, removed or got new edges since the last call,
+    /// None if the graph was replaced and everything has to be rebuilt
+    pub fn take_changes(&self) -> Option<Vec<Position>> {
+        self.changes.write().replace(vec![])
+    }
+
+    fn connect_nodes(&self, nodes: Vec<NodeIndex>) -> anyhow::Result<()> {
         let _started = Instant::now();
         let tree = self.entity_tree.read();
         let mut edges_to_add: Vec<(NodeIndex, NodeIndex, f64)> = vec![];
-        let nodes: Vec<NodeIndex> = self.entity_graph.read().node_indices().collect();
         for node_index in nodes {
             let inner = self.entity_graph.read();
             let node_index = node_index;
@@ -800,6 +1012,349 @@ impl EntityGraph {
         // );
         Ok(())
     }
+    pub fn update_inventory(&self, inventory: InventoryResponse) -> anyhow::Result<()> {
+        match self.node_at(&inventory.position) {
+            Some(node_index) => {
+                let mut inner = self.entity_graph.write();
+                let node = inner.node_weight_mut(node_index).unwrap();
+                if node.entity_name != inventory.name {
+                    return Err(anyhow!(
+                        "inventory update for {} but found {} @ {}",
+                        inventory.name,
+                        node.entity_name,
+                        inventory.position
+                    ));
+                }
+                node.output_inventory = *inventory.output_inventory;
+                node.input_inventory = *inventory.input_inventory;
+                node.fuel_inventory = *inventory.fuel_inventory;
+                Ok(())
+            }
+            None => Err(anyhow!(
+                "inventory update for unknown entity {} @ {}",
+                inventory.name,
+                inventory.position
+            )),
+        }
+    }
+
+    pub fn inventory_at(&self, position: &Position) -> Option<InventoryResponse> {
+        self.node_at(position)
+            .and_then(|node_index| self.node_weight(node_index))
+            .map(|node| node.inventory())
+    }
+
+    /// all entities which burn fuel and have less than `min_count` fuel items left
+    pub fn entities_needing_fuel(&self, min_count: u32) -> Vec<InventoryResponse> {
+        let inner = self.entity_graph.read();
+        let inventories: Vec<InventoryResponse> = inner
+            .node_indices()
+            .filter_map(|node_index| inner.node_weight(node_index))
+            .filter(|node| match &node.fuel_inventory {
+                Some(fuel) => fuel.values().sum::<u32>() < min_count,
+                None => false,
+            })
+            .map(|node| node.inventory())
+            .collect();
+        inventories
+    }
+
+    /// all entities which have something in their output inventory
+    pub fn entities_with_output(&self) -> Vec<InventoryResponse> {
+        let inner = self.entity_graph.read();
+        let inventories: Vec<InventoryResponse> = inner
+            .node_indices()
+            .filter_map(|node_index| inner.node_weight(node_index))
+            .filter(|node| match &node.output_inventory {
+                Some(output) => output.values().any(|count| *count > 0),
+                None => false,
+            })
+            .map(|node| node.inventory())
+            .collect();
+        inventories
+    }
+
+    /// power network layer: poles are grouped by wire reach, every entity whose bounding box
+    /// touches the supply area of a pole belongs to the network of that pole
+    fn electric_layer(&self) -> Arc<ElectricLayer> {
+        if let Some(layer) = self.electric_layer.read().as_ref() {
+            return layer.clone();
+        }
+        let layer = Arc::new(self.build_electric_layer());
+        *self.electric_layer.write() = Some(layer.clone());
+        layer
+    }
+
+    fn build_electric_layer(&self) -> ElectricLayer {
+        let inner = self.entity_graph.read();
+        let tree = self.entity_tree.read();
+        let mut poles: Vec<(Position, f64, f64)> = vec![];
+        for node_index in inner.node_indices() {
+            let node = inner.node_weight(node_index).unwrap();
+            if node.entity_type != EntityType::ElectricPole {
+                continue;
+            }
+            match self.entity_prototypes.get(&node.entity_name) {
+                Some(prototype) => poles.push((
+                    node.position.clone(),
+                    prototype
+                        .supply_area_distance
+                        .as_ref()
+                        .map(|d| d.to_f64().unwrap())
+                        .unwrap_or(0.),
+                    prototype
+                        .max_wire_distance
+                        .as_ref()
+                        .map(|d| d.to_f64().unwrap())
+                        .unwrap_or(0.),
+                )),
+                None => warn!("electric pole prototype {} not found", node.entity_name),
+            }
+        }
+
+        let mut parents: Vec<usize> = (0..poles.len()).collect();
+        fn find(parents: &mut [usize], i: usize) -> usize {
+            let mut root = i;
+            while parents[root] != root {
+                root = parents[root];
+            }
+            parents[i] = root;
+            root
+        }
+        for a in 0..poles.len() {
+            for b in a + 1..poles.len() {
+                let reach = poles[a].2.min(poles[b].2);
+                if calculate_distance(&poles[a].0, &poles[b].0) <= reach {
+                    let root_a = find(&mut parents, a);
+                    let root_b = find(&mut parents, b);
+                    if root_a != root_b {
+                        parents[root_b] = root_a;
+                    }
+                }
+            }
+        }
+        let mut network_by_root: HashMap<usize, usize> = HashMap::new();
+        let mut networks: Vec<ElectricNetwork> = vec![];
+        let mut pole_areas: Vec<(Rect, usize)> = vec![];
+        for (i, (position, supply_area_distance, _)) in poles.iter().enumerate() {
+            let root = find(&mut parents, i);
+            let network_index = *network_by_root.entry(root).or_insert_with(|| {
+                networks.push(ElectricNetwork {
+                    id: networks.len() as u32 + 1,
+                    poles: vec![],
+                    generators: vec![],
+                    consumers: vec![],
+                    generation_capacity: 0.,
+                    consumption: 0.,
+                    satisfaction: 0.,
+                });
+                networks.len() - 1
+            });
+            networks[network_index].poles.push(position.clone());
+            pole_areas.push((
+                Rect::new(
+                    &Position::new(
+                        position.x() - supply_area_distance,
+                        position.y() - supply_area_distance,
+                    ),
+                    &Position::new(
+                        position.x() + supply_area_distance,
+                        position.y() + supply_area_distance,
+                    ),
+                ),
+                network_index,
+            ));
+        }
+
+        let mut covered: HashMap<ItemId, usize> = HashMap::new();
+        for (area, network_index) in &pole_areas {
+            for (entity, _, item_id) in tree.query(area.clone().into()) {
+                if covered.contains_key(&item_id) {
+                    continue;
+                }
+                if let Some(prototype) = self.entity_prototypes.get(&entity.name) {
+                    let network = &mut networks[*network_index];
+                    if let Some(usage) = prototype.electric_energy_usage.as_ref() {
+                        network.consumers.push(entity.position.clone());
+                        network.consumption += usage.to_f64().unwrap();
+                        covered.insert(item_id, *network_index);
+                    } else if let Some(production) = prototype.max_energy_production.as_ref() {
+                        network.generators.push(entity.position.clone());
+                        network.generation_capacity += production.to_f64().unwrap();
+                        covered.insert(item_id, *network_index);
+                    }
+                }
+            }
+        }
+        for network in networks.iter_mut() {
+            network.satisfaction = if network.consumption > 0. {
+                (network.generation_capacity / network.consumption).min(1.)
+            } else {
+                1.
+            };
+        }
+        ElectricLayer {
+            networks,
+            pole_areas,
+            covered,
+        }
+    }
+
+    pub fn electric_networks(&self) -> Vec<ElectricNetwork> {
+        self.electric_layer().networks.clone()
+    }
+
+    /// electric consumers which are outside of any pole supply area or
+    /// connected to a network without any generation capacity
+    pub fn unpowered_entities(&self) -> Vec<FactorioEntity> {
+        let layer = self.electric_layer();
+        let inner = self.entity_graph.read();
+        let tree = self.entity_tree.read();
+        let mut unpowered: Vec<FactorioEntity> = vec![];
+        for node_index in inner.node_indices() {
+            let node = inner.node_weight(node_index).unwrap();
+            let is_consumer = self
+                .entity_prototypes
+                .get(&node.entity_name)
+                .map(|prototype| prototype.electric_energy_usage.is_some())
+                .unwrap_or(false);
+            if !is_consumer {
+                continue;
+            }
+            let entity_id = node.entity_id.unwrap();
+            let powered = match layer.covered.get(&entity_id) {
+                Some(network_index) => layer.networks[*network_index].generation_capacity > 0.,
+                None => false,
+            };
+            if !powered {
+                if let Some(entity) = tree.get(entity_id) {
+                    unpowered.push(entity.clone());
+                }
+            }
+        }
+        unpowered
+    }
+
+    /// checks if an entity would be powered if placed, it does not need to be part of the graph
+    pub fn is_powered(&self, entity: &FactorioEntity) -> bool {
+        let layer = self.electric_layer();
+        let bounding_box: QuadTreeRect = entity.bounding_box.clone().into();
+        layer.pole_areas.iter().any(|(area, network_index)| {
+            let area: QuadTreeRect = area.clone().into();
+            area.intersects(&bounding_box)
+                && layer.networks[*network_index].generation_capacity > 0.
+        })
+    }
+
+    /// trees and rocks inside the rect
+    pub fn obstacles_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        let tree = self.obstacle_tree.read();
+        let obstacles: Vec<FactorioEntity> = tree
+            .query(rect.clone().into())
+            .into_iter()
+            .map(|(obstacle, _, _)| obstacle.clone())
+            .collect();
+        obstacles
+    }
+
+    /// cliffs, walls, turrets and other entities outside of the graph inside the rect
+    pub fn blockers_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        let tree = self.blocker_tree.read();
+        let blockers: Vec<FactorioEntity> = tree
+            .query(rect.clone().into())
+            .into_iter()
+            .map(|(blocker, _, _)| blocker.clone())
+            .collect();
+        blockers
+    }
+
+    /// cliffs inside the rect, only explosives remove them
+    pub fn cliffs_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        self.blockers_in(rect)
+            .into_iter()
+            .filter(|blocker| blocker.entity_type == EntityType::Cliff.to_string())
+            .collect()
+    }
+
+    /// enemy spawners and worms inside the rect
+    pub fn enemies_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        let tree = self.enemy_tree.read();
+        let enemies: Vec<FactorioEntity> = tree
+            .query(rect.clone().into())
+            .into_iter()
+            .map(|(enemy, _, _)| enemy.clone())
+            .collect();
+        enemies
+    }
+
+    /// groups all known spawners and worms into bases, anything closer than
+    /// `ENEMY_BASE_DISTANCE` to a member belongs to the same base
+    pub fn enemy_bases(&self) -> Vec<EnemyBase> {
+        let tree = self.enemy_tree.read();
+        let enemies: Vec<FactorioEntity> = tree
+            .query(max_area())
+            .into_iter()
+            .map(|(enemy, _, _)| enemy.clone())
+            .collect();
+        drop(tree);
+        let mut base_of: Vec<Option<usize>> = vec![None; enemies.len()];
+        let mut bases: Vec<EnemyBase> = vec![];
+        for start in 0..enemies.len() {
+            if base_of[start].is_some() {
+                continue;
+            }
+            let base = bases.len();
+            base_of[start] = Some(base);
+            let mut members: Vec<usize> = vec![start];
+            let mut index = 0;
+            while index < members.len() {
+                let member = &enemies[members[index]];
+                for (other, other_base) in base_of.iter_mut().enumerate() {
+                    if other_base.is_none()
+                        && calculate_distance(&member.position, &enemies[other].position)
+                            <= ENEMY_BASE_DISTANCE
+                    {
+                        *other_base = Some(base);
+                        members.push(other);
+                    }
+                }
+                index += 1;
+            }
+            let mut rect = enemies[start].bounding_box.clone();
+            let mut spawners = 0;
+            let mut worms = 0;
+            for member in &members {
+                let enemy = &enemies[*member];
+                rect = Rect::new(
+                    &Position::new(
+                        rect.left_top.x().min(enemy.bounding_box.left_top.x()),
+                        rect.left_top.y().min(enemy.bounding_box.left_top.y()),
+                    ),
+                    &Position::new(
+                        rect.right_bottom
+                            .x()
+                            .max(enemy.bounding_box.right_bottom.x()),
+                        rect.right_bottom
+                            .y()
+                            .max(enemy.bounding_box.right_bottom.y()),
+                    ),
+                );
+                if enemy.entity_type == EntityType::UnitSpawner.to_string() {
+                    spawners += 1;
+                } else {
+                    worms += 1;
+                }
+            }
+            bases.push(EnemyBase {
+                center: rect.center(),
+                rect,
+                spawners,
+                worms,
+            });
+        }
+        bases
+    }
+
     pub fn entity_by_id(&self, id: ItemId) -> Option<FactorioEntity> {
         self.entity_tree.read().get(id).cloned()
     }
@@ -869,6 +1424,9 @@ pub struct EntityNode {
     pub entity_type: EntityType,
     pub entity_id: Option<ItemId>,
     pub miner_ore: Option<String>,
+    pub output_inventory: Option<BTreeMap<String, u32>>,
+    pub input_inventory: Option<BTreeMap<String, u32>>,
+    pub fuel_inventory: Option<BTreeMap<String, u32>>,
 }
 
 impl std::fmt::Display for EntityNode {
@@ -912,15 +1470,49 @@ impl EntityNode {
             direction,
             miner_ore,
             entity_id: Some(entity_id),
+            output_inventory: *entity.output_inventory,
+            input_inventory: *entity.input_inventory,
+            fuel_inventory: *entity.fuel_inventory,
             entity_name: entity.name,
             entity_type,
         }
     }
+
+    pub fn inventory(&self) -> InventoryResponse {
+        InventoryResponse {
+            name: self.entity_name.clone(),
+            position: self.position.clone(),
+            output_inventory: Box::new(self.output_inventory.clone()),
+            input_inventory: Box::new(self.input_inventory.clone()),
+            fuel_inventory: Box::new(self.fuel_inventory.clone()),
+        }
+    }
+}
+
+struct ElectricLayer {
+    networks: Vec<ElectricNetwork>,
+    pole_areas: Vec<(Rect, usize)>,
+    covered: HashMap<ItemId, usize>,
 }
 
 pub type EntityGraphInner = StableGraph<EntityNode, f64>;
 
 pub type QuadTreeRect = TypedRect<f32, Rect>;
+
+fn chunk_of(position: &Position) -> ChunkPosition {
+    ChunkPosition {
+        x: (position.x().floor() as i32).div_euclid(32),
+        y: (position.y().floor() as i32).div_euclid(32),
+    }
+}
+
+/// bounds of every quad tree, the whole map the bot can know about
+pub fn max_area() -> QuadTreeRect {
+    QuadTreeRect::new(
+        TypedPoint2D::new(-5120., -5120.),
+        TypedSize2D::new(10240., 10240.),
+    )
+}
 pub type BlockedQuadTree = QuadTree<bool, Rect, [(ItemId, QuadTreeRect); 4]>;
 pub type EntityQuadTree = QuadTree<FactorioEntity, Rect, [(ItemId, QuadTreeRect); 4]>;
 pub type TileQuadTree = QuadTree<FactorioTile, Rect, [(ItemId, QuadTreeRect); 4]>;
@@ -928,7 +1520,7 @@ pub type ResourceQuadTree = QuadTree<String, Rect, [(ItemId, QuadTreeRect); 4]>;
 
 #[cfg(test)]
 mod tests {
-    use crate::factorio::tests::entity_graph_from;
+    use crate::factorio::tests::{entity_graph_from, fixture_entity_prototypes};
 
     use super::*;
 
@@ -958,6 +1550,38 @@ mod tests {
 "#,
         );
     }
+    #[test]
+    fn test_incremental_connect() {
+        let graph = EntityGraph::new(
+            Arc::new(fixture_entity_prototypes()),
+            Arc::new(DashMap::new()),
+        );
+        // added against belt direction so every new belt feeds into an existing one
+        for y in &[2.5, 1.5, 0.5] {
+            graph
+                .add(
+                    vec![FactorioEntity::new_transport_belt(
+                        &Position::new(0.5, *y),
+                        Direction::South,
+                    )],
+                    None,
+                )
+                .unwrap();
+        }
+        let edge_count = |graph: &EntityGraph| graph.inner_graph().edge_count();
+        assert_eq!(edge_count(&graph), 2);
+        assert_eq!(graph.take_changes(), None);
+
+        let middle = FactorioEntity::new_transport_belt(&Position::new(0.5, 1.5), Direction::South);
+        graph.remove(&middle).unwrap();
+        assert_eq!(edge_count(&graph), 0);
+        assert_eq!(graph.take_changes().unwrap().len(), 3);
+
+        graph.add(vec![middle], None).unwrap();
+        assert_eq!(edge_count(&graph), 2);
+        assert_eq!(graph.take_changes().unwrap().len(), 3);
+    }
+
     #[test]
     fn test_condense() {
         let graph = entity_graph_from(vec![
@@ -979,6 +1603,161 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inventory_update() {
+        let mut drill =
+            FactorioEntity::new_burner_mining_drill(&Position::new(1., 1.), Direction::North);
+        drill.fuel_inventory = Box::new(Some(BTreeMap::new()));
+        let graph = entity_graph_from(vec![
+            drill,
+            FactorioEntity::new_stone_furnace(&Position::new(1., 4.), Direction::North),
+        ])
+        .unwrap();
+        assert_eq!(graph.entities_needing_fuel(1).len(), 1);
+        assert_eq!(graph.entities_with_output().len(), 0);
+
+        let mut fuel = BTreeMap::new();
+        fuel.insert("coal".to_string(), 5);
+        let mut output = BTreeMap::new();
+        output.insert("iron-plate".to_string(), 3);
+        graph
+            .update_inventory(InventoryResponse {
+                name: "burner-mining-drill".into(),
+                position: Position::new(1., 1.),
+                output_inventory: Box::new(None),
+                input_inventory: Box::new(None),
+                fuel_inventory: Box::new(Some(fuel)),
+            })
+            .unwrap();
+        graph
+            .update_inventory(InventoryResponse {
+                name: "stone-furnace".into(),
+                position: Position::new(1., 4.),
+                output_inventory: Box::new(Some(output)),
+                input_inventory: Box::new(None),
+                fuel_inventory: Box::new(None),
+            })
+            .unwrap();
+        assert_eq!(graph.entities_needing_fuel(1).len(), 0);
+        assert_eq!(graph.entities_with_output().len(), 1);
+        assert!(graph
+            .update_inventory(InventoryResponse {
+                name: "wooden-chest".into(),
+                position: Position::new(1., 4.),
+                output_inventory: Box::new(None),
+                input_inventory: Box::new(None),
+                fuel_inventory: Box::new(None),
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_resource_patches() {
+        let iron_ore = EntityName::IronOre.to_string();
+        let mut entities: Vec<FactorioEntity> = vec![];
+        let mut ore = |x: f64, y: f64| {
+            let mut entity =
+                FactorioEntity::new_resource(&Position::new(x, y), Direction::North, &iron_ore);
+            entity.amount = Some(1000);
+            entities.push(entity);
+        };
+        // a compact square and a longer but thin line with more tiles
+        for y in 0..4 {
+            for x in 0..4 {
+                ore(x as f64 + 0.5, y as f64 + 0.5);
+            }
+        }
+        for y in 0..20 {
+            ore(20.5, y as f64 + 0.5);
+        }
+        let graph = entity_graph_from(entities).unwrap();
+        let patches = graph.resource_patches(&iron_ore);
+        assert_eq!(patches.len(), 2);
+        let square = &patches[0];
+        assert_eq!(square.elements.len(), 16);
+        assert_eq!(square.total_amount(), 16000);
+        assert_eq!(square.centroid(), Position::new(2., 2.));
+        assert_eq!(square.compactness(), 1.);
+        assert_eq!(square.fill_ratio(), 1.);
+        assert!(patches[1].compactness() < 0.2);
+
+        let drill =
+            FactorioEntity::new_electric_mining_drill(&Position::new(2., 2.), Direction::North);
+        assert_eq!(
+            square.depletion_seconds(&[drill.clone()], &graph.entity_prototypes()),
+            Some(32000.)
+        );
+        assert_eq!(
+            patches[1].depletion_seconds(&[drill], &graph.entity_prototypes()),
+            None
+        );
+        assert!(graph.resource_patches("coal").is_empty());
+    }
+
+    #[test]
+    fn test_electric_networks() {
+        let graph = entity_graph_from(vec![
+            FactorioEntity::new_steam_engine(&Position::new(-3.5, 0.5), Direction::North),
+            FactorioEntity::new_small_electric_pole(&Position::new(-1.5, 0.5)),
+            FactorioEntity::new_small_electric_pole(&Position::new(5.5, 0.5)),
+            FactorioEntity::new_electric_mining_drill(&Position::new(5.5, 3.5), Direction::North),
+            FactorioEntity::new_electric_mining_drill(&Position::new(20.5, 20.5), Direction::North),
+            FactorioEntity::new_small_electric_pole(&Position::new(30.5, 30.5)),
+            FactorioEntity::new_electric_mining_drill(&Position::new(30.5, 32.5), Direction::North),
+        ])
+        .unwrap();
+        let networks = graph.electric_networks();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].poles.len(), 2);
+        assert_eq!(networks[0].generators, vec![Position::new(-3.5, 0.5)]);
+        assert_eq!(networks[0].consumers, vec![Position::new(5.5, 3.5)]);
+        assert_eq!(networks[0].generation_capacity, 900000.);
+        assert_eq!(networks[0].consumption, 90000.);
+        assert_eq!(networks[0].satisfaction, 1.);
+        assert_eq!(networks[1].consumers.len(), 1);
+        assert_eq!(networks[1].satisfaction, 0.);
+
+        let unpowered: Vec<Position> = graph
+            .unpowered_entities()
+            .iter()
+            .map(|entity| entity.position.clone())
+            .collect();
+        assert_eq!(
+            unpowered,
+            vec![Position::new(20.5, 20.5), Position::new(30.5, 32.5)]
+        );
+        assert!(graph.is_powered(&FactorioEntity::new_electric_mining_drill(
+            &Position::new(0.5, 3.5),
+            Direction::North
+        )));
+        assert!(
+            !graph.is_powered(&FactorioEntity::new_electric_mining_drill(
+                &Position::new(12.5, 0.5),
+                Direction::North
+            ))
+        );
+
+        // the cached layer has to follow added and removed poles
+        graph
+            .add(
+                vec![FactorioEntity::new_small_electric_pole(&Position::new(
+                    11.5, 0.5,
+                ))],
+                None,
+            )
+            .unwrap();
+        assert!(graph.is_powered(&FactorioEntity::new_electric_mining_drill(
+            &Position::new(12.5, 0.5),
+            Direction::North
+        )));
+        graph
+            .remove(&FactorioEntity::new_small_electric_pole(&Position::new(
+                -1.5, 0.5,
+            )))
+            .unwrap();
+        assert_eq!(graph.electric_networks()[0].generation_capacity, 0.);
+    }
+
     #[test]
     fn test_splitters2() {
         let graph = entity_graph_from(vec![]).unwrap();
@@ -1003,4 +1782,45 @@ mod tests {
 "#,
         );
     }
+
+    #[test]
+    fn test_enemy_bases() {
+        let enemy = |name: &str, entity_type: EntityType, x: f64, y: f64, size: f64| {
+            let position = Position::new(x, y);
+            FactorioEntity {
+                name: name.into(),
+                entity_type: entity_type.to_string(),
+                bounding_box: add_to_rect(&Rect::from_wh(size, size), &position),
+                position,
+                force: Some("enemy".into()),
+                ..Default::default()
+            }
+        };
+        let graph = entity_graph_from(vec![
+            enemy("biter-spawner", EntityType::UnitSpawner, 100., 100., 5.),
+            enemy("biter-spawner", EntityType::UnitSpawner, 110., 100., 5.),
+            enemy("small-worm-turret", EntityType::Turret, 104., 106., 2.),
+            enemy("biter-spawner", EntityType::UnitSpawner, 200., 200., 5.),
+            enemy("small-biter", EntityType::Unit, 150., 150., 0.4),
+        ])
+        .unwrap();
+        let mut bases = graph.enemy_bases();
+        bases.sort_by(|a, b| a.center.x().partial_cmp(&b.center.x()).unwrap());
+        assert_eq!(bases.len(), 2);
+        assert_eq!((bases[0].spawners, bases[0].worms), (2, 1));
+        assert_eq!((bases[1].spawners, bases[1].worms), (1, 0));
+        assert_eq!(bases[1].center, Position::new(200., 200.));
+        // biters move around and are tracked by the world instead
+        let around_biter = add_to_rect(&Rect::from_wh(4., 4.), &Position::new(150., 150.));
+        assert!(graph.inner_tree().query(around_biter.into()).is_empty());
+        assert_eq!(
+            graph
+                .enemies_in(&add_to_rect(
+                    &Rect::from_wh(4., 4.),
+                    &Position::new(104., 106.)
+                ))
+                .len(),
+            1
+        );
+    }
 }
@@ -3,7 +3,7 @@ use crate::factorio::util::{add_to_rect, format_dotgraph};
 use crate::num_traits::FromPrimitive;
 use crate::types::{
     Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioRecipe,
-    Position, Rect,
+    FlowAnalysis, FlowEdgeSaturation, Position, Rect, StarvedMachine,
 };
 use aabb_quadtree::{ItemId, QuadTree};
 use dashmap::lock::{RwLock, RwLockReadGuard};
@@ -14,11 +14,16 @@ use petgraph::dot::{Config, Dot};
 use petgraph::graph::NodeIndex;
 use petgraph::stable_graph::StableGraph;
 use petgraph::visit::{depth_first_search, Bfs, Control, DfsEvent, EdgeRef};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 
+// base crafting time of iron, copper and stone smelting, used when the recipe is unknown
+const SMELTING_TIME: f64 = 3.2;
+// machines running below this share of their full speed are reported as starved
+const STARVED_THRESHOLD: f64 = 0.99;
+
 pub struct FlowGraph {
     entity_graph: Arc<EntityGraph>,
     entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
@@ -33,25 +38,17 @@ impl FlowGraph {
             entity_prototypes: entity_graph.entity_prototypes(),
             recipes: entity_graph.recipes(),
             entity_graph,
-            flow_tree: RwLock::new(FlowQuadTree::new(
-                QuadTreeRect::new(
-                    TypedPoint2D::new(-5120., -5120.),
-                    TypedSize2D::new(10240., 10240.),
-                ),
-                true,
-                32,
-                128,
-                32,
-                8,
-            )),
+            flow_tree: RwLock::new(new_flow_tree()),
             inner: RwLock::new(FlowGraphInner::new()),
         }
     }
 
+    /// only recalculates the flow of entity graph components changed since the last update
     pub fn update(&self) -> anyhow::Result<()> {
         let _started = Instant::now();
+        let roots = self.affected_roots();
         let inner = self.entity_graph.inner_graph();
-        for entity_root_index in inner.externals(petgraph::Direction::Incoming) {
+        for entity_root_index in roots {
             let entity_root = inner.node_weight(entity_root_index).unwrap();
             if entity_root.entity_type == EntityType::OffshorePump
                 || (entity_root.entity_type == EntityType::MiningDrill
@@ -116,31 +113,35 @@ impl FlowGraph {
                             }
                             EntityType::AssemblingMachine => {
                                 // can have multiple incoming and multiple outgoing
-                                let tree = self.entity_graph.inner_tree();
-                                let entity = tree.get(source_node.entity_id.unwrap()).unwrap();
-
-                                if let Some(recipe) = entity.recipe.as_ref() {
-                                    if let Some(recipe) = self.recipes.get(recipe) {
-                                        let mut output: FlowRates = vec![];
-                                        for product in recipe.products.iter() {
-                                            // FIXME: only if enough input?
-                                            output.push((
-                                                product.name.clone(),
-                                                product.amount as f64 / 3.2, // FIXME: correct amount based on assembler speed
-                                            ));
-                                        }
+                                let recipe = self
+                                    .entity_graph
+                                    .entity_by_id(source_node.entity_id.unwrap())
+                                    .and_then(|entity| entity.recipe)
+                                    .and_then(|recipe| self.recipes.get(&recipe))
+                                    .map(|recipe| recipe.value().clone());
+                                match recipe {
+                                    Some(recipe) => {
+                                        let incoming =
+                                            self.sum_incoming_edge_weights(&source_node.position);
+                                        let output = self.craft(
+                                            &source_node.entity_name,
+                                            &recipe,
+                                            &incoming,
+                                        );
+                                        let outgoing_count = entity_graph
+                                            .edges_directed(
+                                                source_node_index,
+                                                petgraph::Direction::Outgoing,
+                                            )
+                                            .count();
                                         self.update_flow_edge(
-                                            FlowEdge::Single(output),
+                                            FlowEdge::Single(divide_rates(&output, outgoing_count)),
                                             source_node,
                                             target_node,
                                         );
                                         Control::Continue
-                                    } else {
-                                        // warn!("recipe not found: {}", recipe);
-                                        Control::Prune
                                     }
-                                } else {
-                                    Control::Prune
+                                    None => Control::Prune,
                                 }
                             }
                             EntityType::Splitter => {
@@ -164,42 +165,27 @@ impl FlowGraph {
                                 // can have multiple incoming and multiple outgoing
                                 let incoming =
                                     self.sum_incoming_edge_weights(&source_node.position);
-                                /*
-                                Smelting iron, copper, and stone each take a base 3.2 seconds to finish.
-                                Smelting steel takes a base 16 seconds.
-                                Stone Furnaces have a crafting speed of 1.
-                                Both Steel and Electric Furnaces have a crafting speed of 2.
-                                One furnace making iron can support one furnace making steel.
-                                Stone and Steel Furnaces consume 0.0225 coal/second.
-                                             */
-
-                                let mut output: FlowRates = vec![];
-                                for (name, _rate) in &incoming {
-                                    if let Ok(name) = EntityName::from_str(&name) {
-                                        match name {
-                                            EntityName::IronOre => output.push((
-                                                EntityName::IronPlate.to_string(),
-                                                1. / 3.2,
-                                            )),
-                                            EntityName::CopperOre => output.push((
-                                                EntityName::CopperPlate.to_string(),
-                                                1. / 3.2,
-                                            )),
-                                            EntityName::Stone => output.push((
-                                                EntityName::StoneBrick.to_string(),
-                                                1. / 3.2,
-                                            )),
-                                            EntityName::IronPlate => output
-                                                .push((EntityName::Steel.to_string(), 1. / 3.2)),
-                                            EntityName::Coal => {}
-                                            _ => warn!("invalid furnace input: {}", name),
+                                let output = match self.smelting_recipe(&incoming) {
+                                    Some(recipe) => {
+                                        self.craft(&source_node.entity_name, &recipe, &incoming)
+                                    }
+                                    None => {
+                                        for (name, _) in &incoming {
+                                            if *name != EntityName::Coal.to_string() {
+                                                warn!("invalid furnace input: {}", name);
+                                            }
                                         }
-                                    } else {
-                                        warn!("invalid furnace input: {}", name);
+                                        vec![]
                                     }
-                                }
+                                };
+                                let outgoing_count = entity_graph
+                                    .edges_directed(
+                                        source_node_index,
+                                        petgraph::Direction::Outgoing,
+                                    )
+                                    .count();
                                 self.update_flow_edge(
-                                    FlowEdge::Single(output),
+                                    FlowEdge::Single(divide_rates(&output, outgoing_count)),
                                     source_node,
                                     target_node,
                                 );
@@ -271,6 +257,75 @@ impl FlowGraph {
         Ok(())
     }
 
+    /// removes the flow of everything connected to changed entities and returns the entity
+    /// graph roots from which it has to be propagated again
+    fn affected_roots(&self) -> Vec<NodeIndex> {
+        let inner = self.entity_graph.inner_graph();
+        let entity_nodes: Vec<NodeIndex> = match self.entity_graph.take_changes() {
+            None => {
+                *self.inner.write() = FlowGraphInner::new();
+                *self.flow_tree.write() = new_flow_tree();
+                inner.node_indices().collect()
+            }
+            Some(mut positions) => {
+                let mut entity_nodes: HashSet<NodeIndex> = HashSet::new();
+                let mut flow_nodes: HashSet<NodeIndex> = HashSet::new();
+                let flow = self.inner.read();
+                // flood fill both graphs, the old flow may reach entities which are no longer connected
+                while let Some(position) = positions.pop() {
+                    if let Some(node_index) = self.entity_graph.node_at(&position) {
+                        if entity_nodes.insert(node_index) {
+                            for neighbor in inner.neighbors_undirected(node_index) {
+                                positions
+                                    .push(inner.node_weight(neighbor).unwrap().position.clone());
+                            }
+                        }
+                    }
+                    if let Some(node_index) = self.node_at(&position) {
+                        if flow_nodes.insert(node_index) {
+                            for neighbor in flow.neighbors_undirected(node_index) {
+                                positions
+                                    .push(flow.node_weight(neighbor).unwrap().position.clone());
+                            }
+                        }
+                    }
+                }
+                drop(flow);
+                for node_index in flow_nodes {
+                    self.remove_flow_node(node_index);
+                }
+                let mut entity_nodes: Vec<NodeIndex> = entity_nodes.into_iter().collect();
+                entity_nodes.sort();
+                entity_nodes
+            }
+        };
+        let roots: Vec<NodeIndex> = entity_nodes
+            .into_iter()
+            .filter(|node_index| {
+                inner
+                    .neighbors_directed(*node_index, petgraph::Direction::Incoming)
+                    .next()
+                    .is_none()
+            })
+            .collect();
+        roots
+    }
+
+    fn remove_flow_node(&self, node_index: NodeIndex) {
+        if let Some(node) = self.inner.write().remove_node(node_index) {
+            let mut tree = self.flow_tree.write();
+            let item_ids: Vec<ItemId> = tree
+                .query(add_to_rect(&Rect::from_wh(0.1, 0.1), &node.position).into())
+                .iter()
+                .filter(|(index, _, _)| **index == node_index)
+                .map(|(_, _, item_id)| *item_id)
+                .collect();
+            for item_id in item_ids {
+                tree.remove(item_id);
+            }
+        }
+    }
+
     pub fn get_or_create_flow_node(&self, entity_node: &EntityNode) -> NodeIndex {
         self.node_at(&entity_node.position).unwrap_or_else(|| {
             let entity_id = entity_node.entity_id.unwrap();
@@ -543,6 +598,323 @@ impl FlowGraph {
         }
         self.sum_production_rates(rates)
     }
+
+    /// furnaces pick their recipe by the items they are fed with
+    pub fn smelting_recipe(&self, input: &[FlowRate]) -> Option<FactorioRecipe> {
+        for (name, rate) in input {
+            if *rate <= 0. {
+                continue;
+            }
+            let recipe = self.recipes.iter().find(|recipe| {
+                recipe.category == "smelting"
+                    && match recipe.ingredients.as_ref() {
+                        Some(ingredients) => ingredients.len() == 1 && ingredients[0].name == *name,
+                        None => false,
+                    }
+            });
+            if let Some(recipe) = recipe {
+                return Some(recipe.value().clone());
+            }
+        }
+        None
+    }
+
+    /// output rates of a crafting machine running the given recipe, limited by
+    /// the available input and by its crafting speed
+    pub fn craft(
+        &self,
+        entity_name: &str,
+        recipe: &FactorioRecipe,
+        input: &[FlowRate],
+    ) -> FlowRates {
+        let (crafting_speed, productivity) = match self.entity_prototypes.get(entity_name) {
+            Some(prototype) => (
+                (*prototype.crafting_speed)
+                    .map(|speed| speed.to_f64().unwrap())
+                    .unwrap_or(1.),
+                (*prototype.base_productivity)
+                    .map(|productivity| productivity.to_f64().unwrap())
+                    .unwrap_or(0.),
+            ),
+            None => (1., 0.),
+        };
+        let mut crafts_per_second = crafting_speed / recipe.energy.to_f64().unwrap();
+        if let Some(ingredients) = recipe.ingredients.as_ref() {
+            for ingredient in ingredients {
+                let available: f64 = input
+                    .iter()
+                    .filter(|(name, _)| *name == ingredient.name)
+                    .map(|(_, rate)| rate)
+                    .sum();
+                crafts_per_second = crafts_per_second.min(available / ingredient.amount as f64);
+            }
+        }
+        let mut output: FlowRates = vec![];
+        if crafts_per_second <= 0. {
+            return output;
+        }
+        for product in &recipe.products {
+            self.add_production_rate(
+                &mut output,
+                (
+                    product.name.clone(),
+                    crafts_per_second
+                        * product.amount as f64
+                        * product.probability.to_f64().unwrap()
+                        * (1. + productivity),
+                ),
+            );
+        }
+        output
+    }
+
+    /// the recipe a crafting machine runs, furnaces pick it by the items going in or out
+    fn machine_recipe(
+        &self,
+        node: &FlowNode,
+        rates: &[FlowRate],
+        is_input: bool,
+    ) -> Option<FactorioRecipe> {
+        match node.entity_type {
+            EntityType::Furnace if is_input => self.smelting_recipe(rates),
+            EntityType::Furnace => self
+                .recipes
+                .iter()
+                .find(|recipe| {
+                    recipe.category == "smelting"
+                        && recipe.products.iter().any(|product| {
+                            rates
+                                .iter()
+                                .any(|(name, rate)| *rate > 0. && *name == product.name)
+                        })
+                })
+                .map(|recipe| recipe.value().clone()),
+            EntityType::AssemblingMachine => {
+                let tree = self.entity_graph.inner_tree();
+                let recipe = tree.get(node.entity_id?)?.recipe.clone()?;
+                drop(tree);
+                self.recipes
+                    .get(&recipe)
+                    .map(|recipe| recipe.value().clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// how much an entity can carry or process based on its prototype. crafting machines are
+    /// limited per ingredient for rates going into them and per product for those coming out
+    pub fn node_capacity(
+        &self,
+        node: &FlowNode,
+        rates: &[FlowRate],
+        is_input: bool,
+    ) -> Option<FlowCapacity> {
+        let prototype = self.entity_prototypes.get(&node.entity_name)?;
+        match node.entity_type {
+            EntityType::TransportBelt | EntityType::UndergroundBelt | EntityType::Splitter => {
+                // a belt lane holds 4 items per tile
+                let belt_speed = (*prototype.belt_speed)?.to_f64().unwrap();
+                Some(FlowCapacity::Lanes(belt_speed * 60. * 4.))
+            }
+            EntityType::Inserter => {
+                // one item per swing, a swing is one full revolution
+                let rotation_speed = (*prototype.inserter_rotation_speed)?.to_f64().unwrap();
+                Some(FlowCapacity::Total(rotation_speed * 60.))
+            }
+            EntityType::Furnace | EntityType::AssemblingMachine => {
+                let crafting_speed = (*prototype.crafting_speed)?.to_f64().unwrap();
+                let productivity = (*prototype.base_productivity)
+                    .map(|productivity| productivity.to_f64().unwrap())
+                    .unwrap_or(0.);
+                match self.machine_recipe(node, rates, is_input) {
+                    Some(recipe) => {
+                        let crafts_per_second = crafting_speed / recipe.energy.to_f64().unwrap();
+                        let items: FlowRates = if is_input {
+                            recipe
+                                .ingredients
+                                .iter()
+                                .flatten()
+                                .map(|ingredient| {
+                                    (
+                                        ingredient.name.clone(),
+                                        ingredient.amount as f64 * crafts_per_second,
+                                    )
+                                })
+                                .collect()
+                        } else {
+                            recipe
+                                .products
+                                .iter()
+                                .map(|product| {
+                                    (
+                                        product.name.clone(),
+                                        product.amount as f64
+                                            * crafts_per_second
+                                            * (1. + productivity),
+                                    )
+                                })
+                                .collect()
+                        };
+                        Some(FlowCapacity::Items(items))
+                    }
+                    None if node.entity_type == EntityType::Furnace => Some(FlowCapacity::Items(
+                        rates
+                            .iter()
+                            .filter(|(name, _)| !is_fuel(name))
+                            .map(|(name, _)| (name.clone(), crafting_speed / SMELTING_TIME))
+                            .collect(),
+                    )),
+                    None => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// flow rate divided by the capacity of the weaker end of the edge
+    pub fn edge_saturation(
+        &self,
+        edge: &FlowEdge,
+        source: &FlowNode,
+        target: &FlowNode,
+    ) -> Option<f64> {
+        let rates = edge.rates();
+        let mut saturation: Option<f64> = None;
+        for capacity in [
+            self.node_capacity(source, &rates, false),
+            self.node_capacity(target, &rates, true),
+        ]
+        .iter()
+        .flatten()
+        {
+            let edge_saturation = match (capacity, edge) {
+                (FlowCapacity::Lanes(lane), FlowEdge::Double(left, right)) => {
+                    sum_rates(left).max(sum_rates(right)) / lane
+                }
+                (FlowCapacity::Lanes(lane), FlowEdge::Single(rates)) => {
+                    sum_rates(rates) / (2. * lane)
+                }
+                (FlowCapacity::Total(total), edge) => edge.total() / total,
+                // items the machine does not craft with, like fuel, are not limited
+                (FlowCapacity::Items(items), _) => items
+                    .iter()
+                    .map(|(name, capacity)| {
+                        let rate: f64 = rates
+                            .iter()
+                            .filter(|(rate_name, _)| rate_name == name)
+                            .map(|(_, rate)| rate)
+                            .sum();
+                        rate / capacity
+                    })
+                    .fold(f64::NAN, f64::max),
+            };
+            if edge_saturation.is_finite() {
+                saturation = Some(saturation.map_or(edge_saturation, |s| s.max(edge_saturation)));
+            }
+        }
+        saturation
+    }
+
+    /// returns (input rate, required rate) of the most lacking input of a crafting machine
+    fn machine_input(&self, node: &FlowNode, input: &[FlowRate]) -> Option<(f64, f64)> {
+        let prototype = self.entity_prototypes.get(&node.entity_name)?;
+        let crafting_speed = (*prototype.crafting_speed)?.to_f64().unwrap();
+        match node.entity_type {
+            EntityType::Furnace => {
+                if let Some(recipe) = self.smelting_recipe(input) {
+                    let ingredient = &recipe.ingredients.as_ref().as_ref()?[0];
+                    let input_rate: f64 = input
+                        .iter()
+                        .filter(|(name, _)| *name == ingredient.name)
+                        .map(|(_, rate)| rate)
+                        .sum();
+                    let required_rate =
+                        ingredient.amount as f64 * crafting_speed / recipe.energy.to_f64().unwrap();
+                    return Some((input_rate, required_rate));
+                }
+                let input_rate: f64 = input
+                    .iter()
+                    .filter(|(name, _)| !is_fuel(name))
+                    .map(|(_, rate)| rate)
+                    .sum();
+                Some((input_rate, crafting_speed / SMELTING_TIME))
+            }
+            EntityType::AssemblingMachine => {
+                let recipe = self.machine_recipe(node, input, true)?;
+                let energy = recipe.energy.to_f64().unwrap();
+                let mut most_lacking: Option<(f64, f64)> = None;
+                for ingredient in recipe.ingredients.as_ref().as_ref()? {
+                    let required_rate = ingredient.amount as f64 * crafting_speed / energy;
+                    let input_rate: f64 = input
+                        .iter()
+                        .filter(|(name, _)| *name == ingredient.name)
+                        .map(|(_, rate)| rate)
+                        .sum();
+                    let is_worse = match most_lacking {
+                        Some((i, r)) => input_rate / required_rate < i / r,
+                        None => true,
+                    };
+                    if is_worse {
+                        most_lacking = Some((input_rate, required_rate));
+                    }
+                }
+                most_lacking
+            }
+            _ => None,
+        }
+    }
+
+    /// compares flow rates against entity capacities to find overloaded edges and
+    /// crafting machines which do not get enough input to run at full speed
+    pub fn analyze(&self) -> FlowAnalysis {
+        let graph = self.inner.read();
+        let mut edges: Vec<FlowEdgeSaturation> = vec![];
+        let mut starved: Vec<StarvedMachine> = vec![];
+        for node_index in graph.node_indices() {
+            let node = graph.node_weight(node_index).unwrap();
+            for edge in graph.edges_directed(node_index, petgraph::Direction::Outgoing) {
+                let target = graph.node_weight(edge.target()).unwrap();
+                if let Some(saturation) = self.edge_saturation(edge.weight(), node, target) {
+                    edges.push(FlowEdgeSaturation {
+                        from_name: node.entity_name.clone(),
+                        from_position: node.position.clone(),
+                        to_name: target.entity_name.clone(),
+                        to_position: target.position.clone(),
+                        rate: edge.weight().total(),
+                        saturation,
+                    });
+                }
+            }
+            let mut input: FlowRates = vec![];
+            for edge in graph.edges_directed(node_index, petgraph::Direction::Incoming) {
+                for rate in edge.weight().rates() {
+                    self.add_production_rate(&mut input, rate);
+                }
+            }
+            if let Some((input_rate, required_rate)) = self.machine_input(node, &input) {
+                if required_rate > 0. && input_rate < required_rate * STARVED_THRESHOLD {
+                    starved.push(StarvedMachine {
+                        name: node.entity_name.clone(),
+                        position: node.position.clone(),
+                        input_rate,
+                        required_rate,
+                        utilization: input_rate / required_rate,
+                    });
+                }
+            }
+        }
+        let bottlenecks: Vec<FlowEdgeSaturation> = edges
+            .iter()
+            .filter(|edge| edge.saturation > 1.)
+            .cloned()
+            .collect();
+        FlowAnalysis {
+            edges,
+            bottlenecks,
+            starved,
+        }
+    }
+
     pub fn graphviz_dot(&self) -> String {
         format_dotgraph(
             Dot::with_config(&*self.inner.read(), &[Config::GraphContentOnly]).to_string(),
@@ -644,6 +1016,44 @@ impl FlowEdge {
     }
 }
 
+fn divide_rates(rates: &[FlowRate], divisor: usize) -> FlowRates {
+    rates
+        .iter()
+        .map(|(name, rate)| (name.clone(), rate / divisor.max(1) as f64))
+        .collect()
+}
+
+impl FlowEdge {
+    /// all rates of the edge, lanes are not merged
+    pub fn rates(&self) -> FlowRates {
+        match self {
+            FlowEdge::Single(rates) => rates.clone(),
+            FlowEdge::Double(left, right) => left.iter().chain(right.iter()).cloned().collect(),
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        sum_rates(&self.rates())
+    }
+}
+
+fn sum_rates(rates: &[FlowRate]) -> f64 {
+    rates.iter().map(|(_, rate)| rate).sum()
+}
+
+fn is_fuel(name: &str) -> bool {
+    name == EntityName::Coal.to_string() || name == EntityName::Wood.to_string()
+}
+
+/// items per second an entity can carry
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlowCapacity {
+    Lanes(f64),
+    Total(f64),
+    // per item of the recipe a crafting machine runs
+    Items(FlowRates),
+}
+
 impl Default for FlowEdge {
     fn default() -> Self {
         FlowEdge::Single(vec![])
@@ -651,6 +1061,20 @@ impl Default for FlowEdge {
 }
 
 pub type FlowGraphInner = StableGraph<FlowNode, FlowEdge>;
+
+fn new_flow_tree() -> FlowQuadTree {
+    FlowQuadTree::new(
+        QuadTreeRect::new(
+            TypedPoint2D::new(-5120., -5120.),
+            TypedSize2D::new(10240., 10240.),
+        ),
+        true,
+        32,
+        128,
+        32,
+        8,
+    )
+}
 pub type FlowRate = (String, f64);
 pub type FlowRates = Vec<FlowRate>;
 
@@ -774,4 +1198,189 @@ mod tests {
 "#,
         );
     }
+
+    #[test]
+    fn test_bottlenecks() {
+        let entity_graph = entity_graph_from(vec![
+            FactorioEntity::new_resource(
+                &Position::new(0.5, -1.5),
+                Direction::South,
+                &EntityName::IronOre.to_string(),
+            ),
+            FactorioEntity::new_electric_mining_drill(&Position::new(0.5, -1.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::South),
+            FactorioEntity::new_inserter(&Position::new(0.5, 1.5), Direction::North),
+            FactorioEntity::new_stone_furnace(&Position::new(1., 3.), Direction::South),
+            FactorioEntity::new_inserter(&Position::new(0.5, 4.5), Direction::North),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 5.5), Direction::South),
+        ])
+        .unwrap();
+        let flow_graph = FlowGraph::new(Arc::new(entity_graph));
+        flow_graph.update().unwrap();
+        let analysis = flow_graph.analyze();
+        assert_eq!(analysis.edges.len(), 5);
+        // an electric drill mines faster than a stone furnace can smelt
+        assert_eq!(analysis.bottlenecks.len(), 1);
+        assert_eq!(analysis.bottlenecks[0].from_name, "inserter");
+        assert_eq!(analysis.bottlenecks[0].to_name, "stone-furnace");
+        assert!((analysis.bottlenecks[0].saturation - 1.6).abs() < 0.001);
+        assert!(analysis.starved.is_empty());
+    }
+
+    #[test]
+    fn test_assembler_recipe() {
+        let entity_graph = entity_graph_from(vec![
+            FactorioEntity::new_resource(
+                &Position::new(0.5, -1.5),
+                Direction::South,
+                &EntityName::IronOre.to_string(),
+            ),
+            FactorioEntity::new_electric_mining_drill(&Position::new(0.5, -1.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::South),
+            FactorioEntity::new_inserter(&Position::new(0.5, 1.5), Direction::North),
+            FactorioEntity::new_stone_furnace(&Position::new(1., 3.), Direction::South),
+            FactorioEntity::new_inserter(&Position::new(0.5, 4.5), Direction::North),
+            FactorioEntity::new_assembling_machine(
+                &Position::new(0.5, 6.5),
+                Direction::South,
+                "iron-gear-wheel",
+            ),
+            FactorioEntity::new_inserter(&Position::new(0.5, 8.5), Direction::North),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 9.5), Direction::South),
+        ])
+        .unwrap();
+        let flow_graph = FlowGraph::new(Arc::new(entity_graph));
+        flow_graph.update().unwrap();
+        let analysis = flow_graph.analyze();
+        let smelted = analysis
+            .edges
+            .iter()
+            .find(|edge| edge.from_name == "stone-furnace")
+            .unwrap();
+        assert!((smelted.rate - 0.3125).abs() < 0.001);
+        // two plates per gear, limited by the furnace and not the assembler
+        let crafted = analysis
+            .edges
+            .iter()
+            .find(|edge| edge.from_name == "assembling-machine-1")
+            .unwrap();
+        assert!((crafted.rate - 0.15625).abs() < 0.001);
+        assert_eq!(analysis.starved.len(), 1);
+        assert_eq!(analysis.starved[0].name, "assembling-machine-1");
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let entity_graph = Arc::new(
+            entity_graph_from(vec![
+                FactorioEntity::new_resource(
+                    &Position::new(0.5, -1.5),
+                    Direction::South,
+                    &EntityName::IronOre.to_string(),
+                ),
+                FactorioEntity::new_electric_mining_drill(
+                    &Position::new(0.5, -1.5),
+                    Direction::South,
+                ),
+                FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::South),
+                FactorioEntity::new_inserter(&Position::new(0.5, 1.5), Direction::North),
+                FactorioEntity::new_stone_furnace(&Position::new(1., 3.), Direction::South),
+                FactorioEntity::new_inserter(&Position::new(0.5, 4.5), Direction::North),
+                FactorioEntity::new_transport_belt(&Position::new(0.5, 5.5), Direction::South),
+            ])
+            .unwrap(),
+        );
+        let flow_graph = FlowGraph::new(entity_graph.clone());
+        flow_graph.update().unwrap();
+        assert_eq!(flow_graph.analyze().edges.len(), 5);
+
+        let inserter = FactorioEntity::new_inserter(&Position::new(0.5, 4.5), Direction::North);
+        entity_graph.remove(&inserter).unwrap();
+        flow_graph.update().unwrap();
+        assert_eq!(flow_graph.analyze().edges.len(), 3);
+        assert!(flow_graph.node_at(&Position::new(0.5, 5.5)).is_none());
+
+        entity_graph.add(vec![inserter], None).unwrap();
+        flow_graph.update().unwrap();
+        let analysis = flow_graph.analyze();
+        assert_eq!(analysis.edges.len(), 5);
+        let last = analysis
+            .edges
+            .iter()
+            .find(|edge| edge.to_name == "transport-belt" && edge.from_name == "inserter")
+            .unwrap();
+        assert!((last.rate - 0.3125).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_machine_capacity() {
+        let furnace = FactorioEntity::new_stone_furnace(&Position::new(1., 3.), Direction::South);
+        let assembler = FactorioEntity::new_assembling_machine(
+            &Position::new(5.5, 3.5),
+            Direction::South,
+            "iron-gear-wheel",
+        );
+        let inserter = FactorioEntity::new_inserter(&Position::new(0.5, 1.5), Direction::North);
+        let entity_graph = Arc::new(
+            entity_graph_from(vec![furnace.clone(), assembler.clone(), inserter.clone()]).unwrap(),
+        );
+        let flow_graph = FlowGraph::new(entity_graph.clone());
+        let node = |entity: &FactorioEntity| {
+            FlowNode::new(
+                entity,
+                None,
+                entity_graph.entity_at(&entity.position).unwrap(),
+            )
+        };
+        let (furnace, assembler, inserter) = (node(&furnace), node(&assembler), node(&inserter));
+
+        // steel takes 16s for 5 plates, coal on the same inserter does not count
+        let edge = FlowEdge::Single(vec![
+            (EntityName::IronPlate.to_string(), 0.3125),
+            (EntityName::Coal.to_string(), 0.2),
+        ]);
+        assert_eq!(
+            flow_graph.node_capacity(&furnace, &edge.rates(), true),
+            Some(FlowCapacity::Items(vec![(
+                EntityName::IronPlate.to_string(),
+                0.3125
+            )]))
+        );
+        let steel = FlowEdge::Single(vec![("steel-plate".into(), 0.125)]);
+        assert_eq!(
+            flow_graph.node_capacity(&furnace, &steel.rates(), false),
+            Some(FlowCapacity::Items(vec![("steel-plate".into(), 0.0625)]))
+        );
+        let saturation = flow_graph
+            .edge_saturation(&steel, &furnace, &inserter)
+            .unwrap();
+        assert!((saturation - 2.).abs() < 0.001);
+
+        // an unknown recipe falls back to the smelting time without fuel
+        let edge = FlowEdge::Single(vec![
+            ("unknown-ore".into(), 0.3125),
+            (EntityName::Coal.to_string(), 0.2),
+        ]);
+        assert_eq!(
+            flow_graph.node_capacity(&furnace, &edge.rates(), true),
+            Some(FlowCapacity::Items(vec![(
+                "unknown-ore".into(),
+                1. / SMELTING_TIME
+            )]))
+        );
+
+        // two plates per gear in 0.5s at crafting speed 0.5
+        let plates = FlowEdge::Single(vec![(EntityName::IronPlate.to_string(), 4.)]);
+        assert_eq!(
+            flow_graph.node_capacity(&assembler, &plates.rates(), true),
+            Some(FlowCapacity::Items(vec![(
+                EntityName::IronPlate.to_string(),
+                2.
+            )]))
+        );
+        assert_eq!(
+            flow_graph.node_capacity(&assembler, &[], false),
+            Some(FlowCapacity::Items(vec![("iron-gear-wheel".into(), 1.)]))
+        );
+    }
 }
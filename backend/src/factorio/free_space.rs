@@ -0,0 +1,221 @@
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::types::{FreeSpaceQuery, Position, Rect};
+
+const CHUNK_SIZE: i32 = 32;
+const DEFAULT_MAX_DISTANCE: u32 = 128;
+
+/// tile counts over a window of the map, any rectangle is summed up in constant time
+struct TileSums {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+    sums: Vec<u32>,
+}
+
+impl TileSums {
+    fn new(left: i32, top: i32, width: i32, height: i32) -> TileSums {
+        TileSums {
+            left,
+            top,
+            width,
+            height,
+            sums: vec![0; ((width + 1) * (height + 1)) as usize],
+        }
+    }
+
+    fn window(&self) -> QuadTreeRect {
+        Rect::new(
+            &Position::new(self.left as f64, self.top as f64),
+            &Position::new(
+                (self.left + self.width) as f64,
+                (self.top + self.height) as f64,
+            ),
+        )
+        .into()
+    }
+
+    /// marks every tile the rect touches, before `accumulate`
+    fn mark(&mut self, rect: &QuadTreeRect) {
+        let from_x = ((rect.origin.x + 0.01).floor() as i32 - self.left).max(0);
+        let from_y = ((rect.origin.y + 0.01).floor() as i32 - self.top).max(0);
+        let to_x =
+            ((rect.origin.x + rect.size.width - 0.01).ceil() as i32 - self.left).min(self.width);
+        let to_y =
+            ((rect.origin.y + rect.size.height - 0.01).ceil() as i32 - self.top).min(self.height);
+        for y in from_y..to_y {
+            for x in from_x..to_x {
+                self.sums[((y + 1) * (self.width + 1) + x + 1) as usize] = 1;
+            }
+        }
+    }
+
+    fn accumulate(&mut self) {
+        let stride = (self.width + 1) as usize;
+        for y in 1..=self.height as usize {
+            for x in 1..=self.width as usize {
+                self.sums[y * stride + x] += self.sums[(y - 1) * stride + x]
+                    + self.sums[y * stride + x - 1]
+                    - self.sums[(y - 1) * stride + x - 1];
+            }
+        }
+    }
+
+    /// marked tiles inside the tile rect, which must lie inside the window
+    fn count(&self, left: i32, top: i32, width: i32, height: i32) -> u32 {
+        let stride = self.width + 1;
+        let x0 = left - self.left;
+        let y0 = top - self.top;
+        let x1 = x0 + width;
+        let y1 = y0 + height;
+        let at = |x: i32, y: i32| self.sums[(y * stride + x) as usize];
+        at(x1, y1) + at(x0, y0) - at(x0, y1) - at(x1, y0)
+    }
+}
+
+/// nearest area of the given size around a position where nothing is built, with no water and
+/// depending on the query no ore or only ore. the margin around the area has to be free as
+/// well, ore there is fine
+pub fn find_free_space(entity_graph: &EntityGraph, query: &FreeSpaceQuery) -> anyhow::Result<Rect> {
+    if query.width == 0 || query.height == 0 {
+        bail!("area must not be empty");
+    }
+    let width = query.width as i32;
+    let height = query.height as i32;
+    let margin = query.margin.unwrap_or(0) as i32;
+    let max_distance = query.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE) as i32;
+    let step = if query.align_to_chunk.unwrap_or(false) {
+        CHUNK_SIZE
+    } else {
+        1
+    };
+    let avoid_resources = query.avoid_resources.unwrap_or(true);
+    let near_x = query.near.x().floor() as i32;
+    let near_y = query.near.y().floor() as i32;
+
+    // every candidate left top with its margin fits into the window
+    let left = near_x - max_distance - width - margin - step;
+    let top = near_y - max_distance - height - margin - step;
+    let size = 2 * (max_distance + width + height + 2 * margin + step);
+    let mut blocked = TileSums::new(left, top, size, size);
+    let mut resources = TileSums::new(left, top, size, size);
+    for (_, rect, _) in entity_graph.blocked_tree().query(blocked.window()) {
+        blocked.mark(&rect);
+    }
+    for (tile, rect, _) in entity_graph.tile_tree().query(blocked.window()) {
+        if tile.player_collidable {
+            blocked.mark(&rect);
+        }
+    }
+    for (name, rect, _) in entity_graph.resource_tree().query(resources.window()) {
+        match &query.require_resource {
+            Some(required) if required != name => {}
+            _ => resources.mark(&rect),
+        }
+    }
+    blocked.accumulate();
+    resources.accumulate();
+
+    let area = (width * height) as u32;
+    let align = |value: i32| value.div_euclid(step) * step;
+    let mut best: Option<(f64, i32, i32)> = None;
+    let mut y = align(near_y - max_distance - height / 2);
+    while y <= near_y + max_distance - height / 2 {
+        let mut x = align(near_x - max_distance - width / 2);
+        while x <= near_x + max_distance - width / 2 {
+            let dx = x as f64 + width as f64 / 2. - query.near.x();
+            let dy = y as f64 + height as f64 / 2. - query.near.y();
+            let distance = dx * dx + dy * dy;
+            if best.map_or(true, |(best_distance, _, _)| distance < best_distance)
+                && blocked.count(
+                    x - margin,
+                    y - margin,
+                    width + 2 * margin,
+                    height + 2 * margin,
+                ) == 0
+            {
+                let ore = resources.count(x, y, width, height);
+                let fits = if query.require_resource.is_some() {
+                    ore == area
+                } else {
+                    !avoid_resources || ore == 0
+                };
+                if fits {
+                    best = Some((distance, x, y));
+                }
+            }
+            x += step;
+        }
+        y += step;
+    }
+    match best {
+        Some((_, x, y)) => Ok(Rect::new(
+            &Position::new(x as f64, y as f64),
+            &Position::new((x + width) as f64, (y + height) as f64),
+        )),
+        None => bail!(
+            "no free {}x{} area within {} tiles of {}",
+            width,
+            height,
+            max_distance,
+            query.near
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::types::EntityName;
+
+    use super::*;
+
+    #[test]
+    fn test_find_free_space() {
+        let world = fixture_world();
+        let mut query = FreeSpaceQuery {
+            width: 4,
+            height: 4,
+            near: Position::new(0., 0.),
+            margin: None,
+            avoid_resources: None,
+            require_resource: None,
+            align_to_chunk: None,
+            max_distance: None,
+        };
+        // the iron patch covers -5..=5 around the origin
+        let rect = find_free_space(&world.entity_graph, &query).unwrap();
+        let ore = world.entity_graph.resource_tree();
+        assert!(ore.query(rect.clone().into()).is_empty());
+        drop(ore);
+
+        query.require_resource = Some(EntityName::IronOre.to_string());
+        let rect = find_free_space(&world.entity_graph, &query).unwrap();
+        assert_eq!(rect, Rect::from_wh(4., 4.));
+
+        // the lake spans 38..=42
+        query.require_resource = None;
+        query.near = Position::new(40., 40.);
+        query.margin = Some(1);
+        let rect = find_free_space(&world.entity_graph, &query).unwrap();
+        assert!(world
+            .entity_graph
+            .blocked_tree()
+            .query(
+                Rect::new(
+                    &Position::new(rect.left_top.x() - 0.9, rect.left_top.y() - 0.9),
+                    &Position::new(rect.right_bottom.x() + 0.9, rect.right_bottom.y() + 0.9),
+                )
+                .into()
+            )
+            .is_empty());
+
+        query.align_to_chunk = Some(true);
+        let rect = find_free_space(&world.entity_graph, &query).unwrap();
+        assert_eq!(rect.left_top.x() as i32 % 32, 0);
+        assert_eq!(rect.left_top.y() as i32 % 32, 0);
+
+        query.require_resource = Some(EntityName::UraniumOre.to_string());
+        assert!(find_free_space(&world.entity_graph, &query).is_err());
+    }
+}
@@ -1,18 +1,33 @@
+pub mod belt_router;
+pub mod blueprint_builder;
+pub mod blueprint_transform;
+pub mod circuit_graph;
+pub mod defense;
 pub mod entity_graph;
 pub mod flow_graph;
+pub mod free_space;
 pub mod instance_setup;
+pub mod outpost;
 pub mod output_parser;
 pub mod output_reader;
+pub mod pipe_router;
 pub mod plan_builder;
 pub mod planner;
 pub mod process_control;
+pub mod production_block;
+pub mod rail_graph;
 pub mod rcon;
 pub mod roll_best_seed;
+pub mod seed_db;
 pub mod task_graph;
+pub mod tech_tree;
 #[cfg(test)]
 pub mod tests;
 
 pub mod factorio_planner;
+pub mod tour;
 pub mod util;
+pub mod walk_pathfinder;
 pub mod world;
+pub mod world_query;
 pub mod ws;
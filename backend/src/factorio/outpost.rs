@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashSet};
+
+use num_traits::ToPrimitive;
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::{add_to_rect, add_to_rect_turned, tile_center};
+use crate::types::{
+    Direction, EntityName, EntityType, FactorioEntity, MiningOutpost, Pos, Position, Rect,
+    ResourcePatch,
+};
+
+pub const CHEST: &str = "wooden-chest";
+// electric mining drill, used when the prototype does not report it
+const DEFAULT_MINING_RADIUS: f64 = 2.49;
+
+struct DrillShape {
+    name: String,
+    width: i32,
+    height: i32,
+    radius: f64,
+    collision_box: Rect,
+}
+
+impl DrillShape {
+    fn center(&self, tile: &Pos) -> Position {
+        Position::new(
+            tile.0 as f64 + self.width as f64 / 2.,
+            tile.1 as f64 + self.height as f64 / 2.,
+        )
+    }
+
+    /// the tile the drill puts its output on, for even widths the left one of the two in front
+    fn drop_tile(&self, tile: &Pos, direction: Direction) -> Pos {
+        match direction {
+            Direction::South => Pos(tile.0 + self.width / 2, tile.1 + self.height),
+            _ => Pos(tile.0 + (self.width - 1) / 2, tile.1 - 1),
+        }
+    }
+
+    fn footprint(&self, tile: &Pos) -> Vec<Pos> {
+        let mut tiles: Vec<Pos> = vec![];
+        for y in tile.1..tile.1 + self.height {
+            for x in tile.0..tile.0 + self.width {
+                tiles.push(Pos(x, y));
+            }
+        }
+        tiles
+    }
+
+    /// resource tiles whose center is inside the mining area
+    fn mined_tiles(&self, tile: &Pos, resources: &HashSet<Pos>) -> usize {
+        let center = self.center(tile);
+        let from_x = (center.x() - self.radius - 0.5).ceil() as i32;
+        let to_x = (center.x() + self.radius - 0.5).floor() as i32;
+        let from_y = (center.y() - self.radius - 0.5).ceil() as i32;
+        let to_y = (center.y() + self.radius - 0.5).floor() as i32;
+        let mut count = 0;
+        for y in from_y..=to_y {
+            for x in from_x..=to_x {
+                if resources.contains(&Pos(x, y)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn entity(&self, tile: &Pos, direction: Direction) -> FactorioEntity {
+        let position = self.center(tile);
+        let drop = self.drop_tile(tile, direction);
+        FactorioEntity {
+            name: self.name.clone(),
+            entity_type: EntityType::MiningDrill.to_string(),
+            bounding_box: add_to_rect_turned(&self.collision_box, &position, direction),
+            position,
+            direction: direction.to_u8().unwrap(),
+            drop_position: Some(tile_center(&drop)),
+            ..Default::default()
+        }
+    }
+}
+
+/// drill rows covering a resource patch.
+///
+/// drills with power face each other in pairs of rows around a belt running west, burner drills
+/// face north into a chest each. a drill is only placed where its mining area holds at least
+/// half its footprint in resource tiles, water and existing entities are left out.
+/// electric drill rows leave a free column for small poles every few drills, as many as the
+/// pole supply area reaches on each side and the wire reaches to the next column
+pub fn plan_mining_outpost(
+    entity_graph: &EntityGraph,
+    patch: &ResourcePatch,
+    drill_name: &str,
+) -> anyhow::Result<MiningOutpost> {
+    let prototypes = entity_graph.entity_prototypes();
+    let prototype = prototypes
+        .get(drill_name)
+        .ok_or_else(|| anyhow!("drill {} not found", drill_name))?;
+    if prototype.entity_type != EntityType::MiningDrill.to_string() {
+        bail!("{} is no mining drill", drill_name);
+    }
+    let drill = DrillShape {
+        name: drill_name.into(),
+        width: prototype.collision_box.width().ceil() as i32,
+        height: prototype.collision_box.height().ceil() as i32,
+        radius: (*prototype.mining_drill_radius)
+            .map(|radius| radius.to_f64().unwrap())
+            .unwrap_or(DEFAULT_MINING_RADIUS),
+        collision_box: prototype.collision_box.clone(),
+    };
+    let uses_belts = prototype.electric_energy_usage.is_some();
+    drop(prototype);
+
+    let resources: HashSet<Pos> = patch.elements.iter().map(|e| e.into()).collect();
+    let min_tiles = (drill.width * drill.height / 2) as usize;
+    let blocked_tree = entity_graph.blocked_tree();
+    let is_free = |pos: &Pos| {
+        let rect: QuadTreeRect = add_to_rect(&Rect::from_wh(0.5, 0.5), &tile_center(pos)).into();
+        blocked_tree.query(rect).is_empty()
+    };
+    let can_mine = |tile: &Pos| {
+        drill.footprint(tile).iter().all(|pos| is_free(pos))
+            && drill.mined_tiles(tile, &resources) >= min_tiles
+    };
+    // rows start outside the patch when the mining area is larger than the drill
+    let margin = ((drill.radius * 2.).ceil() as i32 - drill.height).max(0) / 2;
+    let left = patch.rect.left_top.x().floor() as i32 - margin;
+    let top = patch.rect.left_top.y().floor() as i32 - margin;
+    let right = patch.rect.right_bottom.x().floor() as i32 + margin;
+    let bottom = patch.rect.right_bottom.y().floor() as i32 + margin;
+
+    let mut entities: Vec<FactorioEntity> = vec![];
+    let mut outputs: Vec<Position> = vec![];
+    let mut drills = 0;
+    if uses_belts {
+        let pole_name = EntityName::SmallElectricPole.to_string();
+        let pole = prototypes
+            .get(&pole_name)
+            .ok_or_else(|| anyhow!("pole {} not found", pole_name))?;
+        let supply_area_distance = pole
+            .supply_area_distance
+            .as_ref()
+            .map(|d| d.to_f64().unwrap())
+            .unwrap_or(0.);
+        let max_wire_distance = pole
+            .max_wire_distance
+            .as_ref()
+            .map(|d| d.to_f64().unwrap())
+            .unwrap_or(0.);
+        drop(pole);
+        let mut per_side = ((supply_area_distance - 0.5) / drill.width as f64)
+            .ceil()
+            .max(1.) as i32;
+        while per_side > 1 && (2 * per_side * drill.width + 1) as f64 > max_wire_distance {
+            per_side -= 1;
+        }
+        // (left x, is pole column) of every column across the patch
+        let mut columns: Vec<(i32, bool)> = vec![];
+        let mut x = left;
+        while x <= right {
+            for is_pole in &[false, true, false] {
+                if *is_pole {
+                    columns.push((x, true));
+                    x += 1;
+                } else {
+                    for _ in 0..per_side {
+                        columns.push((x, false));
+                        x += drill.width;
+                    }
+                }
+            }
+        }
+        let mut placed: HashSet<Pos> = HashSet::new();
+        for ty in (top..=bottom).step_by((drill.height * 2 + 1) as usize) {
+            let belt_y = ty + drill.height;
+            // (west end, east end) of the belt and whether a drill puts anything on it
+            let mut segment: Option<(i32, i32, bool)> = None;
+            let mut segments: Vec<(i32, i32, bool)> = vec![];
+            for (tx, is_pole) in &columns {
+                let tx = *tx;
+                let width = if *is_pole { 1 } else { drill.width };
+                if !(tx..tx + width).all(|x| is_free(&Pos(x, belt_y))) {
+                    segments.extend(segment.take());
+                    continue;
+                }
+                let (west, mut east, mut used) = segment.unwrap_or_else(|| {
+                    let west = if is_free(&Pos(tx - 1, belt_y)) {
+                        tx - 1
+                    } else {
+                        tx
+                    };
+                    (west, west, false)
+                });
+                if *is_pole {
+                    segment = Some((west, east, used));
+                    continue;
+                }
+                for (drill_y, direction) in
+                    &[(ty, Direction::South), (belt_y + 1, Direction::North)]
+                {
+                    let tile = Pos(tx, *drill_y);
+                    if can_mine(&tile) {
+                        placed.insert(tile.clone());
+                        entities.push(drill.entity(&tile, *direction));
+                        east = east.max(drill.drop_tile(&tile, *direction).0);
+                        used = true;
+                        drills += 1;
+                    }
+                }
+                segment = Some((west, east, used));
+            }
+            segments.extend(segment.take());
+            for (west, east, used) in segments {
+                if !used {
+                    continue;
+                }
+                for x in west..=east {
+                    entities.push(FactorioEntity::new_transport_belt(
+                        &tile_center(&Pos(x, belt_y)),
+                        Direction::West,
+                    ));
+                }
+                outputs.push(tile_center(&Pos(west, belt_y)));
+            }
+            for (px, _) in columns.iter().filter(|(_, is_pole)| *is_pole) {
+                for drill_y in &[ty, belt_y + 1] {
+                    let pole_tile = Pos(*px, drill_y + drill.height / 2);
+                    if (placed.contains(&Pos(px - drill.width, *drill_y))
+                        || placed.contains(&Pos(px + 1, *drill_y)))
+                        && is_free(&pole_tile)
+                    {
+                        entities.push(FactorioEntity::new_small_electric_pole(&tile_center(
+                            &pole_tile,
+                        )));
+                    }
+                }
+            }
+        }
+    } else {
+        for ty in (top..=bottom).step_by((drill.height + 1) as usize) {
+            for tx in (left..=right).step_by(drill.width as usize) {
+                let tile = Pos(tx, ty);
+                let chest = drill.drop_tile(&tile, Direction::North);
+                if can_mine(&tile) && is_free(&chest) {
+                    entities.push(drill.entity(&tile, Direction::North));
+                    let position = tile_center(&chest);
+                    entities.push(FactorioEntity {
+                        name: CHEST.into(),
+                        entity_type: EntityType::Container.to_string(),
+                        bounding_box: add_to_rect(&Rect::from_wh(0.7, 0.7), &position),
+                        position,
+                        ..Default::default()
+                    });
+                    drills += 1;
+                }
+            }
+        }
+    }
+    if drills == 0 {
+        bail!(
+            "no room for a single {} on the {} patch",
+            drill_name,
+            patch.name
+        );
+    }
+
+    let mut cost: BTreeMap<String, u32> = BTreeMap::new();
+    for entity in &entities {
+        *cost.entry(entity.name.clone()).or_insert(0) += 1;
+    }
+    Ok(MiningOutpost {
+        resource_name: patch.name.clone(),
+        drill_name: drill_name.into(),
+        drills,
+        entities,
+        outputs,
+        cost,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::factorio::util::calculate_distance;
+    use crate::types::EntityName;
+
+    use super::*;
+
+    #[test]
+    fn test_mining_outpost() {
+        let world = fixture_world();
+        let patch = world
+            .entity_graph
+            .resource_patches(&EntityName::IronOre.to_string())
+            .remove(0);
+        let outpost = plan_mining_outpost(
+            &world.entity_graph,
+            &patch,
+            &EntityName::ElectricMiningDrill.to_string(),
+        )
+        .unwrap();
+        assert!(outpost.drills >= 8);
+        assert_eq!(
+            outpost.cost[&EntityName::ElectricMiningDrill.to_string()],
+            outpost.drills
+        );
+        assert!(!outpost.outputs.is_empty());
+        let tiles: HashSet<Pos> = outpost
+            .entities
+            .iter()
+            .flat_map(|entity| {
+                let rect = &entity.bounding_box;
+                let mut tiles: Vec<Pos> = vec![];
+                for y in rect.left_top.y().floor() as i32..rect.right_bottom.y().ceil() as i32 {
+                    for x in rect.left_top.x().floor() as i32..rect.right_bottom.x().ceil() as i32 {
+                        tiles.push(Pos(x, y));
+                    }
+                }
+                tiles
+            })
+            .collect();
+        let poles: Vec<&FactorioEntity> = outpost
+            .entities
+            .iter()
+            .filter(|e| e.name == EntityName::SmallElectricPole.to_string())
+            .collect();
+        assert_eq!(
+            outpost.cost[&EntityName::SmallElectricPole.to_string()] as usize,
+            poles.len()
+        );
+        let footprint = outpost.drills as usize * 9
+            + outpost.cost[&EntityName::TransportBelt.to_string()] as usize
+            + poles.len();
+        assert_eq!(tiles.len(), footprint);
+        // every drill drops onto a belt
+        for drill in outpost
+            .entities
+            .iter()
+            .filter(|e| e.name == outpost.drill_name)
+        {
+            let drop = drill.drop_position.as_ref().unwrap();
+            assert!(outpost.entities.iter().any(|e| {
+                e.name == EntityName::TransportBelt.to_string()
+                    && calculate_distance(&e.position, drop) < 0.1
+            }));
+            // and sits in the supply area of a pole
+            assert!(poles.iter().any(|pole| {
+                let area = add_to_rect(&Rect::from_wh(5., 5.), &pole.position);
+                area.intersects(&drill.bounding_box)
+            }));
+        }
+
+        let burner = plan_mining_outpost(
+            &world.entity_graph,
+            &patch,
+            &EntityName::BurnerMiningDrill.to_string(),
+        )
+        .unwrap();
+        assert_eq!(burner.cost[CHEST], burner.drills);
+        assert!(burner.outputs.is_empty());
+        assert!(plan_mining_outpost(&world.entity_graph, &patch, "pipe").is_err());
+    }
+}
@@ -8,9 +8,10 @@ use crate::factorio::ws::{
     PlayerDistanceChangedMessage, PlayerLeftMessage, ResearchCompletedMessage,
 };
 use crate::types::{
-    ChunkPosition, FactorioEntity, FactorioEntityPrototype, FactorioForce, FactorioGraphic,
-    FactorioItemPrototype, FactorioRecipe, FactorioTile, PlayerChangedDistanceEvent,
-    PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, Pos, Position, Rect,
+    ChunkPollution, ChunkPosition, FactorioEntity, FactorioEntityPrototype, FactorioForce,
+    FactorioGraphic, FactorioGroundItem, FactorioItemPrototype, FactorioRecipe, FactorioTile,
+    InventoryResponse, PlayerChangedDistanceEvent, PlayerChangedMainInventoryEvent,
+    PlayerChangedPositionEvent, Pos, Position, Rect, ResearchProgressEvent,
 };
 
 pub struct OutputParser {
@@ -181,10 +182,25 @@ impl OutputParser {
                 }
             }
             "on_research_finished" => {
+                let space_pos = rest
+                    .find(' ')
+                    .ok_or_else(|| anyhow!("invalid research finished: {}", rest))?;
+                let force_name = &rest[0..space_pos];
+                let technology = &rest[space_pos + 1..];
+                self.world.research_finished(force_name, technology)?;
                 if let Some(websocket_server) = self.websocket_server.as_ref() {
-                    websocket_server.send(ResearchCompletedMessage {}).await?;
+                    websocket_server
+                        .send(ResearchCompletedMessage {
+                            force_name: force_name.into(),
+                            technology: technology.into(),
+                        })
+                        .await?;
                 }
             }
+            "research_progress" => {
+                let event: ResearchProgressEvent = serde_json::from_str(rest)?;
+                self.world.update_research_progress(event)?;
+            }
             "force" => {
                 let force: FactorioForce = serde_json::from_str(rest).unwrap_or_else(|err| {
                     panic!("failed to deserialize force: {:?} '{}'", err, rest)
@@ -209,6 +225,51 @@ impl OutputParser {
                 });
                 self.world.on_some_entity_deleted(entity)?;
             }
+            "on_entity_inventory_changed" => {
+                let inventory: InventoryResponse =
+                    serde_json::from_str(rest).unwrap_or_else(|err| {
+                        panic!("failed to deserialize inventory: {:?} '{}'", err, rest)
+                    });
+                if let Err(err) = self.world.on_entity_inventory_changed(inventory) {
+                    warn!("<red>failed to update inventory</>: {:?}", err);
+                }
+            }
+            "ground_items" => {
+                let colon_pos = rest
+                    .find(':')
+                    .ok_or_else(|| anyhow!("invalid ground items: {}", rest))?;
+                let rect: Rect = rest[0..colon_pos].parse()?;
+                let mut items = &rest[colon_pos + 1..];
+                if items == "{}" {
+                    items = "[]"
+                }
+                let items: Vec<FactorioGroundItem> = serde_json::from_str(items)?;
+                self.world.update_ground_items(rect, items)?;
+            }
+            "enemy_units" => {
+                let colon_pos = rest
+                    .find(':')
+                    .ok_or_else(|| anyhow!("invalid enemy units: {}", rest))?;
+                let rect: Rect = rest[0..colon_pos].parse()?;
+                let mut units = &rest[colon_pos + 1..];
+                if units == "{}" {
+                    units = "[]"
+                }
+                let units: Vec<FactorioEntity> = serde_json::from_str(units)?;
+                self.world.update_enemy_units(rect, units)?;
+            }
+            "pollution" => {
+                let colon_pos = rest
+                    .find(':')
+                    .ok_or_else(|| anyhow!("invalid pollution: {}", rest))?;
+                let rect: Rect = rest[0..colon_pos].parse()?;
+                let mut chunks = &rest[colon_pos + 1..];
+                if chunks == "{}" {
+                    chunks = "[]"
+                }
+                let chunks: Vec<ChunkPollution> = serde_json::from_str(chunks)?;
+                self.world.update_pollution(rect, chunks)?;
+            }
             "on_player_main_inventory_changed" => {
                 let event: PlayerChangedMainInventoryEvent = serde_json::from_str(rest)?;
                 let player_id = event.player_id;
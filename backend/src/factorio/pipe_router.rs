@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+
+use dashmap::DashMap;
+use num_traits::{FromPrimitive, ToPrimitive};
+use pathfinding::prelude::astar;
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::{add_to_rect, move_pos, span_rect, tile_center};
+use crate::types::{
+    Direction, EntityType, FactorioEntity, FactorioEntityPrototype, Pos, Position, Rect,
+};
+
+pub const PIPE: &str = "pipe";
+pub const PIPE_TO_GROUND: &str = "pipe-to-ground";
+pub const OFFSHORE_PUMP: &str = "offshore-pump";
+
+const PIPE_COST: u32 = 10;
+const PIPE_TO_GROUND_COST: u32 = 40;
+// pipe-to-ground reports its range on the fluid box connection, not the prototype
+const DEFAULT_PIPE_TO_GROUND_MAX: i32 = 10;
+// detour room around the two ends of a pipe
+const SEARCH_MARGIN: f64 = 20.;
+// direction of the start tile, pipes connect to all sides so the first step may go anywhere
+const NO_DIRECTION: u8 = 8;
+
+/// a tile next to an entity its fluid box connects to
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluidboxConnection {
+    pub fluidbox: usize,
+    pub target: Pos,
+    pub max_underground_distance: Option<u32>,
+}
+
+/// the tiles the fluid boxes of an entity connect to, the connection positions of a prototype
+/// are listed once per direction: north, east, south, west
+pub fn fluidbox_connections(
+    entity_prototypes: &DashMap<String, FactorioEntityPrototype>,
+    entity: &FactorioEntity,
+) -> Vec<FluidboxConnection> {
+    let mut connections: Vec<FluidboxConnection> = vec![];
+    if let Some(prototype) = entity_prototypes.get(&entity.name) {
+        if let Some(fluidboxes) = &*prototype.fluidbox_prototypes {
+            for (index, fluidbox) in fluidboxes.iter().enumerate() {
+                for connection in (*fluidbox.pipe_connections).iter().flatten() {
+                    if let Some(offset) = connection.positions.get(entity.direction as usize / 2) {
+                        connections.push(FluidboxConnection {
+                            fluidbox: index,
+                            target: (&entity.position.add(offset)).into(),
+                            max_underground_distance: connection.max_underground_distance,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    connections
+}
+
+/// pipes from a fluid box of one entity to a fluid box of another, trying every connection of both.
+/// entities may be planned and not yet in the world.
+///
+/// no pipe may end up next to a fluid box of any other system, including the other fluid boxes
+/// of both ends like the steam output of a boiler, or the fluids would mix. pipe-to-ground
+/// crosses obstacles and foreign pipes but only enters straight in and leaves straight out
+pub fn route_pipe(
+    entity_graph: &EntityGraph,
+    from: &FactorioEntity,
+    from_fluidbox: usize,
+    to: &FactorioEntity,
+    to_fluidbox: usize,
+) -> anyhow::Result<Vec<FactorioEntity>> {
+    let prototypes = entity_graph.entity_prototypes();
+    let from_connections = fluidbox_connections(&prototypes, from);
+    let to_connections = fluidbox_connections(&prototypes, to);
+    let area = span_rect(&from.position, &to.position, SEARCH_MARGIN);
+    let mut foreign: HashSet<Pos> = HashSet::new();
+    for (entity, _, _) in entity_graph.inner_tree().query(area.clone().into()) {
+        let is_end =
+            |end: &FactorioEntity| entity.name == end.name && entity.position == end.position;
+        if is_end(from) || is_end(to) {
+            continue;
+        }
+        // the ground side of pipe-to-ground only connects to its partner
+        for connection in fluidbox_connections(&prototypes, entity) {
+            if connection.max_underground_distance.is_none() {
+                foreign.insert(connection.target);
+            }
+        }
+    }
+    let underground_max = fluidbox_connections(
+        &prototypes,
+        &FactorioEntity {
+            name: PIPE_TO_GROUND.into(),
+            ..Default::default()
+        },
+    )
+    .iter()
+    .find_map(|connection| connection.max_underground_distance)
+    .map(|distance| distance as i32)
+    .unwrap_or(DEFAULT_PIPE_TO_GROUND_MAX);
+
+    let mut best: Option<(Vec<(Pos, u8)>, u32)> = None;
+    for start in from_connections
+        .iter()
+        .filter(|connection| connection.fluidbox == from_fluidbox)
+    {
+        for goal in to_connections
+            .iter()
+            .filter(|connection| connection.fluidbox == to_fluidbox)
+        {
+            // every other connection of both ends belongs to another system or is not ours to use
+            let mut forbidden = foreign.clone();
+            for connection in from_connections.iter().chain(to_connections.iter()) {
+                if connection != start
+                    && connection != goal
+                    && connection.max_underground_distance.is_none()
+                {
+                    forbidden.insert(connection.target.clone());
+                }
+            }
+            if let Some((path, cost)) = search(
+                entity_graph,
+                &area,
+                &[&from.bounding_box, &to.bounding_box],
+                &forbidden,
+                &start.target,
+                &goal.target,
+                underground_max,
+            ) {
+                if best
+                    .as_ref()
+                    .map(|(_, best_cost)| cost < *best_cost)
+                    .unwrap_or(true)
+                {
+                    best = Some((path, cost));
+                }
+            }
+        }
+    }
+    let (path, _) = best.ok_or_else(|| anyhow!("no pipe route found"))?;
+
+    let mut result: Vec<FactorioEntity> = vec![];
+    for i in 0..path.len() {
+        let (pos, _) = &path[i];
+        let entity = match (path.get(i + 1), i.checked_sub(1).map(|i| &path[i])) {
+            (Some((next, direction)), _) if pos.distance(next) > 1 => pipe_entity(
+                &prototypes,
+                PIPE_TO_GROUND,
+                pos,
+                Direction::from_u8(*direction).unwrap().opposite(),
+            ),
+            (_, Some((last, _))) if pos.distance(last) > 1 => pipe_entity(
+                &prototypes,
+                PIPE_TO_GROUND,
+                pos,
+                Direction::from_u8(path[i].1).unwrap(),
+            ),
+            _ => pipe_entity(&prototypes, PIPE, pos, Direction::North),
+        };
+        result.push(entity);
+    }
+    Ok(result)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search(
+    entity_graph: &EntityGraph,
+    area: &Rect,
+    ends: &[&Rect],
+    forbidden: &HashSet<Pos>,
+    start: &Pos,
+    goal: &Pos,
+    underground_max: i32,
+) -> Option<(Vec<(Pos, u8)>, u32)> {
+    let blocked_tree = entity_graph.blocked_tree();
+    let ends: Vec<QuadTreeRect> = ends.iter().map(|rect| (*rect).clone().into()).collect();
+    let is_free = |pos: &Pos| {
+        let center = tile_center(pos);
+        let rect: QuadTreeRect = add_to_rect(&Rect::from_wh(0.5, 0.5), &center).into();
+        area.contains(&center)
+            && blocked_tree.query(rect).is_empty()
+            && !ends.iter().any(|end| end.intersects(&rect))
+    };
+    let is_pipe_tile = |pos: &Pos| is_free(pos) && !forbidden.contains(pos);
+    if !is_pipe_tile(start) || !is_pipe_tile(goal) {
+        return None;
+    }
+    astar(
+        // state is (tile, direction we came from, whether the tile is a pipe-to-ground output)
+        &(start.clone(), NO_DIRECTION, false),
+        |(pos, direction, is_output)| {
+            let mut successors: Vec<((Pos, u8, bool), u32)> = vec![];
+            for next_direction in Direction::orthogonal() {
+                let next_u8 = next_direction.to_u8().unwrap();
+                if *direction != NO_DIRECTION {
+                    // outputs only connect straight ahead
+                    if next_u8 == (*direction + 4) % 8 || (*is_output && next_u8 != *direction) {
+                        continue;
+                    }
+                }
+                let next = move_pos(pos, next_direction, 1);
+                if is_pipe_tile(&next) {
+                    successors.push(((next, next_u8, false), PIPE_COST));
+                }
+            }
+            // the input has to be reached straight, its ground side faces backwards
+            if *direction != NO_DIRECTION && !*is_output {
+                let direction = Direction::from_u8(*direction).unwrap();
+                for length in 2..=underground_max {
+                    let exit = move_pos(pos, direction, length);
+                    if is_pipe_tile(&exit) {
+                        successors.push((
+                            (exit, direction.to_u8().unwrap(), true),
+                            PIPE_TO_GROUND_COST,
+                        ));
+                    }
+                }
+            }
+            successors
+        },
+        |(pos, _, _)| {
+            pos.distance(goal) * (PIPE_TO_GROUND_COST / underground_max as u32).min(PIPE_COST)
+        },
+        // an output only connects forward, the goal entity is beside the last tile
+        |(pos, _, is_output)| pos == goal && !*is_output,
+    )
+    .map(|(path, cost)| {
+        (
+            path.into_iter()
+                .map(|(pos, direction, _)| (pos, direction))
+                .collect(),
+            cost,
+        )
+    })
+}
+
+fn pipe_entity(
+    entity_prototypes: &DashMap<String, FactorioEntityPrototype>,
+    name: &str,
+    pos: &Pos,
+    direction: Direction,
+) -> FactorioEntity {
+    let position = tile_center(pos);
+    let collision_box = entity_prototypes
+        .get(name)
+        .map(|prototype| prototype.collision_box.clone())
+        .unwrap_or_else(|| Rect::from_wh(0.6, 0.6));
+    FactorioEntity {
+        name: name.into(),
+        entity_type: if name == PIPE_TO_GROUND {
+            EntityType::PipeToGround.to_string()
+        } else {
+            EntityType::Pipe.to_string()
+        },
+        bounding_box: add_to_rect(&turned_rect(&collision_box, direction), &position),
+        position,
+        direction: direction.to_u8().unwrap(),
+        ..Default::default()
+    }
+}
+
+/// offshore pump on a water tile found by find_offshore_pump_placement_options,
+/// its output connects to the land tile in pump_direction
+pub fn offshore_pump_at(
+    entity_prototypes: &DashMap<String, FactorioEntityPrototype>,
+    water: &Pos,
+    pump_direction: Direction,
+) -> FactorioEntity {
+    let position = tile_center(water);
+    let direction = pump_direction.opposite();
+    let collision_box = entity_prototypes
+        .get(OFFSHORE_PUMP)
+        .map(|prototype| prototype.collision_box.clone())
+        .unwrap_or_else(|| Rect::from_wh(0.8, 0.8));
+    FactorioEntity {
+        name: OFFSHORE_PUMP.into(),
+        entity_type: EntityType::OffshorePump.to_string(),
+        bounding_box: add_to_rect(&turned_rect(&collision_box, direction), &position),
+        position,
+        direction: direction.to_u8().unwrap(),
+        ..Default::default()
+    }
+}
+
+/// unlike add_to_rect_turned this also turns boxes which are not centered, like the offshore pump's
+fn turned_rect(rect: &Rect, direction: Direction) -> Rect {
+    let a = rect.left_top.turn(direction);
+    let b = rect.right_bottom.turn(direction);
+    Rect::new(
+        &Position::new(a.x().min(b.x()), a.y().min(b.y())),
+        &Position::new(a.x().max(b.x()), a.y().max(b.y())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::factorio::util::add_to_rect_turned;
+
+    use super::*;
+
+    #[test]
+    fn test_route_pipe() {
+        let world = fixture_world();
+        let prototypes = world.entity_graph.entity_prototypes();
+        // a pipe of some other fluid from 35,25 to 35,36 between pump and boiler
+        let foreign: Vec<FactorioEntity> = (25..=36)
+            .map(|y| pipe_entity(&prototypes, PIPE, &Pos(35, y), Direction::North))
+            .collect();
+        world.entity_graph.add(foreign, None).unwrap();
+
+        // the water from 38,38 to 42,42 has land to the north
+        let pump = offshore_pump_at(&prototypes, &Pos(40, 38), Direction::North);
+        assert_eq!(
+            fluidbox_connections(&prototypes, &pump)[0].target,
+            Pos(40, 37)
+        );
+        let position = Position::new(30.5, 30.);
+        let boiler = FactorioEntity {
+            name: "boiler".into(),
+            entity_type: EntityType::Boiler.to_string(),
+            bounding_box: add_to_rect_turned(
+                &prototypes.get("boiler").unwrap().collision_box,
+                &position,
+                Direction::North,
+            ),
+            position,
+            direction: 0,
+            ..Default::default()
+        };
+        let pipes = route_pipe(&world.entity_graph, &pump, 0, &boiler, 0).unwrap();
+        let positions: Vec<Pos> = pipes.iter().map(|e| (&e.position).into()).collect();
+        assert_eq!(positions[0], Pos(40, 37));
+        assert_eq!(*positions.last().unwrap(), Pos(32, 30));
+        // the foreign pipe is crossed underground without touching it
+        let undergrounds: Vec<&FactorioEntity> =
+            pipes.iter().filter(|e| e.name == PIPE_TO_GROUND).collect();
+        assert!(!undergrounds.is_empty() && undergrounds.len() % 2 == 0);
+        for pos in &positions {
+            assert!(pos.0 < 34 || pos.0 > 36);
+            // the steam output of the boiler
+            assert_ne!(*pos, Pos(30, 28));
+        }
+    }
+}
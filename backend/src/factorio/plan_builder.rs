@@ -1,15 +1,28 @@
-use crate::factorio::task_graph::{MineTarget, PositionRadius, TaskGraph};
-use crate::factorio::util::calculate_distance;
+use crate::factorio::rail_graph::rail_path;
+use crate::factorio::task_graph::{
+    InventoryItem, InventoryLocation, MineTarget, PositionRadius, TaskGraph,
+};
+use crate::factorio::tech_tree::ResearchPlan;
+use crate::factorio::tour::split_tours;
+use crate::factorio::util::{add_to_rect_turned, calculate_distance};
 use crate::factorio::world::FactorioWorld;
+use crate::num_traits::FromPrimitive;
 use crate::types::{
-    FactorioEntity, FactorioPlayer, PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent,
-    Position,
+    CircuitConnector, CombinatorSettings, Direction, EntityName, EntityType, FactorioEntity,
+    FactorioPlayer, PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, Position, Rect,
+    TrainScheduleRecord,
 };
 use dashmap::lock::RwLock;
 use num_traits::ToPrimitive;
 use rlua::{Context, Table};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+// defines.inventory values used by the BotBridge mod
+const INVENTORY_FUEL: u32 = 1;
+const INVENTORY_CHEST: u32 = 1;
+const INVENTORY_RESULT: u32 = 3;
+
 pub struct PlanBuilder {
     graph: Arc<RwLock<TaskGraph>>,
     world: Arc<FactorioWorld>,
@@ -27,11 +40,16 @@ impl PlanBuilder {
         name: &str,
         count: u32,
     ) -> anyhow::Result<()> {
+        let reach_distance = self.player(player_id).resource_reach_distance as f64;
+        let walk_distance =
+            if calculate_distance(&self.player(player_id).position, &position) > reach_distance {
+                Some(self.distance(player_id, &position, reach_distance))
+            } else {
+                None
+            };
         let mut graph = self.graph.write();
         let player = self.world.players.get(&player_id).unwrap();
-        let distance = calculate_distance(&player.position, &position).ceil();
-        let reach_distance = player.resource_reach_distance as f64;
-        if distance > reach_distance {
+        if let Some(distance) = walk_distance {
             graph.add_walk_node(
                 player_id,
                 distance,
@@ -73,12 +91,97 @@ impl PlanBuilder {
         Ok(())
     }
 
-    fn distance(&self, player_id: u32, position: &Position) -> f64 {
-        calculate_distance(
-            &self.world.players.get(&player_id).unwrap().position,
-            position,
-        )
-        .ceil()
+    /// mines all targets, split into one tour per bot ordered to keep the walking short
+    pub fn mine_all(&self, player_ids: &[u32], targets: &[MineTarget]) -> anyhow::Result<()> {
+        if player_ids.is_empty() {
+            bail!("no bots to mine with");
+        }
+        let starts: Vec<Position> = player_ids
+            .iter()
+            .map(|player_id| self.player(*player_id).position)
+            .collect();
+        let positions: Vec<Position> = targets
+            .iter()
+            .map(|target| target.position.clone())
+            .collect();
+        for (player_id, tour) in player_ids.iter().zip(split_tours(&starts, &positions)) {
+            let reach_distance = self.player(*player_id).resource_reach_distance as f64;
+            for index in tour {
+                let target = &targets[index];
+                if calculate_distance(&self.player(*player_id).position, &target.position)
+                    > reach_distance
+                {
+                    self.add_walk(
+                        *player_id,
+                        PositionRadius::from_position(&target.position, reach_distance),
+                    )?;
+                }
+                self.mine(
+                    *player_id,
+                    target.position.clone(),
+                    &target.name,
+                    target.count,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// places all entities, split into one tour per bot ordered to keep the walking short.
+    /// every bot needs the items for its share in its inventory
+    pub fn place_all(&self, player_ids: &[u32], entities: &[FactorioEntity]) -> anyhow::Result<()> {
+        if player_ids.is_empty() {
+            bail!("no bots to build with");
+        }
+        let starts: Vec<Position> = player_ids
+            .iter()
+            .map(|player_id| self.player(*player_id).position)
+            .collect();
+        let positions: Vec<Position> = entities
+            .iter()
+            .map(|entity| entity.position.clone())
+            .collect();
+        for (player_id, tour) in player_ids.iter().zip(split_tours(&starts, &positions)) {
+            for index in tour {
+                let entity = &entities[index];
+                let (item_name, item_count) = self.world.item_to_place(&entity.name);
+                self.add_place_with_item(*player_id, entity.clone(), &item_name, item_count)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// mines all trees and rocks intersecting the area with `mine_all`, mined items go into the
+    /// simulated inventories. cliffs need explosives and are returned untouched
+    pub fn clear_area(
+        &self,
+        player_ids: &[u32],
+        area: &Rect,
+    ) -> anyhow::Result<Vec<FactorioEntity>> {
+        let obstacles = self.world.entity_graph.obstacles_in(area);
+        let cliffs = self.world.entity_graph.cliffs_in(area);
+        let targets: Vec<MineTarget> = obstacles
+            .iter()
+            .map(|obstacle| MineTarget {
+                position: obstacle.position.clone(),
+                name: obstacle.name.clone(),
+                count: 1,
+            })
+            .collect();
+        self.mine_all(player_ids, &targets)?;
+        for obstacle in obstacles {
+            self.world.on_some_entity_deleted(obstacle)?;
+        }
+        Ok(cliffs)
+    }
+
+    /// walking distance until the player is within radius of position
+    fn distance(&self, player_id: u32, position: &Position, radius: f64) -> f64 {
+        let start = self.player(player_id).position;
+        self.world
+            .walk_pathfinder
+            .walk_distance(&start, position, radius)
+            .ceil()
     }
 
     fn player(&self, player_id: u32) -> FactorioPlayer {
@@ -97,7 +200,7 @@ impl PlanBuilder {
     // }
 
     pub fn add_walk(&self, player_id: u32, goal: PositionRadius) -> anyhow::Result<()> {
-        let distance = self.distance(player_id, &goal.position);
+        let distance = self.distance(player_id, &goal.position, goal.radius);
         let mut graph = self.graph.write();
         self.world
             .player_changed_position(PlayerChangedPositionEvent {
@@ -108,7 +211,19 @@ impl PlanBuilder {
         Ok(())
     }
 
-    pub fn add_place(&mut self, player_id: u32, entity: FactorioEntity) -> anyhow::Result<()> {
+    pub fn add_place(&self, player_id: u32, entity: FactorioEntity) -> anyhow::Result<()> {
+        let item_name = entity.name.clone();
+        self.add_place_with_item(player_id, entity, &item_name, 1)
+    }
+
+    /// places an entity which is built from some other item, like rails
+    fn add_place_with_item(
+        &self,
+        player_id: u32,
+        entity: FactorioEntity,
+        item_name: &str,
+        item_count: u32,
+    ) -> anyhow::Result<()> {
         let player = self.player(player_id);
         let distance = calculate_distance(&player.position, &entity.position);
         let build_distance = player.build_distance as f64;
@@ -119,17 +234,30 @@ impl PlanBuilder {
             )?;
         }
         let mut inventory = *self.player(player_id).main_inventory;
-        let inventory_item_count = *inventory.get(&entity.name).unwrap_or(&0);
-        if inventory_item_count < 1 {
+        let inventory_item_count = *inventory.get(item_name).unwrap_or(&0);
+        if inventory_item_count < item_count {
             return Err(anyhow!(
-                "player #{} does not have {} in inventory",
+                "player #{} does not have {}x {} in inventory",
                 player_id,
-                &entity.name
+                item_count,
+                item_name
             ));
         }
+        let is_electric = self
+            .world
+            .entity_prototypes
+            .get(&entity.name)
+            .map(|prototype| prototype.electric_energy_usage.is_some())
+            .unwrap_or(false);
+        if is_electric && !self.world.entity_graph.is_powered(&entity) {
+            warn!(
+                "<yellow>{} at {} will not be powered</>",
+                entity.name, entity.position
+            );
+        }
         let mut graph = self.graph.write();
         graph.add_place_node(player_id, 1., entity.clone());
-        inventory.insert(entity.name.clone(), inventory_item_count - 1);
+        inventory.insert(item_name.into(), inventory_item_count - item_count);
         self.world
             .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
                 player_id,
@@ -139,6 +267,336 @@ impl PlanBuilder {
         Ok(())
     }
 
+    pub fn refuel(
+        &self,
+        player_id: u32,
+        position: Position,
+        fuel_name: &str,
+        count: u32,
+    ) -> anyhow::Result<()> {
+        let mut target = match self.world.entity_graph.inventory_at(&position) {
+            Some(inventory) => inventory,
+            None => return Err(anyhow!("no entity to refuel at {}", position)),
+        };
+        if target.fuel_inventory.is_none() {
+            return Err(anyhow!(
+                "{} at {} does not burn fuel",
+                target.name,
+                position
+            ));
+        }
+        let player = self.player(player_id);
+        let reach_distance = player.reach_distance as f64;
+        if calculate_distance(&player.position, &position) > reach_distance {
+            self.add_walk(
+                player_id,
+                PositionRadius::from_position(&position, reach_distance),
+            )?;
+        }
+        let mut inventory = *self.player(player_id).main_inventory;
+        let inventory_item_count = *inventory.get(fuel_name).unwrap_or(&0);
+        if inventory_item_count < count {
+            return Err(anyhow!(
+                "player #{} does not have {}x {} in inventory",
+                player_id,
+                count,
+                fuel_name
+            ));
+        }
+        let mut graph = self.graph.write();
+        graph.add_insert_to_inventory_node(
+            player_id,
+            1.,
+            InventoryLocation {
+                entity_name: target.name.clone(),
+                position: position.clone(),
+                inventory_type: INVENTORY_FUEL,
+            },
+            InventoryItem::new(fuel_name, count),
+        );
+        drop(graph);
+        inventory.insert(fuel_name.into(), inventory_item_count - count);
+        self.world
+            .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
+                player_id,
+                main_inventory: Box::new(inventory),
+            })?;
+        let mut fuel = (*target.fuel_inventory).clone().unwrap_or_default();
+        *fuel.entry(fuel_name.into()).or_insert(0) += count;
+        target.fuel_inventory = Box::new(Some(fuel));
+        self.world.on_entity_inventory_changed(target)?;
+        Ok(())
+    }
+
+    pub fn collect(&self, player_id: u32, position: Position) -> anyhow::Result<()> {
+        let mut target = match self.world.entity_graph.inventory_at(&position) {
+            Some(inventory) => inventory,
+            None => return Err(anyhow!("no entity to collect from at {}", position)),
+        };
+        let output = match target.output_inventory.as_ref() {
+            Some(output) => output.clone(),
+            None => return Err(anyhow!("{} at {} has no output", target.name, position)),
+        };
+        let player = self.player(player_id);
+        let reach_distance = player.reach_distance as f64;
+        if calculate_distance(&player.position, &position) > reach_distance {
+            self.add_walk(
+                player_id,
+                PositionRadius::from_position(&position, reach_distance),
+            )?;
+        }
+        let inventory_type = match self.world.entity_graph.node_at(&position) {
+            Some(node_index) => match self.world.entity_graph.node_weight(node_index) {
+                Some(node)
+                    if node.entity_type == EntityType::Container
+                        || node.entity_type == EntityType::LogisticContainer =>
+                {
+                    INVENTORY_CHEST
+                }
+                _ => INVENTORY_RESULT,
+            },
+            None => INVENTORY_RESULT,
+        };
+        let mut inventory = *self.player(player_id).main_inventory;
+        let mut graph = self.graph.write();
+        for (name, count) in &output {
+            if *count == 0 {
+                continue;
+            }
+            graph.add_remove_from_inventory_node(
+                player_id,
+                1.,
+                InventoryLocation {
+                    entity_name: target.name.clone(),
+                    position: position.clone(),
+                    inventory_type,
+                },
+                InventoryItem::new(name, *count),
+            );
+            *inventory.entry(name.clone()).or_insert(0) += *count;
+        }
+        drop(graph);
+        self.world
+            .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
+                player_id,
+                main_inventory: Box::new(inventory),
+            })?;
+        target.output_inventory = Box::new(Some(BTreeMap::new()));
+        self.world.on_entity_inventory_changed(target)?;
+        Ok(())
+    }
+
+    /// crafts the science packs for every missing technology up to target from the player's
+    /// inventory, then researches them with those packs
+    pub fn research(&self, player_id: u32, target: &str) -> anyhow::Result<ResearchPlan> {
+        let force = self.player(player_id).force;
+        let plan = self.world.research_plan(&force, target)?;
+        let mut inventory = *self.player(player_id).main_inventory;
+        let mut crafts: Vec<(f64, InventoryItem)> = vec![];
+        for (name, count) in &plan.science_packs {
+            let count = *count as u32;
+            let recipe = match self.world.recipes.get(name) {
+                Some(recipe) => recipe.value().clone(),
+                None => bail!("no recipe for {}", name),
+            };
+            let amount = recipe
+                .products
+                .iter()
+                .find(|product| &product.name == name)
+                .map(|product| product.amount.max(1))
+                .unwrap_or(1);
+            let craft_count = (count + amount - 1) / amount;
+            for ingredient in recipe.ingredients.iter().flatten() {
+                let needed = ingredient.amount * craft_count;
+                let available = *inventory.get(&ingredient.name).unwrap_or(&0);
+                if available < needed {
+                    bail!(
+                        "player #{} does not have {}x {} in inventory to craft {}x {}",
+                        player_id,
+                        needed,
+                        ingredient.name,
+                        count,
+                        name
+                    );
+                }
+                inventory.insert(ingredient.name.clone(), available - needed);
+            }
+            // leftover packs of the last craft stay in the inventory
+            let leftover = craft_count * amount - count;
+            if leftover > 0 {
+                *inventory.entry(name.clone()).or_insert(0) += leftover;
+            }
+            crafts.push((
+                recipe.energy.to_f64().unwrap() * craft_count as f64,
+                InventoryItem::new(name, count),
+            ));
+        }
+        let mut graph = self.graph.write();
+        for (cost, item) in crafts {
+            graph.add_craft_node(player_id, cost, item);
+        }
+        for technology in &plan.order {
+            graph.add_research_node(player_id, technology);
+        }
+        drop(graph);
+        self.world
+            .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
+                player_id,
+                main_inventory: Box::new(inventory),
+            })?;
+        for technology in &plan.order {
+            self.world.research_finished(&force, technology)?;
+        }
+        Ok(plan)
+    }
+
+    pub fn place_combinator(
+        &self,
+        player_id: u32,
+        name: &str,
+        position: Position,
+        direction: u8,
+        settings: CombinatorSettings,
+    ) -> anyhow::Result<()> {
+        let prototype = match self.world.entity_prototypes.get(name) {
+            Some(prototype) => prototype.value().clone(),
+            None => return Err(anyhow!("prototype {} not found", name)),
+        };
+        let entity = FactorioEntity {
+            name: name.into(),
+            entity_type: prototype.entity_type,
+            bounding_box: add_to_rect_turned(
+                &prototype.collision_box,
+                &position,
+                Direction::from_u8(direction).unwrap_or(Direction::North),
+            ),
+            position,
+            direction,
+            combinator: Some(settings),
+            ..Default::default()
+        };
+        self.add_place(player_id, entity)
+    }
+
+    /// connects a red or green wire between two connection points, both entities must exist
+    pub fn connect_wire(
+        &self,
+        player_id: u32,
+        wire: &str,
+        from: (Position, u8),
+        to: (Position, u8),
+    ) -> anyhow::Result<()> {
+        let connector = |(position, circuit_id): (Position, u8)| match self
+            .world
+            .entity_graph
+            .entity_at(&position)
+            .and_then(|entity_id| self.world.entity_graph.entity_by_id(entity_id))
+        {
+            Some(entity) => Ok(CircuitConnector {
+                entity_name: entity.name,
+                position: entity.position,
+                circuit_id,
+            }),
+            None => Err(anyhow!("no entity to connect a wire to at {}", position)),
+        };
+        let from = connector(from)?;
+        let to = connector(to)?;
+        let wire_item = format!("{}-wire", wire);
+        let mut inventory = *self.player(player_id).main_inventory;
+        let inventory_item_count = *inventory.get(&wire_item).unwrap_or(&0);
+        if inventory_item_count < 1 {
+            return Err(anyhow!(
+                "player #{} does not have {} in inventory",
+                player_id,
+                wire_item
+            ));
+        }
+        let player = self.player(player_id);
+        let reach_distance = player.reach_distance as f64;
+        if calculate_distance(&player.position, &from.position) > reach_distance {
+            self.add_walk(
+                player_id,
+                PositionRadius::from_position(&from.position, reach_distance),
+            )?;
+        }
+        self.world
+            .circuit_graph
+            .connect(wire, from.clone(), to.clone())?;
+        let mut graph = self.graph.write();
+        graph.add_connect_wire_node(player_id, 1., wire, from, to);
+        drop(graph);
+        inventory.insert(wire_item, inventory_item_count - 1);
+        self.world
+            .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
+                player_id,
+                main_inventory: Box::new(inventory),
+            })?;
+        Ok(())
+    }
+
+    /// places rails from one point to another, see rail_path for the layout
+    pub fn place_rails(
+        &self,
+        player_id: u32,
+        from: &Position,
+        to: &Position,
+    ) -> anyhow::Result<Vec<FactorioEntity>> {
+        let rails = rail_path(from, to)?;
+        let rail_item = EntityName::Rail.to_string();
+        let rail_items = |rail: &FactorioEntity| {
+            if rail.name == EntityName::CurvedRail.to_string() {
+                4
+            } else {
+                1
+            }
+        };
+        let needed: u32 = rails.iter().map(rail_items).sum();
+        let available = *self
+            .player(player_id)
+            .main_inventory
+            .get(&rail_item)
+            .unwrap_or(&0);
+        if available < needed {
+            return Err(anyhow!(
+                "player #{} needs {}x {} but has {}",
+                player_id,
+                needed,
+                rail_item,
+                available
+            ));
+        }
+        for rail in &rails {
+            self.add_place_with_item(player_id, rail.clone(), &rail_item, rail_items(rail))?;
+        }
+        Ok(rails)
+    }
+
+    pub fn set_train_schedule(
+        &self,
+        player_id: u32,
+        locomotive: Position,
+        records: Vec<TrainScheduleRecord>,
+    ) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Err(anyhow!("train schedule needs at least one station"));
+        }
+        let stations = self.world.rail_graph.network().stations;
+        for record in &records {
+            if !stations
+                .iter()
+                .any(|station| station.name == record.station)
+            {
+                warn!(
+                    "<yellow>train station {} does not exist (yet)</>",
+                    record.station
+                );
+            }
+        }
+        let mut graph = self.graph.write();
+        graph.add_set_train_schedule_node(player_id, 1., locomotive, records);
+        Ok(())
+    }
+
     pub fn group_start(&self, label: &str) {
         let mut graph = self.graph.write();
         graph.group_start(label);
@@ -171,6 +629,169 @@ pub fn create_lua_plan_builder(
         )?,
     )?;
     let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "mineAll",
+        ctx.create_function(
+            move |_ctx, (player_ids, targets): (Vec<u32>, rlua::Value)| {
+                let targets: Vec<MineTarget> = rlua_serde::from_value(targets)?;
+                plan_builder
+                    .mine_all(&player_ids, &targets)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "placeAll",
+        ctx.create_function(
+            move |_ctx, (player_ids, entities): (Vec<u32>, rlua::Value)| {
+                let entities: Vec<FactorioEntity> = rlua_serde::from_value(entities)?;
+                plan_builder
+                    .place_all(&player_ids, &entities)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "clearArea",
+        ctx.create_function(move |ctx, (player_ids, area): (Vec<u32>, String)| {
+            let area: Rect = area
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            let cliffs = plan_builder
+                .clear_area(&player_ids, &area)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, cliffs))
+        })?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "refuel",
+        ctx.create_function(
+            move |_ctx, (player_id, position, fuel_name, count): (u32, String, String, u32)| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                plan_builder
+                    .refuel(player_id, position, fuel_name.as_str(), count)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "collect",
+        ctx.create_function(move |_ctx, (player_id, position): (u32, String)| {
+            let position: Position = position
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            plan_builder
+                .collect(player_id, position)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(())
+        })?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "research",
+        ctx.create_function(move |ctx, (player_id, target): (u32, String)| {
+            let plan = plan_builder
+                .research(player_id, target.as_str())
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, plan))
+        })?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "placeCombinator",
+        ctx.create_function(
+            move |_ctx,
+                  (player_id, name, position, direction, settings): (
+                u32,
+                String,
+                String,
+                u8,
+                rlua::Value,
+            )| {
+                let settings: CombinatorSettings = rlua_serde::from_value(settings)?;
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                plan_builder
+                    .place_combinator(player_id, &name, position, direction, settings)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "connectWire",
+        ctx.create_function(
+            move |_ctx,
+                  (player_id, wire, from, from_circuit_id, to, to_circuit_id): (
+                u32,
+                String,
+                String,
+                u8,
+                String,
+                u8,
+            )| {
+                let from: Position = from
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let to: Position = to
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                plan_builder
+                    .connect_wire(
+                        player_id,
+                        &wire,
+                        (from, from_circuit_id),
+                        (to, to_circuit_id),
+                    )
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "placeRails",
+        ctx.create_function(move |ctx, (player_id, from, to): (u32, String, String)| {
+            let from: Position = from
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            let to: Position = to
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            let rails = plan_builder
+                .place_rails(player_id, &from, &to)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, rails))
+        })?,
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "setTrainSchedule",
+        ctx.create_function(
+            move |_ctx, (player_id, locomotive, records): (u32, String, rlua::Value)| {
+                let locomotive: Position = locomotive
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let records: Vec<TrainScheduleRecord> = rlua_serde::from_value(records)?;
+                plan_builder
+                    .set_train_schedule(player_id, locomotive, records)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let plan_builder = _plan_builder.clone();
     map_table.set(
         "groupStart",
         ctx.create_function(move |_ctx, label: String| {
@@ -188,3 +809,167 @@ pub fn create_lua_plan_builder(
     )?;
     Ok(map_table)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::factorio::util::add_to_rect;
+    use crate::types::{FactorioForce, FactorioIngredient, FactorioTechnology};
+    use noisy_float::types::r64;
+
+    use super::*;
+
+    #[test]
+    fn test_place_all() {
+        let world = Arc::new(fixture_world());
+        let belt = EntityName::TransportBelt.to_string();
+        for (player_id, x) in &[(1, 28.), (2, -28.)] {
+            let mut inventory: BTreeMap<String, u32> = BTreeMap::new();
+            inventory.insert(belt.clone(), 4);
+            world.players.insert(
+                *player_id,
+                FactorioPlayer {
+                    player_id: *player_id,
+                    position: Position::new(*x, 20.),
+                    main_inventory: Box::new(inventory),
+                    ..Default::default()
+                },
+            );
+        }
+        let entities: Vec<FactorioEntity> = [-30.5, 30.5, -31.5, 31.5, -32.5, 32.5, -33.5, 33.5]
+            .iter()
+            .map(|x| FactorioEntity::new_transport_belt(&Position::new(*x, 20.5), Direction::East))
+            .collect();
+        let graph = Arc::new(RwLock::new(TaskGraph::new()));
+        graph.write().group_start("build");
+        let plan_builder = PlanBuilder::new(graph, world.clone());
+        plan_builder.place_all(&[1, 2], &entities).unwrap();
+        for player_id in &[1, 2] {
+            assert_eq!(
+                world
+                    .players
+                    .get(player_id)
+                    .unwrap()
+                    .main_inventory
+                    .get(&belt),
+                Some(&0)
+            );
+        }
+        assert!(plan_builder.place_all(&[1, 2], &entities).is_err());
+    }
+
+    #[test]
+    fn test_clear_area() {
+        let world = Arc::new(fixture_world());
+        world.players.insert(
+            1,
+            FactorioPlayer {
+                player_id: 1,
+                position: Position::new(-10., -10.),
+                ..Default::default()
+            },
+        );
+        let cliff_position = Position::new(-22., -22.);
+        world
+            .entity_graph
+            .add(
+                vec![FactorioEntity {
+                    name: "cliff".into(),
+                    entity_type: EntityType::Cliff.to_string(),
+                    bounding_box: add_to_rect(&Rect::from_wh(4., 2.), &cliff_position),
+                    position: cliff_position.clone(),
+                    ..Default::default()
+                }],
+                None,
+            )
+            .unwrap();
+        let area = add_to_rect(&Rect::from_wh(8., 8.), &Position::new(-20., -20.));
+        assert!(!world.entity_graph.obstacles_in(&area).is_empty());
+        let graph = Arc::new(RwLock::new(TaskGraph::new()));
+        graph.write().group_start("clear");
+        let plan_builder = PlanBuilder::new(graph.clone(), world.clone());
+        let cliffs = plan_builder.clear_area(&[1], &area).unwrap();
+        assert_eq!(cliffs.len(), 1);
+        assert_eq!(cliffs[0].position, cliff_position);
+        assert!(world.entity_graph.obstacles_in(&area).is_empty());
+        graph.write().group_end();
+        assert!(graph.read().graphviz_dot().contains("Mining tree"));
+    }
+
+    #[test]
+    fn test_research() {
+        let world = Arc::new(fixture_world());
+        let mut technologies: BTreeMap<String, FactorioTechnology> = BTreeMap::new();
+        technologies.insert(
+            "automation".into(),
+            FactorioTechnology {
+                name: "automation".into(),
+                enabled: true,
+                upgrade: false,
+                researched: false,
+                prerequisites: None,
+                research_unit_ingredients: vec![FactorioIngredient {
+                    name: "automation-science-pack".into(),
+                    ingredient_type: "item".into(),
+                    amount: 1,
+                }],
+                research_unit_count: 10,
+                research_unit_energy: Box::new(r64(10.)),
+                order: "a".into(),
+                level: 1,
+                valid: true,
+                unlocks_recipes: None,
+            },
+        );
+        world
+            .update_force(FactorioForce {
+                name: "player".into(),
+                force_id: 1,
+                current_research: None,
+                research_progress: None,
+                technologies: Box::new(technologies),
+                research_queue: None,
+            })
+            .unwrap();
+        let mut inventory: BTreeMap<String, u32> = BTreeMap::new();
+        inventory.insert(EntityName::CopperPlate.to_string(), 12);
+        inventory.insert("iron-gear-wheel".to_string(), 9);
+        world.players.insert(
+            1,
+            FactorioPlayer {
+                player_id: 1,
+                main_inventory: Box::new(inventory),
+                ..Default::default()
+            },
+        );
+        let graph = Arc::new(RwLock::new(TaskGraph::new()));
+        graph.write().group_start("research");
+        let plan_builder = PlanBuilder::new(graph, world.clone());
+        // one gear short of 10 packs
+        assert!(plan_builder.research(1, "automation").is_err());
+
+        let mut inventory = *world.players.get(&1).unwrap().main_inventory.clone();
+        assert_eq!(
+            inventory.get(&EntityName::CopperPlate.to_string()),
+            Some(&12)
+        );
+        inventory.insert("iron-gear-wheel".to_string(), 10);
+        world
+            .player_changed_main_inventory(PlayerChangedMainInventoryEvent {
+                player_id: 1,
+                main_inventory: Box::new(inventory),
+            })
+            .unwrap();
+        let plan = plan_builder.research(1, "automation").unwrap();
+        assert_eq!(plan.order, vec!["automation"]);
+        let player = world.players.get(&1).unwrap();
+        assert_eq!(
+            player
+                .main_inventory
+                .get(&EntityName::CopperPlate.to_string()),
+            Some(&2)
+        );
+        assert_eq!(player.main_inventory.get("iron-gear-wheel"), Some(&0));
+        assert_eq!(player.main_inventory.get("automation-science-pack"), None);
+    }
+}
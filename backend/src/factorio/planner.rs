@@ -23,6 +23,7 @@ pub struct Planner {
     real_world: Arc<FactorioWorld>,
     plan_world: Arc<FactorioWorld>,
     graph: Arc<RwLock<TaskGraph>>,
+    lua_score: Option<f64>,
 }
 
 impl Planner {
@@ -34,6 +35,7 @@ impl Planner {
             rcon,
             real_world: world,
             plan_world: Arc::new(plan_world),
+            lua_score: None,
         }
     }
 
@@ -42,18 +44,19 @@ impl Planner {
         plan_world.import(self.real_world.clone()).unwrap();
         self.plan_world = Arc::new(plan_world);
         self.graph = Arc::new(RwLock::new(TaskGraph::new()));
+        self.lua_score = None;
     }
 
     pub fn plan(&mut self, lua_code: String, bot_count: u32) -> anyhow::Result<()> {
         let all_bots = self.initiate_missing_players_with_default_inventory(bot_count);
         self.plan_world.import(self.real_world.clone())?;
         let lua = Lua::new();
-        lua.context::<_, rlua::Result<()>>(|ctx| {
+        self.lua_score = lua.context::<_, rlua::Result<Option<f64>>>(|ctx| {
             let world = create_lua_world(ctx, self.plan_world.clone())?;
             let plan = create_lua_plan_builder(ctx, self.graph.clone(), self.plan_world.clone())?;
             let globals = ctx.globals();
             globals.set("all_bots", all_bots)?;
-            globals.set("world", world)?;
+            globals.set("world", world.clone())?;
             globals.set("plan", plan)?;
             if let Some(rcon) = self.rcon.as_ref() {
                 let rcon = create_lua_rcon(ctx, rcon.clone())?;
@@ -64,7 +67,12 @@ impl Planner {
                 .basic_scheduler()
                 .build()
                 .unwrap()
-                .block_on(chunk.exec_async(ctx))
+                .block_on(chunk.exec_async(ctx))?;
+            // plans may rate the world they leave behind, used when rolling seeds
+            match globals.get::<_, rlua::Value>("score")? {
+                rlua::Value::Function(score) => Ok(Some(score.call(world)?)),
+                _ => Ok(None),
+            }
         })?;
         Ok(())
     }
@@ -75,6 +83,10 @@ impl Planner {
     pub fn graph(&self) -> TaskGraph {
         self.graph.read().clone()
     }
+    /// result of the plan's `score(world)` function if it defines one
+    pub fn lua_score(&self) -> Option<f64> {
+        self.lua_score
+    }
 
     fn initiate_missing_players_with_default_inventory(&mut self, bot_count: u32) -> Vec<u32> {
         let mut player_ids: Vec<u32> = vec![];
@@ -280,6 +292,8 @@ pub fn execute_plan(
 #[cfg(test)]
 mod tests {
     use crate::factorio::tests::{draw_world, fixture_world};
+    use crate::factorio::util::add_to_rect;
+    use crate::types::{EntityType, FactorioEntity, Position, Rect};
 
     use super::*;
 
@@ -335,4 +349,76 @@ mod tests {
 "#,
         );
     }
+
+    #[test]
+    fn test_malformed_positions() {
+        let world = Arc::new(fixture_world());
+        let mut planner = Planner::new(world, None);
+        planner
+            .plan(
+                r##"
+    local ok, err = pcall(plan.placeRails, all_bots[1], "1,x", "1,-9")
+    assert(not ok and err ~= nil)
+    ok = pcall(plan.setTrainSchedule, all_bots[1], "locomotive", {})
+    assert(not ok)
+        "##
+                .into(),
+                1,
+            )
+            .unwrap();
+        assert!(planner
+            .plan(r#"plan.placeRails(all_bots[1], "1,1", "x")"#.into(), 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_clear_area() {
+        let world = Arc::new(fixture_world());
+        let position = Position::new(-30., -30.);
+        world
+            .entity_graph
+            .add(
+                vec![FactorioEntity {
+                    name: "rock-huge".into(),
+                    entity_type: EntityType::SimpleEntity.to_string(),
+                    bounding_box: add_to_rect(&Rect::from_wh(3., 3.), &position),
+                    position,
+                    ..Default::default()
+                }],
+                None,
+            )
+            .unwrap();
+        let area: Rect = "-35,-35;-10,-10".parse().unwrap();
+        let obstacles = world.entity_graph.obstacles_in(&area);
+        let mined_stone: u32 = obstacles
+            .iter()
+            .filter_map(|obstacle| {
+                let prototype = world.entity_prototypes.get(&obstacle.name)?;
+                let mine_result = prototype.mine_result.as_ref().as_ref()?;
+                mine_result.get("stone").copied()
+            })
+            .sum();
+        assert!(mined_stone > 0);
+        let mut planner = Planner::new(world, None);
+        planner
+            .plan(
+                r##"
+    local cliffs = plan.clearArea(all_bots, "-35,-35;-10,-10")
+    assert(#cliffs == 0)
+        "##
+                .into(),
+                2,
+            )
+            .unwrap();
+        let dot = planner.graph().graphviz_dot();
+        assert_eq!(dot.matches("Mining ").count(), obstacles.len());
+        let plan_world = planner.world();
+        assert!(plan_world.entity_graph.obstacles_in(&area).is_empty());
+        let stone: u32 = plan_world
+            .players
+            .iter()
+            .map(|player| *player.main_inventory.get("stone").unwrap_or(&0))
+            .sum();
+        assert_eq!(stone, mined_stone);
+    }
 }
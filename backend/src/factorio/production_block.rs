@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashSet};
+
+use num_traits::ToPrimitive;
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::{add_to_rect, tile_center};
+use crate::types::{
+    Direction, EntityName, EntityType, FactorioEntity, Pos, Position, ProductionBlock, Rect,
+};
+
+// small electric pole, used when the prototype does not report it
+const DEFAULT_SUPPLY_AREA_DISTANCE: f64 = 2.5;
+
+/// machine columns producing `rate` items per second of the recipe's main product.
+///
+/// every input position starts one column running south: input belt, inserter, machines,
+/// inserter, output belt. machines are split evenly over the columns and each column has to fit
+/// on a single belt. ingredients share the two lanes of the input belt, burner machines take
+/// their fuel from it as well and leave the ingredients one lane
+pub fn plan_production_block(
+    entity_graph: &EntityGraph,
+    recipe_name: &str,
+    rate: f64,
+    inputs: &[Position],
+    machine_name: Option<&str>,
+) -> anyhow::Result<ProductionBlock> {
+    let recipe = entity_graph
+        .recipes()
+        .get(recipe_name)
+        .map(|recipe| recipe.value().clone())
+        .ok_or_else(|| anyhow!("recipe {} not found", recipe_name))?;
+    if rate <= 0. {
+        bail!("rate must be positive");
+    }
+    if inputs.is_empty() {
+        bail!("at least one input belt position is required");
+    }
+    let ingredients = (*recipe.ingredients).clone().unwrap_or_default();
+    if ingredients
+        .iter()
+        .any(|ingredient| ingredient.ingredient_type == "fluid")
+    {
+        bail!("{} needs fluids which belts cannot carry", recipe_name);
+    }
+    if ingredients.len() > 2 {
+        bail!("{} needs more than two input lanes", recipe_name);
+    }
+    let smelting = recipe.category == "smelting";
+    let machine_name = match machine_name {
+        Some(name) => name.to_string(),
+        None if smelting => EntityName::StoneFurnace.to_string(),
+        None => EntityName::AssemblingMachine1.to_string(),
+    };
+    let machine_type = if smelting {
+        EntityType::Furnace
+    } else {
+        EntityType::AssemblingMachine
+    };
+
+    let prototypes = entity_graph.entity_prototypes();
+    let prototype = prototypes
+        .get(&machine_name)
+        .ok_or_else(|| anyhow!("machine {} not found", machine_name))?;
+    if prototype.entity_type != machine_type.to_string() {
+        bail!("{} cannot craft {}", machine_name, recipe_name);
+    }
+    let crafting_speed = (*prototype.crafting_speed)
+        .map(|speed| speed.to_f64().unwrap())
+        .unwrap_or(1.);
+    let width = prototype.collision_box.width().ceil() as i32;
+    let height = prototype.collision_box.height().ceil() as i32;
+    let collision_box = prototype.collision_box.clone();
+    let burner = prototype.electric_energy_usage.is_none();
+    drop(prototype);
+    let supply_area_distance = prototypes
+        .get(&EntityName::SmallElectricPole.to_string())
+        .and_then(|pole| *pole.supply_area_distance)
+        .map(|distance| distance.to_f64().unwrap())
+        .unwrap_or(DEFAULT_SUPPLY_AREA_DISTANCE);
+    let belt_capacity = prototypes
+        .get(&EntityName::TransportBelt.to_string())
+        .and_then(|belt| *belt.belt_speed)
+        .map(|speed| speed.to_f64().unwrap() * 60. * 8.)
+        .ok_or_else(|| anyhow!("transport belt speed unknown"))?;
+
+    let product = recipe
+        .products
+        .iter()
+        .find(|product| product.name == recipe.name)
+        .or_else(|| recipe.products.first())
+        .ok_or_else(|| anyhow!("{} has no products", recipe_name))?;
+    let crafts_per_second = crafting_speed / recipe.energy.to_f64().unwrap();
+    let machine_rate =
+        crafts_per_second * product.amount as f64 * product.probability.to_f64().unwrap();
+    let input_amount: u32 = ingredients.iter().map(|ingredient| ingredient.amount).sum();
+    let machines = (rate / machine_rate - 1e-9).ceil() as u32;
+    let per_column = (machines as f64 / inputs.len() as f64).ceil();
+    let input_capacity = if burner {
+        belt_capacity / 2.
+    } else {
+        belt_capacity
+    };
+    let input_load = per_column * crafts_per_second * input_amount as f64;
+    if input_load > input_capacity + 1e-9 {
+        bail!(
+            "{} machines need {:.2} items/s per column, more than {} input belt carries. add input positions",
+            machines,
+            input_load,
+            if burner { "one lane of the" } else { "an" }
+        );
+    }
+    let output_load = per_column * crafts_per_second * product.amount as f64;
+    if output_load > belt_capacity + 1e-9 {
+        bail!(
+            "{} machines make {:.2} items/s per column, more than a belt carries. add input positions",
+            machines,
+            output_load
+        );
+    }
+
+    let blocked_tree = entity_graph.blocked_tree();
+    let mut occupied: HashSet<Pos> = HashSet::new();
+    let mut occupy = |tiles: Vec<Pos>| -> anyhow::Result<()> {
+        for tile in tiles {
+            let rect: QuadTreeRect =
+                add_to_rect(&Rect::from_wh(0.5, 0.5), &tile_center(&tile)).into();
+            if !blocked_tree.query(rect).is_empty() || !occupied.insert(tile.clone()) {
+                bail!("tile {:?} of the production block is blocked", tile);
+            }
+        }
+        Ok(())
+    };
+    let pole_reach = (supply_area_distance - 0.5).floor() as i32;
+    let mut entities: Vec<FactorioEntity> = vec![];
+    let mut input_positions: Vec<Position> = vec![];
+    let mut outputs: Vec<Position> = vec![];
+    for (index, input) in inputs.iter().enumerate() {
+        let count = machines as usize / inputs.len()
+            + if index < machines as usize % inputs.len() {
+                1
+            } else {
+                0
+            };
+        if count == 0 {
+            continue;
+        }
+        let start: Pos = input.into();
+        let length = count as i32 * height;
+        let input_x = start.0;
+        let output_x = start.0 + width + 3;
+        for y in start.1..start.1 + length {
+            for x in &[input_x, output_x] {
+                occupy(vec![Pos(*x, y)])?;
+                entities.push(FactorioEntity::new_transport_belt(
+                    &tile_center(&Pos(*x, y)),
+                    Direction::South,
+                ));
+            }
+        }
+        input_positions.push(tile_center(&start));
+        outputs.push(tile_center(&Pos(output_x, start.1 + length - 1)));
+
+        let mut last_pole: Option<i32> = None;
+        for machine in 0..count as i32 {
+            let top = start.1 + machine * height;
+            let tile = Pos(start.0 + 2, top);
+            let footprint: Vec<Pos> = (top..top + height)
+                .flat_map(|y| (tile.0..tile.0 + width).map(move |x| Pos(x, y)))
+                .collect();
+            occupy(footprint)?;
+            let position = Position::new(
+                tile.0 as f64 + width as f64 / 2.,
+                top as f64 + height as f64 / 2.,
+            );
+            entities.push(FactorioEntity {
+                name: machine_name.clone(),
+                entity_type: machine_type.to_string(),
+                bounding_box: add_to_rect(&collision_box, &position),
+                position,
+                recipe: if smelting {
+                    None
+                } else {
+                    Some(recipe.name.clone())
+                },
+                ..Default::default()
+            });
+
+            let inserter_y = top + (height - 1) / 2;
+            let needs_pole = match last_pole {
+                Some(pole_y) => (inserter_y - pole_y).abs() > pole_reach,
+                None => true,
+            };
+            let pole_y = top + height - 1;
+            for x in &[input_x + 1, output_x - 1] {
+                occupy(vec![Pos(*x, inserter_y)])?;
+                entities.push(FactorioEntity::new_inserter(
+                    &tile_center(&Pos(*x, inserter_y)),
+                    Direction::West,
+                ));
+                if needs_pole {
+                    occupy(vec![Pos(*x, pole_y)])?;
+                    entities.push(FactorioEntity::new_small_electric_pole(&tile_center(&Pos(
+                        *x, pole_y,
+                    ))));
+                }
+            }
+            if needs_pole {
+                last_pole = Some(pole_y);
+            }
+        }
+    }
+
+    let mut cost: BTreeMap<String, u32> = BTreeMap::new();
+    for entity in &entities {
+        *cost.entry(entity.name.clone()).or_insert(0) += 1;
+    }
+    Ok(ProductionBlock {
+        recipe_name: recipe.name.clone(),
+        machine_name,
+        machines,
+        rate: machines as f64 * machine_rate,
+        entities,
+        inputs: input_positions,
+        outputs,
+        cost,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::factorio::util::calculate_distance;
+
+    use super::*;
+
+    #[test]
+    fn test_production_block() {
+        let world = fixture_world();
+        let inputs = vec![Position::new(20., -20.), Position::new(30., -20.)];
+        let block = plan_production_block(
+            &world.entity_graph,
+            &EntityName::IronPlate.to_string(),
+            15.,
+            &inputs,
+            None,
+        )
+        .unwrap();
+        // 3.2s per plate at crafting speed 1
+        assert_eq!(block.machines, 48);
+        assert_eq!(block.machine_name, EntityName::StoneFurnace.to_string());
+        assert!((block.rate - 15.).abs() < 1e-6);
+        assert_eq!(block.cost[&block.machine_name], 48);
+        assert_eq!(block.cost[&EntityName::Inserter.to_string()], 96);
+        assert_eq!(block.outputs.len(), 2);
+        // every machine is fed and emptied by an inserter
+        for machine in block
+            .entities
+            .iter()
+            .filter(|e| e.name == block.machine_name)
+        {
+            for side in &[-1.5, 1.5] {
+                let inserter = Position::new(machine.position.x() + side, machine.position.y());
+                assert!(block.entities.iter().any(|e| {
+                    e.name == EntityName::Inserter.to_string()
+                        && calculate_distance(&e.position, &inserter) < 0.6
+                }));
+            }
+        }
+
+        let gears = plan_production_block(
+            &world.entity_graph,
+            "iron-gear-wheel",
+            1.,
+            &inputs[..1],
+            None,
+        )
+        .unwrap();
+        assert_eq!(gears.machines, 1);
+        assert_eq!(
+            gears
+                .entities
+                .iter()
+                .find(|e| e.recipe.is_some())
+                .unwrap()
+                .name,
+            EntityName::AssemblingMachine1.to_string()
+        );
+        // 15 ore/s fill both lanes and leave no room for coal
+        assert!(plan_production_block(
+            &world.entity_graph,
+            &EntityName::IronPlate.to_string(),
+            15.,
+            &inputs[..1],
+            None,
+        )
+        .is_err());
+        // more than one belt of plates
+        assert!(plan_production_block(
+            &world.entity_graph,
+            &EntityName::IronPlate.to_string(),
+            30.,
+            &inputs[..1],
+            None,
+        )
+        .is_err());
+    }
+}
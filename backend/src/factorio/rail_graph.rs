@@ -0,0 +1,613 @@
+use std::collections::{HashMap, HashSet};
+
+use dashmap::DashMap;
+use petgraph::algo::astar;
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+use crate::factorio::util::calculate_distance;
+use crate::num_traits::FromPrimitive;
+use crate::types::{
+    Direction, EntityName, EntityType, FactorioEntity, Position, RailBlock, RailNetwork, RailRoute,
+    RailSegment, Rect, TrainStation,
+};
+
+// a 90 degree turn moves this many tiles forward and sideways
+pub const RAIL_TURN_SIZE: f64 = 11.;
+// rail signals stand next to the joint between two rails
+const SIGNAL_DISTANCE: f64 = 2.;
+// train stops stand next to the rail they belong to
+const STATION_DISTANCE: f64 = 3.;
+
+/// connection point of a rail piece, heading points away from the piece
+#[derive(Debug, Clone, PartialEq)]
+pub struct RailEnd {
+    pub position: Position,
+    pub heading: u8,
+}
+
+/// straight and curved rails with their signals and train stops
+pub struct RailGraph {
+    rails: DashMap<(Position, u8), FactorioEntity>,
+    signals: DashMap<Position, FactorioEntity>,
+    stations: DashMap<Position, FactorioEntity>,
+}
+
+impl RailGraph {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RailGraph {
+            rails: DashMap::new(),
+            signals: DashMap::new(),
+            stations: DashMap::new(),
+        }
+    }
+
+    pub fn import(&self, other: &RailGraph) -> anyhow::Result<()> {
+        self.rails.clear();
+        for entry in other.rails.iter() {
+            self.rails
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        self.signals.clear();
+        for entry in other.signals.iter() {
+            self.signals
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        self.stations.clear();
+        for entry in other.stations.iter() {
+            self.stations
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        Ok(())
+    }
+
+    /// rails, signals and train stops positioned inside the rect
+    pub fn entities_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        let mut entities: Vec<FactorioEntity> = self
+            .rails
+            .iter()
+            .filter(|rail| rect.contains(&rail.position))
+            .map(|rail| rail.value().clone())
+            .collect();
+        for entity in self.signals.iter().chain(self.stations.iter()) {
+            if rect.contains(&entity.position) {
+                entities.push(entity.value().clone());
+            }
+        }
+        entities
+    }
+
+    /// ignores everything but rails, rail signals and train stops
+    pub fn add(&self, entity: &FactorioEntity) {
+        if entity.entity_type == EntityType::StraightRail.to_string()
+            || entity.entity_type == EntityType::CurvedRail.to_string()
+        {
+            self.rails
+                .insert((entity.position.clone(), entity.direction), entity.clone());
+        } else if entity.entity_type == EntityType::RailSignal.to_string()
+            || entity.entity_type == EntityType::RailChainSignal.to_string()
+        {
+            self.signals.insert(entity.position.clone(), entity.clone());
+        } else if entity.entity_type == EntityType::TrainStop.to_string() {
+            self.stations
+                .insert(entity.position.clone(), entity.clone());
+        }
+    }
+
+    pub fn remove(&self, entity: &FactorioEntity) {
+        self.rails
+            .remove(&(entity.position.clone(), entity.direction));
+        self.signals.remove(&entity.position);
+        self.stations.remove(&entity.position);
+    }
+
+    fn layer(&self) -> RailLayer {
+        let mut rails: Vec<FactorioEntity> = self
+            .rails
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        rails.sort_by(|a, b| {
+            a.position
+                .cmp(&b.position)
+                .then(a.direction.cmp(&b.direction))
+        });
+        let ends: Vec<[RailEnd; 2]> = rails
+            .iter()
+            .map(|rail| rail_ends(&rail.name, &rail.position, rail.direction))
+            .collect();
+        let mut ends_by_key: HashMap<(i64, i64, u8), Vec<(usize, usize)>> = HashMap::new();
+        for (index, rail_ends) in ends.iter().enumerate() {
+            for (end_index, end) in rail_ends.iter().enumerate() {
+                ends_by_key
+                    .entry(end_key(&end.position, end.heading))
+                    .or_insert_with(Vec::new)
+                    .push((index, end_index));
+            }
+        }
+        let neighbours: Vec<[Vec<(usize, usize)>; 2]> = ends
+            .iter()
+            .map(|rail_ends| {
+                let connected = |end: &RailEnd| {
+                    ends_by_key
+                        .get(&end_key(&end.position, (end.heading + 4) % 8))
+                        .cloned()
+                        .unwrap_or_default()
+                };
+                [connected(&rail_ends[0]), connected(&rail_ends[1])]
+            })
+            .collect();
+        RailLayer {
+            rails,
+            ends,
+            neighbours,
+        }
+    }
+
+    pub fn network(&self) -> RailNetwork {
+        let layer = self.layer();
+        let signal_positions: Vec<Position> = self
+            .signals
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        let segments = layer.components(|index, end_index| {
+            layer.neighbours[index][end_index].len() == 1
+                && layer.neighbours[index][end_index]
+                    .iter()
+                    .all(|(other, other_end)| layer.neighbours[*other][*other_end].len() == 1)
+        });
+        let segments = segments
+            .into_iter()
+            .enumerate()
+            .map(|(id, rails)| RailSegment {
+                id: id as u32 + 1,
+                length: rails.iter().map(|index| layer.length(*index)).sum(),
+                rails: rails
+                    .iter()
+                    .map(|index| layer.rails[*index].position.clone())
+                    .collect(),
+            })
+            .collect();
+        let blocks = layer.components(|index, end_index| {
+            !signal_positions.iter().any(|signal| {
+                calculate_distance(signal, &layer.ends[index][end_index].position)
+                    <= SIGNAL_DISTANCE
+            })
+        });
+        let blocks = blocks
+            .into_iter()
+            .enumerate()
+            .map(|(id, rails)| {
+                let mut signals: Vec<Position> = signal_positions
+                    .iter()
+                    .filter(|signal| {
+                        rails.iter().any(|index| {
+                            layer.ends[*index].iter().any(|end| {
+                                calculate_distance(signal, &end.position) <= SIGNAL_DISTANCE
+                            })
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                signals.sort();
+                RailBlock {
+                    id: id as u32 + 1,
+                    rails: rails
+                        .iter()
+                        .map(|index| layer.rails[*index].position.clone())
+                        .collect(),
+                    signals,
+                }
+            })
+            .collect();
+        let mut stations: Vec<TrainStation> = self
+            .stations
+            .iter()
+            .map(|entry| TrainStation {
+                name: entry.station_name.clone().unwrap_or_default(),
+                position: entry.position.clone(),
+                rail: layer
+                    .rail_near(&entry.position)
+                    .map(|index| layer.rails[index].position.clone()),
+            })
+            .collect();
+        stations.sort_by(|a, b| a.position.cmp(&b.position));
+        RailNetwork {
+            segments,
+            blocks,
+            stations,
+        }
+    }
+
+    /// shortest way a train can drive between two stations, trains may leave in either direction
+    pub fn route(&self, from: &str, to: &str) -> anyhow::Result<RailRoute> {
+        let layer = self.layer();
+        let station_rail = |name: &str| {
+            let position = self
+                .stations
+                .iter()
+                .find(|entry| entry.station_name.as_deref() == Some(name))
+                .map(|entry| entry.position.clone());
+            match position {
+                Some(position) => layer
+                    .rail_near(&position)
+                    .ok_or_else(|| anyhow!("station {} has no rail", name)),
+                None => Err(anyhow!("station {} not found", name)),
+            }
+        };
+        let start = station_rail(from)?;
+        let goal = station_rail(to)?;
+        let graph = layer.route_graph();
+        let mut best: Option<(f64, Vec<NodeIndex>)> = None;
+        for entry in 0..2 {
+            if let Some((cost, path)) = astar(
+                &graph,
+                NodeIndex::new(start * 2 + entry),
+                |node| node.index() / 2 == goal,
+                |edge| *edge.weight(),
+                |_| 0.,
+            ) {
+                if best.as_ref().map(|(best, _)| cost < *best).unwrap_or(true) {
+                    best = Some((cost, path));
+                }
+            }
+        }
+        match best {
+            Some((cost, path)) => Ok(RailRoute {
+                from: from.into(),
+                to: to.into(),
+                rails: path
+                    .iter()
+                    .map(|node| layer.rails[node.index() / 2].position.clone())
+                    .collect(),
+                length: cost + layer.length(start),
+            }),
+            None => Err(anyhow!("no rail route from {} to {}", from, to)),
+        }
+    }
+}
+
+struct RailLayer {
+    rails: Vec<FactorioEntity>,
+    ends: Vec<[RailEnd; 2]>,
+    // rail and end index connected to each end of each rail
+    neighbours: Vec<[Vec<(usize, usize)>; 2]>,
+}
+
+impl RailLayer {
+    fn length(&self, index: usize) -> f64 {
+        calculate_distance(&self.ends[index][0].position, &self.ends[index][1].position)
+    }
+
+    fn rail_near(&self, position: &Position) -> Option<usize> {
+        let mut best: Option<(f64, usize)> = None;
+        for (index, rail) in self.rails.iter().enumerate() {
+            let distance = calculate_distance(&rail.position, position);
+            if distance <= STATION_DISTANCE && best.map(|(best, _)| distance < best).unwrap_or(true)
+            {
+                best = Some((distance, index));
+            }
+        }
+        best.map(|(_, index)| index)
+    }
+
+    /// groups rails which are connected through ends passing the filter
+    fn components<F>(&self, joins: F) -> Vec<Vec<usize>>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        fn find(parents: &mut [usize], i: usize) -> usize {
+            if parents[i] != i {
+                parents[i] = find(parents, parents[i]);
+            }
+            parents[i]
+        }
+        let mut parents: Vec<usize> = (0..self.rails.len()).collect();
+        for index in 0..self.rails.len() {
+            for end_index in 0..2 {
+                if !joins(index, end_index) {
+                    continue;
+                }
+                for (other, _) in &self.neighbours[index][end_index] {
+                    let a = find(&mut parents, index);
+                    let b = find(&mut parents, *other);
+                    parents[a] = b;
+                }
+            }
+        }
+        let mut components: Vec<Vec<usize>> = vec![];
+        let mut component_of_root: HashMap<usize, usize> = HashMap::new();
+        for index in 0..self.rails.len() {
+            let root = find(&mut parents, index);
+            let component = *component_of_root.entry(root).or_insert_with(|| {
+                components.push(vec![]);
+                components.len() - 1
+            });
+            components[component].push(index);
+        }
+        components
+    }
+
+    /// node rail * 2 + end is a train which entered the rail through that end
+    fn route_graph(&self) -> Graph<usize, f64> {
+        let mut graph: Graph<usize, f64> = Graph::new();
+        for index in 0..self.rails.len() {
+            graph.add_node(index);
+            graph.add_node(index);
+        }
+        let mut edges: HashSet<(usize, usize)> = HashSet::new();
+        for index in 0..self.rails.len() {
+            for entry in 0..2 {
+                for (other, other_end) in &self.neighbours[index][1 - entry] {
+                    let (from, to) = (index * 2 + entry, other * 2 + other_end);
+                    if edges.insert((from, to)) {
+                        graph.add_edge(
+                            NodeIndex::new(from),
+                            NodeIndex::new(to),
+                            self.length(*other),
+                        );
+                    }
+                }
+            }
+        }
+        graph
+    }
+}
+
+fn end_key(position: &Position, heading: u8) -> (i64, i64, u8) {
+    (
+        (position.x() * 2.).round() as i64,
+        (position.y() * 2.).round() as i64,
+        heading,
+    )
+}
+
+fn rotate_clockwise(x: f64, y: f64, quarter_turns: u8) -> (f64, f64) {
+    let (mut x, mut y) = (x, y);
+    for _ in 0..quarter_turns {
+        let previous_x = x;
+        x = -y;
+        y = previous_x;
+    }
+    (x, y)
+}
+
+/// both connection points of a rail piece
+pub fn rail_ends(name: &str, position: &Position, direction: u8) -> [RailEnd; 2] {
+    let direction = direction % 8;
+    let offsets: [(f64, f64, u8); 2] = if name == EntityName::CurvedRail.to_string() {
+        // north facing curves go from the straight end at the bottom to the diagonal end top left,
+        // odd directions are mirrored and everything else is rotated
+        let base = if direction % 2 == 0 {
+            [(1., 4., 4), (-2., -3., 7)]
+        } else {
+            [(-1., 4., 4), (2., -3., 1)]
+        };
+        let quarter_turns = direction / 2;
+        let turn = |(x, y, heading): (f64, f64, u8)| {
+            let (x, y) = rotate_clockwise(x, y, quarter_turns);
+            (x, y, (heading + quarter_turns * 2) % 8)
+        };
+        [turn(base[0]), turn(base[1])]
+    } else {
+        // diagonal straight rails only cover the corner of their cell they are named after
+        match direction {
+            0 | 4 => [(0., -1., 0), (0., 1., 4)],
+            2 | 6 => [(1., 0., 2), (-1., 0., 6)],
+            1 => [(0., -1., 7), (1., 0., 3)],
+            3 => [(1., 0., 1), (0., 1., 5)],
+            5 => [(0., 1., 3), (-1., 0., 7)],
+            _ => [(-1., 0., 5), (0., -1., 1)],
+        }
+    };
+    let end = |(x, y, heading): (f64, f64, u8)| RailEnd {
+        position: position.add(&Position::new(x, y)),
+        heading,
+    };
+    [end(offsets[0]), end(offsets[1])]
+}
+
+// straight rails are centered on odd, curved rails on even coordinates
+fn is_on_rail_grid(name: &str, position: &Position) -> bool {
+    let expected = if name == EntityName::CurvedRail.to_string() {
+        0.
+    } else {
+        1.
+    };
+    position.x().rem_euclid(2.) == expected && position.y().rem_euclid(2.) == expected
+}
+
+/// the rail piece continuing at a connection point, turn is -1 for left, 0 for straight and 1 for right
+fn next_rail(end: &RailEnd, name: &str, turn: i8) -> Option<(FactorioEntity, RailEnd)> {
+    let exit_heading = (end.heading as i8 + turn).rem_euclid(8) as u8;
+    for direction in 0..8 {
+        let offsets = rail_ends(name, &Position::default(), direction);
+        for entry in 0..2 {
+            if offsets[entry].heading != (end.heading + 4) % 8
+                || offsets[1 - entry].heading != exit_heading
+            {
+                continue;
+            }
+            let position = Position::new(
+                end.position.x() - offsets[entry].position.x(),
+                end.position.y() - offsets[entry].position.y(),
+            );
+            if !is_on_rail_grid(name, &position) {
+                continue;
+            }
+            let direction = Direction::from_u8(direction).unwrap();
+            let rail = if name == EntityName::CurvedRail.to_string() {
+                FactorioEntity::new_curved_rail(&position, direction)
+            } else {
+                FactorioEntity::new_straight_rail(&position, direction)
+            };
+            let exit = RailEnd {
+                position: position.add(&offsets[1 - entry].position),
+                heading: exit_heading,
+            };
+            return Some((rail, exit));
+        }
+    }
+    None
+}
+
+fn snap_to_rail_grid(position: &Position) -> Position {
+    Position::new(
+        (position.x() / 2.).floor() * 2. + 1.,
+        (position.y() / 2.).floor() * 2. + 1.,
+    )
+}
+
+/// rails from one point to another, first along the y axis and then with a 90 degree turn along the x axis
+pub fn rail_path(from: &Position, to: &Position) -> anyhow::Result<Vec<FactorioEntity>> {
+    let from = snap_to_rail_grid(from);
+    let to = snap_to_rail_grid(to);
+    let dx = to.x() - from.x();
+    let dy = to.y() - from.y();
+    let heading: u8 = if dy < 0. {
+        0
+    } else if dy > 0. {
+        4
+    } else if dx > 0. {
+        2
+    } else {
+        6
+    };
+    let straight_name = EntityName::StraightRail.to_string();
+    let curved_name = EntityName::CurvedRail.to_string();
+    // start at the back end of the first rail
+    let start_offset = rail_ends(&straight_name, &Position::default(), heading)
+        .iter()
+        .find(|end| end.heading == (heading + 4) % 8)
+        .unwrap()
+        .position
+        .clone();
+    let mut end = RailEnd {
+        position: from.add(&start_offset),
+        heading,
+    };
+    let mut rails: Vec<FactorioEntity> = vec![];
+    if dx != 0. && dy != 0. {
+        if dx.abs() <= RAIL_TURN_SIZE || dy.abs() <= RAIL_TURN_SIZE {
+            bail!(
+                "{} and {} are too close for a turn, rails need {} tiles to turn",
+                from,
+                to,
+                RAIL_TURN_SIZE + 1.
+            );
+        }
+        let turn_y = to.y() - dy.signum() * RAIL_TURN_SIZE;
+        // every straight rail is 2 tiles long
+        let steps = ((turn_y - end.position.y()).abs() / 2.).round() as usize;
+        for _ in 0..steps {
+            let (rail, exit) = next_rail(&end, &straight_name, 0)
+                .ok_or_else(|| anyhow!("no straight rail continues at {}", end.position))?;
+            rails.push(rail);
+            end = exit;
+        }
+        if end.position.y() != turn_y {
+            bail!(
+                "rail path from {} missed the turn at {}",
+                from,
+                end.position
+            );
+        }
+        let turn: i8 = if (heading == 0) == (dx > 0.) { 1 } else { -1 };
+        for (name, turn) in &[
+            (&curved_name, turn),
+            (&straight_name, 0),
+            (&curved_name, turn),
+        ] {
+            let (rail, exit) = next_rail(&end, name, *turn)
+                .ok_or_else(|| anyhow!("failed to turn at {}", end.position))?;
+            rails.push(rail);
+            end = exit;
+        }
+    }
+    let steps = ((to.x() - end.position.x()).abs() + (to.y() - end.position.y()).abs()) / 2.;
+    for _ in 0..=steps.ceil() as usize {
+        let (rail, exit) = next_rail(&end, &straight_name, 0)
+            .ok_or_else(|| anyhow!("no straight rail continues at {}", end.position))?;
+        let done = rail.position == to;
+        rails.push(rail);
+        if done {
+            return Ok(rails);
+        }
+        if calculate_distance(&exit.position, &to) > calculate_distance(&end.position, &to) {
+            break;
+        }
+        end = exit;
+    }
+    bail!("rail path from {} missed {}", from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_entity;
+
+    use super::*;
+
+    #[test]
+    fn test_rail_path() {
+        let rails = rail_path(&Position::new(1., 1.), &Position::new(25., -25.)).unwrap();
+        assert_eq!(rails.len(), 18);
+        let curves: Vec<&FactorioEntity> = rails
+            .iter()
+            .filter(|rail| rail.name == EntityName::CurvedRail.to_string())
+            .collect();
+        assert_eq!(curves.len(), 2);
+        assert_eq!(curves[0].position, Position::new(2., -18.));
+        assert_eq!(curves[1].position, Position::new(8., -24.));
+        assert_eq!(rails.last().unwrap().position, Position::new(25., -25.));
+        assert!(rail_path(&Position::new(1., 1.), &Position::new(5., -25.)).is_err());
+        assert_eq!(
+            rail_path(&Position::new(1., 1.), &Position::new(1., -9.))
+                .unwrap()
+                .len(),
+            6
+        );
+    }
+
+    #[test]
+    fn test_rail_network() {
+        let graph = RailGraph::new();
+        for rail in rail_path(&Position::new(1., 1.), &Position::new(25., -25.)).unwrap() {
+            graph.add(&rail);
+        }
+        graph.add(&fixture_entity(
+            "rail-signal",
+            EntityType::RailSignal,
+            &Position::new(2.5, -2.),
+            0,
+        ));
+        let mut station = fixture_entity(
+            "train-stop",
+            EntityType::TrainStop,
+            &Position::new(3., 1.),
+            0,
+        );
+        station.station_name = Some("iron".into());
+        graph.add(&station);
+        let mut station = fixture_entity(
+            "train-stop",
+            EntityType::TrainStop,
+            &Position::new(25., -23.),
+            0,
+        );
+        station.station_name = Some("smelter".into());
+        graph.add(&station);
+
+        let network = graph.network();
+        assert_eq!(network.segments.len(), 1);
+        assert_eq!(network.segments[0].rails.len(), 18);
+        assert_eq!(network.blocks.len(), 2);
+        assert_eq!(network.stations.len(), 2);
+
+        let route = graph.route("iron", "smelter").unwrap();
+        assert_eq!(route.rails.len(), 18);
+        assert_eq!(route.rails[0], Position::new(1., 1.));
+        assert!(graph.route("iron", "copper").is_err());
+    }
+}
@@ -8,19 +8,23 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::factorio::pipe_router::{offshore_pump_at, route_pipe};
 use crate::factorio::util::{
     blueprint_build_area, build_entity_path, calculate_distance, hashmap_to_lua, map_blocked_tiles,
-    move_pos, move_position, position_to_lua, rect_to_lua, span_rect, str_to_lua, value_to_lua,
-    vec_to_lua, vector_add, vector_multiply, vector_normalize, vector_substract,
+    move_pos, position_to_lua, rect_to_lua, span_rect, str_to_lua, value_to_lua, vec_to_lua,
+    vector_add, vector_multiply, vector_normalize, vector_substract,
 };
 use crate::factorio::world::FactorioWorld;
 use crate::num_traits::FromPrimitive;
 use crate::types::{
-    AreaFilter, Direction, FactorioEntity, FactorioForce, FactorioTile, InventoryResponse, Pos,
-    Position, Rect, RequestEntity,
+    AreaFilter, CircuitConnector, CircuitSignal, CombinatorSettings, Direction, FactorioEntity,
+    FactorioForce, FactorioTile, InventoryResponse, Pos, Position, Rect, RequestEntity,
+    TrainScheduleRecord,
 };
 
 const RCON_INTERFACE: &str = "botbridge";
+// placement options closest to the boiler which are tried for a pipe route
+const PUMP_OPTIONS_TRIED: usize = 10;
 
 pub struct FactorioRcon {
     // conn: Mutex<Connection>,
@@ -452,7 +456,20 @@ impl FactorioRcon {
         *next_action_id = (*next_action_id + 1) % 1000;
         drop(next_action_id);
 
-        let waypoints = self.player_path(&world, player_id, &goal, radius).await?;
+        let player_position = world.players.get(&player_id).map(|p| p.position.clone());
+        let local_waypoints = player_position.and_then(|position| {
+            world
+                .walk_pathfinder
+                .find_path(&position, &goal, radius.unwrap_or(1.))
+                .filter(|waypoints| world.walk_pathfinder.is_explored(&position, waypoints))
+        });
+        let waypoints = match local_waypoints {
+            Some(waypoints) => waypoints,
+            None => self.player_path(&world, player_id, &goal, radius).await?,
+        };
+        if waypoints.is_empty() {
+            return Ok(());
+        }
 
         self.action_start_walk_waypoints(action_id, player_id, waypoints)
             .await?;
@@ -560,14 +577,26 @@ impl FactorioRcon {
         if player.is_none() {
             return Err(anyhow!("player not found"));
         }
-        let player = player.unwrap();
-        let player_position = player.position.clone();
-        let build_distance = player.build_distance as f64;
-        drop(player); // wow, without this factorio (?) freezes (!)
-        let distance = calculate_distance(&player_position, &entity_position);
-        if distance > build_distance {
-            warn!("too far away, moving first!");
-            self.move_player(world, player_id, &entity_position, Some(build_distance))
+        let player_position = player.unwrap().position.clone();
+        let entity_name = world
+            .item_prototypes
+            .get(&item_name)
+            .map(|item| item.place_result.clone())
+            .filter(|place_result| !place_result.is_empty())
+            .unwrap_or_else(|| item_name.clone());
+        let entity_direction = Direction::from_u8(direction)
+            .ok_or_else(|| anyhow!("invalid direction {}", direction))?;
+        if !world.can_place(&entity_name, &entity_position, entity_direction, None)? {
+            return Err(anyhow!(
+                "{} at {} is blocked by another entity, a rail or the terrain",
+                entity_name,
+                entity_position
+            ));
+        }
+        let standing_position =
+            world.standing_position(player_id, &entity_name, &entity_position, entity_direction)?;
+        if calculate_distance(&player_position, &standing_position) > 0.1 {
+            self.move_player(world, player_id, &standing_position, Some(0.))
                 .await?;
         }
         let lines = self
@@ -591,53 +620,11 @@ impl FactorioRcon {
                 if chars[0] == "{" {
                     Ok(serde_json::from_str(&line).unwrap())
                 } else if &line[..] == "§player_blocks_placement§" {
-                    for test_direction in 0..8u8 {
-                        let test_position = move_position(
-                            &player_position,
-                            Direction::from_u8(test_direction).unwrap(),
-                            5.0,
-                        );
-                        if self
-                            .is_area_empty(&AreaFilter::PositionRadius((
-                                test_position.clone(),
-                                Some(2.0),
-                            )))
-                            .await?
-                        {
-                            self.move_player(world, player_id, &test_position, Some(1.0))
-                                .await?;
-                            let lines = self
-                                .remote_call(
-                                    "place_entity",
-                                    vec![
-                                        &player_id.to_string(),
-                                        &str_to_lua(&item_name),
-                                        &position_to_lua(&entity_position),
-                                        &direction.to_string(),
-                                    ],
-                                )
-                                .await?;
-                            return if let Some(lines) = lines {
-                                if lines.len() != 1 {
-                                    return Err(anyhow!("unexpected output {:?}", lines));
-                                }
-                                let line = &lines[0];
-                                let chars = UnicodeSegmentation::graphemes(line.as_str(), true)
-                                    .collect::<Vec<&str>>();
-                                if chars[0] == "{" {
-                                    Ok(serde_json::from_str(&line).unwrap())
-                                } else if &line[..] == "§player_blocks_placement§" {
-                                    Err(anyhow!("player still blocks placement"))
-                                } else {
-                                    Err(anyhow!("{}", line))
-                                }
-                            } else {
-                                Err(anyhow!("unexpected empty output"))
-                            };
-                        }
-                    }
                     Err(anyhow!(
-                        "Error: player blocks placement in all directions! "
+                        "player at {} blocks placement of {} at {}",
+                        standing_position,
+                        item_name,
+                        entity_position
                     ))
                 } else {
                     Err(anyhow!("{}", line))
@@ -1178,11 +1165,208 @@ impl FactorioRcon {
         }
         Err(anyhow!("could not find water"))
     }
+
+    /// offshore pump at the closest placement option which can be piped to the water input
+    /// of the boiler, returns the pump followed by the pipes. the boiler may be planned
+    pub async fn plan_pump_to_boiler(
+        &self,
+        world: &Arc<FactorioWorld>,
+        boiler: &FactorioEntity,
+        pump_direction: Direction,
+    ) -> anyhow::Result<Vec<FactorioEntity>> {
+        let mut options = self
+            .find_offshore_pump_placement_options(world, boiler.position.clone(), pump_direction)
+            .await?;
+        let distance = |pos: &Pos| calculate_distance(&pos.into(), &boiler.position);
+        options.sort_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap());
+        for option in options.iter().take(PUMP_OPTIONS_TRIED) {
+            let pump = offshore_pump_at(&world.entity_prototypes, option, pump_direction);
+            // the water fluid box of a boiler is the first one, the second is its steam output
+            if let Ok(pipes) = route_pipe(&world.entity_graph, &pump, 0, boiler, 0) {
+                let mut entities = vec![pump];
+                entities.extend(pipes);
+                return Ok(entities);
+            }
+        }
+        Err(anyhow!(
+            "none of {} offshore pump placements can be piped to the boiler",
+            options.len().min(PUMP_OPTIONS_TRIED)
+        ))
+    }
+
+    /// connects a red or green wire, the player needs a wire item in the inventory
+    pub async fn connect_wire(
+        &self,
+        player_id: u32,
+        wire: &str,
+        from: &CircuitConnector,
+        to: &CircuitConnector,
+    ) -> anyhow::Result<()> {
+        let lines = self
+            .remote_call(
+                "connect_wire",
+                vec![
+                    &player_id.to_string(),
+                    &str_to_lua(wire),
+                    &str_to_lua(&from.entity_name),
+                    &position_to_lua(&from.position),
+                    &from.circuit_id.to_string(),
+                    &str_to_lua(&to.entity_name),
+                    &position_to_lua(&to.position),
+                    &to.circuit_id.to_string(),
+                ],
+            )
+            .await?;
+        if lines.is_some() {
+            return Err(anyhow!("{:?}", lines.unwrap()));
+        }
+        Ok(())
+    }
+
+    pub async fn set_combinator(
+        &self,
+        entity_name: &str,
+        position: &Position,
+        settings: &CombinatorSettings,
+    ) -> anyhow::Result<()> {
+        let lines = self
+            .remote_call(
+                "set_combinator",
+                vec![
+                    &str_to_lua(entity_name),
+                    &position_to_lua(position),
+                    &str_to_lua(&serde_json::to_string(settings)?),
+                ],
+            )
+            .await?;
+        if lines.is_some() {
+            return Err(anyhow!("{:?}", lines.unwrap()));
+        }
+        Ok(())
+    }
+
+    /// places rails built from rail items, walks to each rail first if needed
+    pub async fn place_rails(
+        &self,
+        world: &Arc<FactorioWorld>,
+        player_id: u32,
+        rails: &[FactorioEntity],
+    ) -> anyhow::Result<Vec<FactorioEntity>> {
+        let mut placed: Vec<FactorioEntity> = vec![];
+        for rail in rails {
+            let player = world
+                .players
+                .get(&player_id)
+                .ok_or_else(|| anyhow!("player not found"))?;
+            let player_position = player.position.clone();
+            let build_distance = player.build_distance as f64;
+            drop(player);
+            if calculate_distance(&player_position, &rail.position) > build_distance {
+                self.move_player(world, player_id, &rail.position, Some(build_distance))
+                    .await?;
+            }
+            let lines = self
+                .remote_call(
+                    "place_rail",
+                    vec![
+                        &player_id.to_string(),
+                        &str_to_lua(&rail.name),
+                        &position_to_lua(&rail.position),
+                        &rail.direction.to_string(),
+                    ],
+                )
+                .await?;
+            match lines {
+                Some(lines) if lines.len() == 1 && lines[0].starts_with('{') => {
+                    placed.push(serde_json::from_str(&lines[0])?)
+                }
+                lines => {
+                    return Err(anyhow!(
+                        "failed to place {} at {}: {:?}",
+                        rail.name,
+                        rail.position,
+                        lines
+                    ))
+                }
+            }
+        }
+        Ok(placed)
+    }
+
+    /// replaces the schedule of the train the locomotive at position belongs to
+    pub async fn set_train_schedule(
+        &self,
+        locomotive: &Position,
+        records: &[TrainScheduleRecord],
+    ) -> anyhow::Result<()> {
+        let lines = self
+            .remote_call(
+                "set_train_schedule",
+                vec![
+                    &position_to_lua(locomotive),
+                    &str_to_lua(&serde_json::to_string(records)?),
+                ],
+            )
+            .await?;
+        if lines.is_some() {
+            return Err(anyhow!("{:?}", lines.unwrap()));
+        }
+        Ok(())
+    }
+
+    /// current signals on the wire of the given connection point
+    pub async fn circuit_signals(
+        &self,
+        entity_name: &str,
+        position: &Position,
+        wire: &str,
+        circuit_id: u8,
+    ) -> anyhow::Result<Vec<CircuitSignal>> {
+        let lines = self
+            .remote_call(
+                "circuit_signals",
+                vec![
+                    &str_to_lua(entity_name),
+                    &position_to_lua(position),
+                    &str_to_lua(wire),
+                    &circuit_id.to_string(),
+                ],
+            )
+            .await?;
+        if lines.is_none() {
+            return Err(anyhow!("Unexpected Empty Response"));
+        }
+        let json = lines.unwrap().pop().unwrap();
+        // empty objects/arrays are the same in lua
+        if json == "{}" {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_str(json.as_str())?)
+    }
 }
 
 pub fn create_lua_rcon(ctx: Context, _rcon: Arc<FactorioRcon>) -> rlua::Result<Table> {
     let map_table = ctx.create_table()?;
     use rlua_async::ContextExt;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "circuitSignals",
+        ctx.create_async_function_mut::<_, _, _, _>(
+            move |_ctx, (name, position, wire, circuit_id): (String, String, String, Option<u8>)| {
+                let rcon = rcon.clone();
+                async move {
+                    let position: Position = position
+                        .parse()
+                        .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                    let signals = rcon
+                        .circuit_signals(&name, &position, &wire, circuit_id.unwrap_or(1))
+                        .await
+                        .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                    Ok(signals)
+                }
+            },
+        )?,
+    )?;
     map_table.set(
         "findByNameInRadius",
         ctx.create_async_function_mut::<_, _, _, _>(
@@ -3,17 +3,19 @@ use std::thread::JoinHandle;
 use std::time::Instant;
 
 use async_std::sync::{Arc, Mutex};
-use config::Config;
+use config::{Config, ConfigError};
 
 use crate::factorio::instance_setup::setup_factorio_instance;
 use crate::factorio::planner::Planner;
 use crate::factorio::process_control::{start_factorio_server, FactorioStartCondition};
 use crate::factorio::rcon::{FactorioRcon, RconSettings};
-use crate::factorio::util::calculate_distance;
+use crate::factorio::seed_db::{inputs_hash, map_hash, SeedDb, SeedRoll};
+use crate::factorio::util::{add_to_rect, calculate_distance};
 use crate::factorio::world::FactorioWorld;
-use crate::types::{AreaFilter, FactorioEntity, Position};
+use crate::types::{AreaFilter, EntityName, FactorioEntity, Position, Rect};
+use std::collections::BTreeMap;
 use std::fs::read_to_string;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Copy, Clone)]
 pub enum RollSeedLimit {
@@ -28,10 +30,41 @@ pub async fn roll_seed(
     parallel: u8,
     plan_name: String,
     bot_count: u32,
+    scoring: Option<String>,
 ) -> anyhow::Result<Option<(u32, f64)>> {
-    let roll: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
-    let best_seed_with_score: Arc<Mutex<Option<(u32, f64)>>> = Arc::new(Mutex::new(None));
+    let heuristics = Arc::new(seed_heuristics(&settings, scoring.as_deref())?);
     let workspace_path: Arc<String> = Arc::new(settings.get("workspace_path")?);
+    let hash = Arc::new(map_hash(&map_exchange_string));
+    let scoring = Arc::new(scoring);
+    let lua_path_str = format!("plans/{}.lua", plan_name);
+    let lua_path = Path::new(&lua_path_str);
+    if !lua_path.exists() {
+        anyhow::bail!("plan {} not found at {}", plan_name, lua_path_str);
+    }
+    let lua_code = Arc::new(read_to_string(std::fs::canonicalize(lua_path)?)?);
+    let inputs = Arc::new(inputs_hash(&lua_code, &heuristics, bot_count)?);
+    let seed_db = SeedDb::open(&seed_db_path(&workspace_path))?;
+    // continue where an interrupted run with the same inputs stopped
+    let previous = seed_db.resumable(&hash, &plan_name, &inputs, scoring.as_deref());
+    let best_previous = previous
+        .iter()
+        .filter(|roll| roll.error.is_none())
+        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal))
+        .map(|roll| (roll.seed, roll.score));
+    if let Some((best_seed, best_score)) = best_previous {
+        info!(
+            "resuming after <green>{}</> previous rolls, best <blue>{}</> with score {}",
+            previous.len(),
+            best_seed,
+            best_score
+        );
+    }
+    let roll: Arc<Mutex<u64>> = Arc::new(Mutex::new(match limit {
+        RollSeedLimit::Rolls(_) => previous.len() as u64,
+        RollSeedLimit::Seconds(_) => 0,
+    }));
+    let best_seed_with_score: Arc<Mutex<Option<(u32, f64)>>> = Arc::new(Mutex::new(best_previous));
+    let seed_db = Arc::new(Mutex::new(seed_db));
     let map_exchange_string = Arc::new(map_exchange_string);
 
     let mut join_handles: Vec<JoinHandle<()>> = vec![];
@@ -72,14 +105,13 @@ pub async fn roll_seed(
         let workspace_path = workspace_path.clone();
         let map_exchange_string = map_exchange_string.clone();
         let plan_name = plan_name.clone();
+        let heuristics = heuristics.clone();
+        let hash = hash.clone();
+        let inputs = inputs.clone();
+        let scoring = scoring.clone();
+        let seed_db = seed_db.clone();
         let roll = roll.clone();
-        let lua_path_str = format!("plans/{}.lua", plan_name);
-        let lua_path = Path::new(&lua_path_str);
-        let lua_path = std::fs::canonicalize(lua_path)?;
-        if !lua_path.exists() {
-            anyhow::bail!("plan {} not found at {}", plan_name, lua_path_str);
-        }
-        let lua_code = read_to_string(lua_path)?;
+        let lua_code = lua_code.clone();
         join_handles.push(std::thread::spawn(move || {
             actix::run(async move {
                 while match limit {
@@ -94,7 +126,18 @@ pub async fn roll_seed(
                     let roll: u64 = *roll_mutex;
                     drop(roll_mutex);
 
-                    let seed: u32 = rand::random();
+                    let seed: u32 = loop {
+                        let seed: u32 = rand::random();
+                        if !seed_db.lock().await.contains(
+                            seed,
+                            &hash,
+                            &plan_name,
+                            &inputs,
+                            scoring.as_deref(),
+                        ) {
+                            break seed;
+                        }
+                    };
                     setup_factorio_instance(
                         &workspace_path,
                         &rcon_settings,
@@ -125,9 +168,10 @@ pub async fn roll_seed(
                     //     seed,
                     //     roll_started.elapsed()
                     // );
-                    match score_seed(rcon, world, seed, lua_code.as_str(), bot_count)
+                    match score_seed(rcon, world, seed, lua_code.as_str(), bot_count, &heuristics)
                         .await {
-                        Ok(score) => {
+                        Ok(seed_score) => {
+                            let score = seed_score.score;
                             let mut best_seed_with_score = best_seed_with_score.lock().await;
                             if let Some((_, previous_score)) = *best_seed_with_score {
                                 if score > previous_score {
@@ -140,16 +184,40 @@ pub async fn roll_seed(
                                 "instance #{} rolled #{}: seed {}{}</> scored {}{}</> in <yellow>{:?}</>",
                                 p + 1,
                                 roll,
-                                if score > -10000. { "<bold><blue>" } else { "" },
+                                if seed_score.missing.is_empty() { "<bold><blue>" } else { "" },
                                 seed,
-                                if score > -10000. { "<bold><green>" } else { "" },
+                                if seed_score.missing.is_empty() { "<bold><green>" } else { "" },
                                 score,
                                 roll_started.elapsed()
                             );
+                            let seed_roll = SeedRoll::new(
+                                seed,
+                                &hash,
+                                &plan_name,
+                                &inputs,
+                                scoring.as_deref(),
+                                &seed_score,
+                                roll_started.elapsed().as_millis() as u64,
+                            );
+                            if let Err(err) = seed_db.lock().await.insert(seed_roll) {
+                                warn!("failed to store roll of seed {}: {}", seed, err);
+                            }
                         },
                         Err(err) => {
                             warn!("instance #{} rolled #{} with seed {} but failed: {}", p + 1,
                                   roll,seed, err);
+                            let seed_roll = SeedRoll::failed(
+                                seed,
+                                &hash,
+                                &plan_name,
+                                &inputs,
+                                scoring.as_deref(),
+                                &err.to_string(),
+                                roll_started.elapsed().as_millis() as u64,
+                            );
+                            if let Err(err) = seed_db.lock().await.insert(seed_roll) {
+                                warn!("failed to store roll of seed {}: {}", seed, err);
+                            }
                         }
                     }
                     child.kill().expect("failed to kill child");
@@ -177,45 +245,206 @@ pub async fn roll_seed(
     Ok(*best_seed_with_score)
 }
 
+/// every scored seed is kept here, see `SeedDb`
+pub fn seed_db_path(workspace_path: &str) -> PathBuf {
+    Path::new(workspace_path).join("seeds.jsonl")
+}
+
+/// weights of the seed score, read from `[seed_score]` in the settings. every heuristic adds
+/// weight * value, distances, cliffs, enemies and the plan makespan count negative
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedHeuristics {
+    // every one of these has to be found within search_radius
+    pub resources: Vec<String>,
+    pub search_radius: f64,
+    // around spawn for water, cliffs and enemies
+    pub spawn_radius: f64,
+    // per resource not found
+    pub missing_resource: f64,
+    // per tile to the nearest entity of each resource
+    pub resource_distance: f64,
+    // patch score of the best patch of every ore, damped by its distance
+    pub patch_score: f64,
+    // per tile of the best patch of every ore
+    pub patch_size: f64,
+    // per 1000 ore in the best patch of every ore
+    pub patch_richness: f64,
+    // per tile to the nearest water, spawn_radius if there is none
+    pub water_distance: f64,
+    // per cliff within spawn_radius
+    pub cliffs: f64,
+    // per spawner or worm within spawn_radius
+    pub enemies: f64,
+    // per unit of the plan's shortest path
+    pub makespan: f64,
+    // result of the plan's score(world) function
+    pub lua: f64,
+}
+
+impl Default for SeedHeuristics {
+    fn default() -> Self {
+        SeedHeuristics {
+            resources: vec![
+                "rock-huge".into(),
+                EntityName::IronOre.to_string(),
+                EntityName::Coal.to_string(),
+                EntityName::CopperOre.to_string(),
+                EntityName::Stone.to_string(),
+                EntityName::CrudeOil.to_string(),
+            ],
+            search_radius: 3000.,
+            spawn_radius: 200.,
+            missing_resource: 10000.,
+            resource_distance: 0.,
+            patch_score: 1.,
+            patch_size: 0.,
+            patch_richness: 0.,
+            water_distance: 0.,
+            cliffs: 0.,
+            enemies: 0.,
+            makespan: 1.,
+            lua: 1.,
+        }
+    }
+}
+
+/// `[seed_score]` from the settings or `[seed_score.<name>]` for other speedrun categories
+pub fn seed_heuristics(settings: &Config, name: Option<&str>) -> anyhow::Result<SeedHeuristics> {
+    let key = match name {
+        Some(name) => format!("seed_score.{}", name),
+        None => "seed_score".into(),
+    };
+    match settings.get::<SeedHeuristics>(&key) {
+        Ok(heuristics) => Ok(heuristics),
+        Err(ConfigError::NotFound(_)) if name.is_none() => Ok(SeedHeuristics::default()),
+        Err(err) => Err(anyhow!("invalid {}: {}", key, err)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedScore {
+    pub score: f64,
+    pub missing: Vec<String>,
+    // weighted value of every heuristic
+    pub parts: BTreeMap<String, f64>,
+}
+
 pub async fn score_seed(
     rcon: Arc<FactorioRcon>,
     world: Arc<FactorioWorld>,
     _seed: u32,
     lua_code: &str,
     bot_count: u32,
-) -> anyhow::Result<f64> {
-    let mut planner = Planner::new(world, Some(rcon.clone()));
+    heuristics: &SeedHeuristics,
+) -> anyhow::Result<SeedScore> {
+    let mut planner = Planner::new(world.clone(), Some(rcon.clone()));
     planner.plan(lua_code.into(), bot_count)?;
-    let mut score = 0.0;
+    let center = Position::new(0., 0.);
+    let mut nearest: BTreeMap<String, Option<f64>> = BTreeMap::new();
+    for resource in &heuristics.resources {
+        let entities = find_nearest_entities(
+            rcon.clone(),
+            &center,
+            heuristics.search_radius,
+            Some(resource.clone()),
+            None,
+        )
+        .await?;
+        nearest.insert(
+            resource.clone(),
+            entities
+                .first()
+                .map(|entity| calculate_distance(&center, &entity.position)),
+        );
+    }
+    let seed_score = score_world(
+        &world,
+        heuristics,
+        &nearest,
+        planner.graph().shortest_path(),
+        planner.lua_score(),
+    );
+    if !seed_score.score.is_finite() {
+        bail!("score is {}: {:?}", seed_score.score, seed_score.parts);
+    }
+    Ok(seed_score)
+}
 
-    let weight = planner.graph().shortest_path();
-    score -= weight;
+/// scores the world around spawn, `nearest` holds the distance to every resource of the
+/// heuristics or None if it was not found
+pub fn score_world(
+    world: &FactorioWorld,
+    heuristics: &SeedHeuristics,
+    nearest: &BTreeMap<String, Option<f64>>,
+    makespan: f64,
+    lua_score: Option<f64>,
+) -> SeedScore {
     let center = Position::new(0., 0.);
-    let resources = vec![
-        "rock-huge",
-        "iron-ore",
-        "coal",
-        "copper-ore",
-        "stone",
-        "crude-oil",
-    ];
-    for resource in resources {
-        let nearest =
-            find_nearest_entities(rcon.clone(), &center, 3000., Some(resource.into()), None)
-                .await?;
-        match nearest.is_empty() {
-            false => {
-                // info!("nearest {} @ {}/{}", resource, nearest.x(), nearest.y());
-                // score -= calculate_distance(&center, &nearest[0].position);
-            }
-            true => {
-                // warn!("not found: {}", resource);
-                score -= 10000.;
+    let spawn_area = add_to_rect(
+        &Rect::from_wh(heuristics.spawn_radius * 2., heuristics.spawn_radius * 2.),
+        &center,
+    );
+    let mut parts: BTreeMap<String, f64> = BTreeMap::new();
+    let mut missing: Vec<String> = vec![];
+    let mut add = |name: &str, value: f64| {
+        if value != 0. {
+            *parts.entry(name.into()).or_insert(0.) += value;
+        }
+    };
+
+    for resource in &heuristics.resources {
+        match nearest.get(resource).cloned().flatten() {
+            Some(distance) => add("resourceDistance", -heuristics.resource_distance * distance),
+            None => {
+                missing.push(resource.clone());
+                add("missingResource", -heuristics.missing_resource);
             }
         }
+        // rich and compact ore patches near spawn are worth more than big ones far away
+        if let Some(patch) = world.entity_graph.resource_patches(resource).first() {
+            let distance = calculate_distance(&center, &patch.centroid());
+            add(
+                "patchScore",
+                heuristics.patch_score * patch.score() / (1. + distance / 100.),
+            );
+            add(
+                "patchSize",
+                heuristics.patch_size * patch.elements.len() as f64,
+            );
+            add(
+                "patchRichness",
+                heuristics.patch_richness * patch.total_amount() as f64 / 1000.,
+            );
+        }
+    }
+    let water = world
+        .entity_graph
+        .tile_tree()
+        .query(spawn_area.clone().into())
+        .into_iter()
+        .filter(|(tile, _, _)| tile.player_collidable && tile.name.contains("water"))
+        .map(|(tile, _, _)| {
+            calculate_distance(
+                &center,
+                &Position::new(tile.position.x() + 0.5, tile.position.y() + 0.5),
+            )
+        })
+        .fold(heuristics.spawn_radius, f64::min);
+    add("waterDistance", -heuristics.water_distance * water);
+    let cliffs = world.entity_graph.cliffs_in(&spawn_area).len();
+    add("cliffs", -heuristics.cliffs * cliffs as f64);
+    let enemies = world.entity_graph.enemies_in(&spawn_area).len();
+    add("enemies", -heuristics.enemies * enemies as f64);
+    add("makespan", -heuristics.makespan * makespan);
+    if let Some(lua_score) = lua_score {
+        add("lua", heuristics.lua * lua_score);
+    }
+    SeedScore {
+        score: parts.values().sum::<f64>().floor(),
+        missing,
+        parts,
     }
-    // info!("scored {} in <yellow>{:?}</>", seed, started.elapsed());
-    Ok(score.floor())
 }
 
 pub async fn find_nearest_entities(
@@ -245,3 +474,72 @@ pub async fn find_nearest_entities(
     });
     Ok(entities)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::types::EntityType;
+
+    use super::*;
+
+    #[test]
+    fn test_score_world() {
+        let world = Arc::new(fixture_world());
+        let mut planner = Planner::new(world.clone(), None);
+        planner
+            .plan(
+                r##"
+    function score(world)
+        return #world.resourcePatches("iron-ore") * 42
+    end
+        "##
+                .into(),
+                1,
+            )
+            .unwrap();
+        assert_eq!(planner.lua_score(), Some(42.));
+
+        let heuristics = SeedHeuristics {
+            resources: vec![
+                EntityName::IronOre.to_string(),
+                EntityName::UraniumOre.to_string(),
+            ],
+            water_distance: 1.,
+            ..Default::default()
+        };
+        let mut nearest: BTreeMap<String, Option<f64>> = BTreeMap::new();
+        nearest.insert(EntityName::IronOre.to_string(), Some(0.));
+        nearest.insert(EntityName::UraniumOre.to_string(), None);
+        let score = score_world(&world, &heuristics, &nearest, 5., planner.lua_score());
+        assert_eq!(score.missing, vec![EntityName::UraniumOre.to_string()]);
+        assert_eq!(score.parts["missingResource"], -10000.);
+        assert_eq!(score.parts["makespan"], -5.);
+        assert_eq!(score.parts["lua"], 42.);
+        assert!(score.parts["patchScore"] > 0.);
+        // the lake starts at 38,38
+        assert!((score.parts["waterDistance"] + 38.5 * 2f64.sqrt()).abs() < 1e-6);
+        assert!(!score.parts.contains_key("cliffs"));
+        assert_eq!(score.score, score.parts.values().sum::<f64>().floor());
+
+        let cliff_position = Position::new(6., 6.);
+        world
+            .entity_graph
+            .add(
+                vec![FactorioEntity {
+                    name: "cliff".into(),
+                    entity_type: EntityType::Cliff.to_string(),
+                    bounding_box: add_to_rect(&Rect::from_wh(4., 2.), &cliff_position),
+                    position: cliff_position,
+                    ..Default::default()
+                }],
+                None,
+            )
+            .unwrap();
+        let heuristics = SeedHeuristics {
+            cliffs: 3.,
+            ..heuristics
+        };
+        let score = score_world(&world, &heuristics, &nearest, 5., None);
+        assert_eq!(score.parts["cliffs"], -3.);
+    }
+}
@@ -0,0 +1,342 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::factorio::roll_best_seed::{SeedHeuristics, SeedScore};
+
+/// one scored seed as stored in the roll database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedRoll {
+    pub seed: u32,
+    pub map_hash: String,
+    pub plan_name: String,
+    // plan code, heuristics and bot count, see `inputs_hash`
+    #[serde(default)]
+    pub inputs_hash: String,
+    pub scoring: Option<String>,
+    pub score: f64,
+    pub missing: Vec<String>,
+    pub parts: BTreeMap<String, f64>,
+    pub duration_ms: u64,
+    pub rolled_at: u64, // unix timestamp
+    // set if the seed could not be scored, score is 0 then
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl SeedRoll {
+    pub fn new(
+        seed: u32,
+        map_hash: &str,
+        plan_name: &str,
+        inputs_hash: &str,
+        scoring: Option<&str>,
+        seed_score: &SeedScore,
+        duration_ms: u64,
+    ) -> SeedRoll {
+        SeedRoll {
+            seed,
+            map_hash: map_hash.into(),
+            plan_name: plan_name.into(),
+            inputs_hash: inputs_hash.into(),
+            scoring: scoring.map(|scoring| scoring.into()),
+            score: seed_score.score,
+            missing: seed_score.missing.clone(),
+            parts: seed_score.parts.clone(),
+            duration_ms,
+            rolled_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            error: None,
+        }
+    }
+
+    /// a seed which failed to score, kept so a resumed run does not roll it again
+    pub fn failed(
+        seed: u32,
+        map_hash: &str,
+        plan_name: &str,
+        inputs_hash: &str,
+        scoring: Option<&str>,
+        error: &str,
+        duration_ms: u64,
+    ) -> SeedRoll {
+        let seed_score = SeedScore {
+            score: 0.,
+            missing: vec![],
+            parts: BTreeMap::new(),
+        };
+        SeedRoll {
+            error: Some(error.into()),
+            ..SeedRoll::new(
+                seed,
+                map_hash,
+                plan_name,
+                inputs_hash,
+                scoring,
+                &seed_score,
+                duration_ms,
+            )
+        }
+    }
+
+    fn same_inputs(
+        &self,
+        map_hash: &str,
+        plan_name: &str,
+        inputs_hash: &str,
+        scoring: Option<&str>,
+    ) -> bool {
+        self.map_hash == map_hash
+            && self.plan_name == plan_name
+            && self.inputs_hash == inputs_hash
+            && self.scoring.as_deref() == scoring
+    }
+}
+
+/// every seed ever rolled, one json line per roll so an interrupted run loses at most the roll
+/// it was writing
+pub struct SeedDb {
+    path: PathBuf,
+    rolls: Vec<SeedRoll>,
+}
+
+impl SeedDb {
+    pub fn open(path: &Path) -> anyhow::Result<SeedDb> {
+        let mut rolls: Vec<SeedRoll> = vec![];
+        if path.exists() {
+            let content = read_to_string(path)?;
+            for (index, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(line) {
+                    Ok(roll) => rolls.push(roll),
+                    Err(err) => warn!("skipping line {} of {}: {}", index + 1, path.display(), err),
+                }
+            }
+            // terminate a line cut off by an interrupted run so the next roll starts fresh
+            if !content.is_empty() && !content.ends_with('\n') {
+                writeln!(OpenOptions::new().append(true).open(path)?)?;
+            }
+        }
+        Ok(SeedDb {
+            path: path.into(),
+            rolls,
+        })
+    }
+
+    pub fn insert(&mut self, roll: SeedRoll) -> anyhow::Result<()> {
+        if !roll.score.is_finite() {
+            bail!("seed {} scored {}", roll.seed, roll.score);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&roll)?)?;
+        self.rolls.push(roll);
+        Ok(())
+    }
+
+    pub fn contains(
+        &self,
+        seed: u32,
+        map_hash: &str,
+        plan_name: &str,
+        inputs_hash: &str,
+        scoring: Option<&str>,
+    ) -> bool {
+        self.rolls.iter().any(|roll| {
+            roll.seed == seed && roll.same_inputs(map_hash, plan_name, inputs_hash, scoring)
+        })
+    }
+
+    /// rolls in the order they were scored, optionally only those of one map and plan
+    pub fn list(&self, map_hash: Option<&str>, plan_name: Option<&str>) -> Vec<&SeedRoll> {
+        self.rolls
+            .iter()
+            .filter(|roll| map_hash.map_or(true, |map_hash| roll.map_hash == map_hash))
+            .filter(|roll| plan_name.map_or(true, |plan_name| roll.plan_name == plan_name))
+            .collect()
+    }
+
+    /// best rolls first, failed rolls are left out
+    pub fn top(
+        &self,
+        map_hash: Option<&str>,
+        plan_name: Option<&str>,
+        count: usize,
+    ) -> Vec<&SeedRoll> {
+        let mut rolls = self.list(map_hash, plan_name);
+        rolls.retain(|roll| roll.error.is_none());
+        rolls.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        rolls.truncate(count);
+        rolls
+    }
+
+    /// previous rolls which a run with these inputs continues from
+    pub fn resumable(
+        &self,
+        map_hash: &str,
+        plan_name: &str,
+        inputs_hash: &str,
+        scoring: Option<&str>,
+    ) -> Vec<&SeedRoll> {
+        self.rolls
+            .iter()
+            .filter(|roll| roll.same_inputs(map_hash, plan_name, inputs_hash, scoring))
+            .collect()
+    }
+}
+
+/// stable across runs and platforms unlike `DefaultHasher`, whitespace is ignored because map
+/// exchange strings are often pasted with line breaks
+pub fn map_hash(map_exchange_string: &str) -> String {
+    fnv_hash(
+        map_exchange_string
+            .bytes()
+            .filter(|byte| !byte.is_ascii_whitespace()),
+    )
+}
+
+/// everything besides the map which changes a score, so editing the plan or the weights starts
+/// a fresh run instead of resuming
+pub fn inputs_hash(
+    plan_code: &str,
+    heuristics: &SeedHeuristics,
+    bot_count: u32,
+) -> anyhow::Result<String> {
+    let heuristics = serde_json::to_string(heuristics)?;
+    Ok(fnv_hash(
+        plan_code
+            .bytes()
+            .chain(std::iter::once(0))
+            .chain(heuristics.bytes())
+            .chain(bot_count.to_le_bytes().iter().copied()),
+    ))
+}
+
+fn fnv_hash(bytes: impl Iterator<Item = u8>) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_db() {
+        let path = std::env::temp_dir().join(format!("seeds-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let hash = map_hash(">>>abc\ndef<<<");
+        assert_eq!(hash, map_hash(">>>abc def<<<"));
+        assert_ne!(hash, map_hash(">>>abcdeg<<<"));
+
+        let inputs = inputs_hash("plan()", &SeedHeuristics::default(), 2).unwrap();
+        assert_ne!(
+            inputs,
+            inputs_hash("plan()", &SeedHeuristics::default(), 3).unwrap()
+        );
+        let other_inputs = inputs_hash(
+            "plan()",
+            &SeedHeuristics {
+                cliffs: 1.,
+                ..Default::default()
+            },
+            2,
+        )
+        .unwrap();
+        assert_ne!(inputs, other_inputs);
+
+        let mut db = SeedDb::open(&path).unwrap();
+        for (seed, score) in &[(1, -5.), (2, 10.), (3, 3.)] {
+            let seed_score = SeedScore {
+                score: *score,
+                missing: vec![],
+                parts: BTreeMap::new(),
+            };
+            db.insert(SeedRoll::new(
+                *seed,
+                &hash,
+                "plan",
+                &inputs,
+                None,
+                &seed_score,
+                100,
+            ))
+            .unwrap();
+        }
+        let nan_score = SeedScore {
+            score: f64::NAN,
+            missing: vec![],
+            parts: BTreeMap::new(),
+        };
+        assert!(db
+            .insert(SeedRoll::new(
+                6, &hash, "plan", &inputs, None, &nan_score, 100
+            ))
+            .is_err());
+        // an interrupted write leaves half a line behind
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"seed\":4,").unwrap();
+        drop(file);
+
+        let mut db = SeedDb::open(&path).unwrap();
+        db.insert(SeedRoll::new(
+            5,
+            &hash,
+            "plan",
+            &inputs,
+            None,
+            &SeedScore {
+                score: 0.,
+                missing: vec![],
+                parts: BTreeMap::new(),
+            },
+            100,
+        ))
+        .unwrap();
+        db.insert(SeedRoll::failed(
+            7,
+            &hash,
+            "plan",
+            &inputs,
+            None,
+            "failed to start",
+            100,
+        ))
+        .unwrap();
+        let db = SeedDb::open(&path).unwrap();
+        assert!(db.contains(5, &hash, "plan", &inputs, None));
+        assert!(db.contains(2, &hash, "plan", &inputs, None));
+        assert!(db.contains(7, &hash, "plan", &inputs, None));
+        assert!(!db.contains(2, &hash, "plan", &inputs, Some("any-percent")));
+        assert!(!db.contains(2, &hash, "plan", &other_inputs, None));
+        assert!(!db.contains(4, &hash, "plan", &inputs, None));
+        assert!(!db.contains(6, &hash, "plan", &inputs, None));
+        assert_eq!(db.resumable(&hash, "plan", &inputs, None).len(), 5);
+        assert!(db.resumable(&hash, "plan", &other_inputs, None).is_empty());
+        assert_eq!(
+            db.list(None, None).last().unwrap().error.as_deref(),
+            Some("failed to start")
+        );
+        let top: Vec<u32> = db
+            .top(Some(&hash), None, 5)
+            .iter()
+            .map(|r| r.seed)
+            .collect();
+        assert_eq!(top, vec![2, 3, 5, 1]);
+        assert!(db.list(Some(&map_hash("other")), None).is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
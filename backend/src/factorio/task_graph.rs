@@ -1,6 +1,6 @@
 use crate::factorio::util::format_dotgraph;
 use crate::num_traits::FromPrimitive;
-use crate::types::{Direction, FactorioEntity, Position};
+use crate::types::{CircuitConnector, Direction, FactorioEntity, Position, TrainScheduleRecord};
 use noisy_float::types::{r64, R64};
 use num_traits::ToPrimitive;
 use petgraph::algo::astar;
@@ -107,6 +107,71 @@ impl TaskGraph {
         self.add_to_group(player_id, node, cost);
     }
 
+    pub fn add_insert_to_inventory_node(
+        &mut self,
+        player_id: u32,
+        cost: f64,
+        location: InventoryLocation,
+        item: InventoryItem,
+    ) {
+        let node = self
+            .inner
+            .add_node(TaskNode::new_insert_to_inventory(player_id, location, item));
+        self.add_to_group(player_id, node, cost);
+    }
+
+    pub fn add_remove_from_inventory_node(
+        &mut self,
+        player_id: u32,
+        cost: f64,
+        location: InventoryLocation,
+        item: InventoryItem,
+    ) {
+        let node = self.inner.add_node(TaskNode::new_remove_from_inventory(
+            player_id, location, item,
+        ));
+        self.add_to_group(player_id, node, cost);
+    }
+
+    pub fn add_craft_node(&mut self, player_id: u32, cost: f64, item: InventoryItem) {
+        let node = self.inner.add_node(TaskNode::new_craft(player_id, item));
+        self.add_to_group(player_id, node, cost);
+    }
+
+    pub fn add_research_node(&mut self, player_id: u32, technology: &str) {
+        let node = self
+            .inner
+            .add_node(TaskNode::new_research(player_id, technology));
+        self.add_to_group(player_id, node, 0.);
+    }
+
+    pub fn add_connect_wire_node(
+        &mut self,
+        player_id: u32,
+        cost: f64,
+        wire: &str,
+        from: CircuitConnector,
+        to: CircuitConnector,
+    ) {
+        let node = self
+            .inner
+            .add_node(TaskNode::new_connect_wire(player_id, wire, from, to));
+        self.add_to_group(player_id, node, cost);
+    }
+
+    pub fn add_set_train_schedule_node(
+        &mut self,
+        player_id: u32,
+        cost: f64,
+        locomotive: Position,
+        records: Vec<TrainScheduleRecord>,
+    ) {
+        let node = self.inner.add_node(TaskNode::new_set_train_schedule(
+            player_id, locomotive, records,
+        ));
+        self.add_to_group(player_id, node, cost);
+    }
+
     pub fn weight(&self, start: NodeIndex, goal: NodeIndex) -> R64 {
         let (weight, _) = self.astar(start, goal).expect("failed to find path");
         r64(weight)
@@ -214,7 +279,7 @@ impl PositionRadius {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MineTarget {
     pub position: Position,
     pub name: String,
@@ -229,6 +294,9 @@ pub enum TaskData {
     InsertToInventory(InventoryLocation, InventoryItem),
     RemoveFromInventory(InventoryLocation, InventoryItem),
     PlaceEntity(FactorioEntity),
+    Research(String),
+    ConnectWire(String, CircuitConnector, CircuitConnector),
+    SetTrainSchedule(Position, Vec<TrainScheduleRecord>),
 }
 
 #[derive(Default, Clone)]
@@ -309,6 +377,58 @@ impl TaskNode {
             Some(TaskData::InsertToInventory(location, item)),
         )
     }
+    pub fn new_remove_from_inventory(
+        player_id: u32,
+        location: InventoryLocation,
+        item: InventoryItem,
+    ) -> TaskNode {
+        TaskNode::new(
+            Some(player_id),
+            &*format!(
+                "Remove {}x{} from {} at {}",
+                &item.name, &item.count, location.entity_name, location.position
+            ),
+            Some(TaskData::RemoveFromInventory(location, item)),
+        )
+    }
+    pub fn new_research(player_id: u32, technology: &str) -> TaskNode {
+        TaskNode::new(
+            Some(player_id),
+            &*format!("Research {}", technology),
+            Some(TaskData::Research(technology.into())),
+        )
+    }
+    pub fn new_connect_wire(
+        player_id: u32,
+        wire: &str,
+        from: CircuitConnector,
+        to: CircuitConnector,
+    ) -> TaskNode {
+        TaskNode::new(
+            Some(player_id),
+            &*format!(
+                "Connect {} wire from {} at {} to {} at {}",
+                wire, from.entity_name, from.position, to.entity_name, to.position
+            ),
+            Some(TaskData::ConnectWire(wire.into(), from, to)),
+        )
+    }
+    pub fn new_set_train_schedule(
+        player_id: u32,
+        locomotive: Position,
+        records: Vec<TrainScheduleRecord>,
+    ) -> TaskNode {
+        let stations: Vec<String> = records.iter().map(|r| r.station.clone()).collect();
+        TaskNode::new(
+            Some(player_id),
+            &*format!(
+                "Schedule train at {} to {}",
+                locomotive,
+                stations.join(", ")
+            ),
+            Some(TaskData::SetTrainSchedule(locomotive, records)),
+        )
+    }
 }
 
 impl std::fmt::Display for TaskNode {
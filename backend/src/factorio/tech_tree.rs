@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::types::{FactorioForce, FactorioTechnology};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResearchPlan {
+    pub target: String,
+    // technologies to research in order, already researched ones are skipped
+    pub order: Vec<String>,
+    // total science packs needed by item name
+    pub science_packs: BTreeMap<String, u64>,
+    pub unlocks_recipes: Vec<String>,
+}
+
+pub struct TechTree<'a> {
+    force: &'a FactorioForce,
+}
+
+impl<'a> TechTree<'a> {
+    pub fn new(force: &'a FactorioForce) -> TechTree<'a> {
+        TechTree { force }
+    }
+
+    pub fn technology(&self, name: &str) -> anyhow::Result<&FactorioTechnology> {
+        self.force
+            .technologies
+            .get(name)
+            .ok_or_else(|| anyhow!("technology {} not found", name))
+    }
+
+    pub fn is_researched(&self, name: &str) -> bool {
+        self.force
+            .technologies
+            .get(name)
+            .map(|technology| technology.researched)
+            .unwrap_or(false)
+    }
+
+    /// prerequisites before dependants, depth first so the target is always last
+    pub fn research_order(&self, target: &str) -> anyhow::Result<Vec<String>> {
+        let mut order: Vec<String> = vec![];
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut in_progress: HashSet<String> = HashSet::new();
+        self.visit(target, &mut order, &mut visited, &mut in_progress)?;
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        name: &str,
+        order: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+        in_progress: &mut HashSet<String>,
+    ) -> anyhow::Result<()> {
+        if visited.contains(name) || self.is_researched(name) {
+            return Ok(());
+        }
+        if in_progress.contains(name) {
+            bail!("cyclic technology prerequisites at {}", name);
+        }
+        in_progress.insert(name.into());
+        let technology = self.technology(name)?;
+        if let Some(prerequisites) = &technology.prerequisites {
+            let mut prerequisites = prerequisites.clone();
+            prerequisites.sort();
+            for prerequisite in prerequisites {
+                self.visit(&prerequisite, order, visited, in_progress)?;
+            }
+        }
+        in_progress.remove(name);
+        visited.insert(name.into());
+        order.push(name.into());
+        Ok(())
+    }
+
+    pub fn science_packs(&self, order: &[String]) -> anyhow::Result<BTreeMap<String, u64>> {
+        let mut science_packs: BTreeMap<String, u64> = BTreeMap::new();
+        for name in order {
+            let technology = self.technology(name)?;
+            for ingredient in &technology.research_unit_ingredients {
+                *science_packs.entry(ingredient.name.clone()).or_insert(0) +=
+                    ingredient.amount as u64 * technology.research_unit_count;
+            }
+        }
+        Ok(science_packs)
+    }
+
+    pub fn plan(&self, target: &str) -> anyhow::Result<ResearchPlan> {
+        let order = self.research_order(target)?;
+        let science_packs = self.science_packs(&order)?;
+        let mut unlocks_recipes: Vec<String> = vec![];
+        for name in &order {
+            if let Some(recipes) = &self.technology(name)?.unlocks_recipes {
+                unlocks_recipes.extend(recipes.iter().cloned());
+            }
+        }
+        Ok(ResearchPlan {
+            target: target.into(),
+            order,
+            science_packs,
+            unlocks_recipes,
+        })
+    }
+
+    /// technologies which may be researched right now
+    pub fn available(&self) -> Vec<String> {
+        self.force
+            .technologies
+            .values()
+            .filter(|technology| {
+                technology.enabled
+                    && !technology.researched
+                    && technology
+                        .prerequisites
+                        .as_ref()
+                        .map(|prerequisites| prerequisites.iter().all(|p| self.is_researched(p)))
+                        .unwrap_or(true)
+            })
+            .map(|technology| technology.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noisy_float::types::r64;
+
+    use crate::types::FactorioIngredient;
+
+    use super::*;
+
+    fn technology(
+        name: &str,
+        prerequisites: &[&str],
+        packs: &[(&str, u32)],
+        count: u64,
+        researched: bool,
+    ) -> FactorioTechnology {
+        FactorioTechnology {
+            name: name.into(),
+            enabled: true,
+            upgrade: false,
+            researched,
+            prerequisites: if prerequisites.is_empty() {
+                None
+            } else {
+                Some(prerequisites.iter().map(|p| p.to_string()).collect())
+            },
+            research_unit_ingredients: packs
+                .iter()
+                .map(|(name, amount)| FactorioIngredient {
+                    name: name.to_string(),
+                    ingredient_type: "item".into(),
+                    amount: *amount,
+                })
+                .collect(),
+            research_unit_count: count,
+            research_unit_energy: Box::new(r64(15.)),
+            order: name.into(),
+            level: 1,
+            valid: true,
+            unlocks_recipes: Some(vec![format!("{}-recipe", name)]),
+        }
+    }
+
+    fn fixture_force() -> FactorioForce {
+        let mut technologies: BTreeMap<String, FactorioTechnology> = BTreeMap::new();
+        for technology in vec![
+            technology(
+                "automation",
+                &[],
+                &[("automation-science-pack", 1)],
+                10,
+                true,
+            ),
+            technology(
+                "logistics",
+                &[],
+                &[("automation-science-pack", 1)],
+                20,
+                false,
+            ),
+            technology(
+                "electronics",
+                &["automation"],
+                &[("automation-science-pack", 1)],
+                30,
+                false,
+            ),
+            technology(
+                "logistic-science-pack",
+                &[],
+                &[("automation-science-pack", 1)],
+                75,
+                false,
+            ),
+            technology(
+                "automation-2",
+                &["electronics", "logistic-science-pack", "logistics"],
+                &[("automation-science-pack", 1), ("logistic-science-pack", 1)],
+                40,
+                false,
+            ),
+        ] {
+            technologies.insert(technology.name.clone(), technology);
+        }
+        FactorioForce {
+            name: "player".into(),
+            force_id: 1,
+            current_research: None,
+            research_progress: None,
+            technologies: Box::new(technologies),
+            research_queue: None,
+        }
+    }
+
+    #[test]
+    fn test_research_plan() {
+        let force = fixture_force();
+        let tree = TechTree::new(&force);
+        let plan = tree.plan("automation-2").unwrap();
+        assert_eq!(
+            plan.order,
+            vec![
+                "electronics",
+                "logistic-science-pack",
+                "logistics",
+                "automation-2"
+            ]
+        );
+        assert_eq!(
+            plan.science_packs.get("automation-science-pack"),
+            Some(&(30 + 75 + 20 + 40))
+        );
+        assert_eq!(plan.science_packs.get("logistic-science-pack"), Some(&40));
+        assert_eq!(plan.unlocks_recipes.len(), 4);
+        assert!(tree.plan("unknown").is_err());
+        assert_eq!(
+            tree.available(),
+            vec!["electronics", "logistic-science-pack", "logistics"]
+        );
+    }
+}
@@ -8,7 +8,8 @@ use crate::factorio::entity_graph::EntityGraph;
 use crate::factorio::util::{add_to_rect, rect_fields};
 use crate::factorio::world::FactorioWorld;
 use crate::types::{
-    Direction, EntityName, FactorioItemPrototype, FactorioRecipe, FactorioTile, Position, Rect,
+    Direction, EntityName, EntityType, FactorioItemPrototype, FactorioRecipe, FactorioTile,
+    Position, Rect,
 };
 #[cfg(test)]
 use crate::types::{FactorioEntity, FactorioEntityPrototype};
@@ -17,7 +18,7 @@ use imageproc::drawing::draw_hollow_rect_mut;
 
 pub fn entity_graph_from(entities: Vec<FactorioEntity>) -> anyhow::Result<EntityGraph> {
     let prototypes = fixture_entity_prototypes();
-    let graph = EntityGraph::new(Arc::new(prototypes), Arc::new(DashMap::new()));
+    let graph = EntityGraph::new(Arc::new(prototypes), Arc::new(fixture_recipes()));
     graph.add(entities, None)?;
     graph.connect()?;
     Ok(graph)
@@ -102,6 +103,23 @@ pub fn spawn_water(tiles: &mut Vec<FactorioTile>, rect: Rect) {
     }
 }
 
+/// entity without a prototype lookup, its bounding box stays just inside its tile
+pub fn fixture_entity(
+    name: &str,
+    entity_type: EntityType,
+    position: &Position,
+    direction: u8,
+) -> FactorioEntity {
+    FactorioEntity {
+        name: name.into(),
+        entity_type: entity_type.to_string(),
+        bounding_box: add_to_rect(&Rect::from_wh(0.8, 0.8), position),
+        position: position.clone(),
+        direction,
+        ..Default::default()
+    }
+}
+
 pub fn fixture_world() -> FactorioWorld {
     let world = FactorioWorld::new();
     let entity_prototypes: Vec<FactorioEntityPrototype> = fixture_entity_prototypes()
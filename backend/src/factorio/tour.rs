@@ -0,0 +1,136 @@
+use crate::factorio::util::calculate_distance;
+use crate::types::Position;
+
+// 2-opt stops after this many passes even if it still finds improvements
+const MAX_IMPROVEMENT_PASSES: usize = 50;
+
+/// walking distance from start through the targets in the given order
+pub fn tour_length(start: &Position, targets: &[Position], order: &[usize]) -> f64 {
+    let mut length = 0.;
+    let mut position = start;
+    for index in order {
+        length += calculate_distance(position, &targets[*index]);
+        position = &targets[*index];
+    }
+    length
+}
+
+/// order to visit all targets from start, nearest neighbour improved by 2-opt.
+/// the tour ends at the last target instead of returning to the start
+pub fn optimize_tour(start: &Position, targets: &[Position]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..targets.len()).collect();
+    let mut order: Vec<usize> = vec![];
+    let mut position = start;
+    while !remaining.is_empty() {
+        let (index, _) = remaining
+            .iter()
+            .enumerate()
+            .map(|(index, target)| (index, calculate_distance(position, &targets[*target])))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        let target = remaining.swap_remove(index);
+        order.push(target);
+        position = &targets[target];
+    }
+    improve_tour(start, targets, &mut order);
+    order
+}
+
+/// reverses segments of the tour as long as that makes it shorter
+fn improve_tour(start: &Position, targets: &[Position], order: &mut Vec<usize>) {
+    let at = |order: &Vec<usize>, index: usize| {
+        if index == 0 {
+            start
+        } else {
+            &targets[order[index - 1]]
+        }
+    };
+    // positions 1..=n are the targets, 0 is the start
+    let n = order.len();
+    for _ in 0..MAX_IMPROVEMENT_PASSES {
+        let mut improved = false;
+        for i in 1..n {
+            for j in i + 1..=n {
+                let before = calculate_distance(at(order, i - 1), at(order, i));
+                let after = calculate_distance(at(order, i - 1), at(order, j));
+                let (before, after) = if j < n {
+                    (
+                        before + calculate_distance(at(order, j), at(order, j + 1)),
+                        after + calculate_distance(at(order, i), at(order, j + 1)),
+                    )
+                } else {
+                    (before, after)
+                };
+                if after < before - 1e-9 {
+                    order[i - 1..j].reverse();
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+/// splits the targets between several starting points: the tour which is shortest so far takes
+/// its closest remaining target, so every start clears its own corner. the tours are then
+/// improved separately
+pub fn split_tours(starts: &[Position], targets: &[Position]) -> Vec<Vec<usize>> {
+    let mut tours: Vec<Vec<usize>> = vec![vec![]; starts.len()];
+    if starts.is_empty() {
+        return tours;
+    }
+    let mut positions: Vec<&Position> = starts.iter().collect();
+    let mut lengths: Vec<f64> = vec![0.; starts.len()];
+    let mut remaining: Vec<usize> = (0..targets.len()).collect();
+    while !remaining.is_empty() {
+        let tour = (0..starts.len())
+            .min_by(|a, b| lengths[*a].partial_cmp(&lengths[*b]).unwrap())
+            .unwrap();
+        let (index, distance) = remaining
+            .iter()
+            .enumerate()
+            .map(|(index, target)| {
+                (
+                    index,
+                    calculate_distance(positions[tour], &targets[*target]),
+                )
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        let target = remaining.swap_remove(index);
+        tours[tour].push(target);
+        positions[tour] = &targets[target];
+        lengths[tour] += distance;
+    }
+    for (tour, start) in tours.iter_mut().zip(starts) {
+        improve_tour(start, targets, tour);
+    }
+    tours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize_tour() {
+        // nearest neighbour goes 1, -2, 5, -10 which crosses the start twice
+        let targets: Vec<Position> = vec![
+            Position::new(1., 0.),
+            Position::new(-2., 0.),
+            Position::new(5., 0.),
+            Position::new(-10., 0.),
+        ];
+        let start = Position::new(0., 0.);
+        assert_eq!(tour_length(&start, &targets, &[0, 1, 2, 3]), 26.);
+        let order = optimize_tour(&start, &targets);
+        assert_eq!(order.len(), targets.len());
+        assert_eq!(tour_length(&start, &targets, &order), 20.);
+
+        let starts = vec![Position::new(-20., 0.), Position::new(20., 0.)];
+        let tours = split_tours(&starts, &targets);
+        assert_eq!(tours, vec![vec![3, 1], vec![2, 0]]);
+    }
+}
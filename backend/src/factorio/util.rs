@@ -7,7 +7,7 @@ use factorio_blueprint::BlueprintCodec;
 use factorio_blueprint::Container::{Blueprint, BlueprintBook};
 use human_sort::compare;
 use itertools::Itertools;
-use num_traits::ToPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 use pathfinding::prelude::astar;
 use serde_json::Value;
 use std::cmp::Ordering;
@@ -87,6 +87,11 @@ pub fn move_position(pos: &Position, direction: Direction, offset: f64) -> Posit
     }
 }
 
+/// center of the tile whose left top corner is pos
+pub fn tile_center(pos: &Pos) -> Position {
+    Position::new(pos.0 as f64 + 0.5, pos.1 as f64 + 0.5)
+}
+
 pub fn move_pos(pos: &Pos, direction: Direction, offset: i32) -> Pos {
     match direction {
         Direction::North => Pos(pos.0, pos.1 - offset),
@@ -215,7 +220,12 @@ pub fn blueprint_build_area(
                         entity.position.y.to_f64().unwrap(),
                     );
                     let collision_box = expand_rect_floor_ceil_div_2(&prototype.collision_box);
-                    let collision_rect = add_to_rect(&collision_box, &entity_position);
+                    let direction = entity.direction.map(|d| d % 8).unwrap_or(0);
+                    let collision_rect = add_to_rect_turned(
+                        &collision_box,
+                        &entity_position,
+                        Direction::from_u8(direction).unwrap(),
+                    );
                     expand_rect(&mut build_area, &collision_rect)
                 }
             }
@@ -245,6 +255,7 @@ pub fn vector_multiply(a: &Position, len: f64) -> Position {
     Position::new(a.x() * len, a.y() * len)
 }
 
+/// rect spanned by a and b, grown by margin on every side
 pub fn span_rect(a: &Position, b: &Position, margin: f64) -> Rect {
     Rect::new(
         &Position::new(
@@ -0,0 +1,264 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use pathfinding::prelude::astar;
+
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::factorio::util::{add_to_rect, calculate_distance, span_rect, tile_center};
+use crate::types::{Pos, Position, Rect};
+
+// walks around obstacles wider than this are left to the game's pathfinder
+const SEARCH_MARGIN: f64 = 32.;
+// the character collision box is 0.4x0.4
+pub const CHARACTER_SIZE: f64 = 0.4;
+// costs are scaled by 10 so diagonal steps can be integers
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+// the least recently used half of the cache is dropped when it grows beyond this
+const MAX_CACHED_PATHS: u64 = 4096;
+
+struct CachedPath {
+    area: Rect,
+    waypoints: Option<Vec<Position>>,
+    last_used: u64,
+}
+
+/// A* on the tile grid using the blocked tree, which also contains water and other
+/// player_collidable tiles from the tile tree. rails and resources do not block walking
+pub struct WalkPathfinder {
+    entity_graph: Arc<EntityGraph>,
+    cache: DashMap<(Pos, Pos, u32), CachedPath>,
+    uses: AtomicU64,
+}
+
+impl WalkPathfinder {
+    pub fn new(entity_graph: Arc<EntityGraph>) -> Self {
+        WalkPathfinder {
+            entity_graph,
+            cache: DashMap::new(),
+            uses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// forgets all cached paths whose search area overlaps the changed rect
+    pub fn invalidate(&self, rect: &Rect) {
+        let rect: QuadTreeRect = rect.clone().into();
+        self.cache.retain(|_, cached| {
+            let area: QuadTreeRect = cached.area.clone().into();
+            !area.intersects(&rect)
+        });
+    }
+
+    /// waypoints ending within radius of goal, empty if start is already there
+    pub fn find_path(
+        &self,
+        start: &Position,
+        goal: &Position,
+        radius: f64,
+    ) -> Option<Vec<Position>> {
+        let key: (Pos, Pos, u32) = (start.into(), goal.into(), (radius * 10.).round() as u32);
+        let used = self.uses.fetch_add(1, Ordering::Relaxed);
+        if let Some(mut cached) = self.cache.get_mut(&key) {
+            cached.last_used = used;
+            return cached.waypoints.clone();
+        }
+        let area = span_rect(start, goal, SEARCH_MARGIN);
+        let waypoints = self.search(start, goal, radius, &area);
+        if self.cache.len() as u64 >= MAX_CACHED_PATHS {
+            let oldest_kept = used.saturating_sub(MAX_CACHED_PATHS / 2);
+            self.cache
+                .retain(|_, cached| cached.last_used >= oldest_kept);
+        }
+        self.cache.insert(
+            key,
+            CachedPath {
+                area,
+                waypoints: waypoints.clone(),
+                last_used: used,
+            },
+        );
+        waypoints
+    }
+
+    /// the local search treats unexplored chunks as walkable, so a path through them
+    /// has to come from the game instead
+    pub fn is_explored(&self, start: &Position, waypoints: &[Position]) -> bool {
+        let mut last = start;
+        for waypoint in waypoints {
+            let steps = calculate_distance(last, waypoint).ceil().max(1.);
+            for step in 0..=steps as u32 {
+                let t = step as f64 / steps;
+                let position = Position::new(
+                    last.x() + (waypoint.x() - last.x()) * t,
+                    last.y() + (waypoint.y() - last.y()) * t,
+                );
+                if !self.entity_graph.is_chunk_known(&position) {
+                    return false;
+                }
+            }
+            last = waypoint;
+        }
+        true
+    }
+
+    /// length of the walking path, the straight line if there is none
+    pub fn walk_distance(&self, start: &Position, goal: &Position, radius: f64) -> f64 {
+        match self.find_path(start, goal, radius) {
+            Some(waypoints) => path_length(start, &waypoints),
+            None => calculate_distance(start, goal),
+        }
+    }
+
+    fn search(
+        &self,
+        start: &Position,
+        goal: &Position,
+        radius: f64,
+        area: &Rect,
+    ) -> Option<Vec<Position>> {
+        let blocked_tree = self.entity_graph.blocked_tree();
+        let is_walkable = |pos: &Pos| {
+            let center = tile_center(pos);
+            area.contains(&center)
+                && blocked_tree
+                    .query(
+                        add_to_rect(&Rect::from_wh(CHARACTER_SIZE, CHARACTER_SIZE), &center).into(),
+                    )
+                    .is_empty()
+        };
+        // the tile center may be up to 0.71 away from goal
+        let radius = radius.max(0.75);
+        let goal_pos: Pos = goal.into();
+        let start_pos: Pos = start.into();
+        let (path, _cost) = astar(
+            &start_pos,
+            |pos| {
+                let mut successors: Vec<(Pos, u32)> = vec![];
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        if dx == 0 && dy == 0 {
+                            continue;
+                        }
+                        let next = Pos(pos.0 + dx, pos.1 + dy);
+                        if !is_walkable(&next) {
+                            continue;
+                        }
+                        if dx != 0 && dy != 0 {
+                            // no cutting corners of blocked tiles
+                            if !is_walkable(&Pos(pos.0 + dx, pos.1))
+                                || !is_walkable(&Pos(pos.0, pos.1 + dy))
+                            {
+                                continue;
+                            }
+                            successors.push((next, DIAGONAL_COST));
+                        } else {
+                            successors.push((next, STRAIGHT_COST));
+                        }
+                    }
+                }
+                successors
+            },
+            |pos| {
+                let dx = (pos.0 - goal_pos.0).abs() as u32;
+                let dy = (pos.1 - goal_pos.1).abs() as u32;
+                let octile =
+                    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy);
+                octile.saturating_sub((radius * STRAIGHT_COST as f64) as u32)
+            },
+            |pos| calculate_distance(&tile_center(pos), goal) <= radius,
+        )?;
+        Some(simplify_path(&path))
+    }
+}
+
+pub fn path_length(start: &Position, waypoints: &[Position]) -> f64 {
+    let mut length = 0.;
+    let mut last = start;
+    for waypoint in waypoints {
+        length += calculate_distance(last, waypoint);
+        last = waypoint;
+    }
+    length
+}
+
+/// keeps only the tiles where the walking direction changes and the last one
+fn simplify_path(path: &[Pos]) -> Vec<Position> {
+    let mut waypoints: Vec<Position> = vec![];
+    for i in 1..path.len() {
+        let step = (path[i].0 - path[i - 1].0, path[i].1 - path[i - 1].1);
+        let is_last = i + 1 == path.len();
+        if is_last || step != (path[i + 1].0 - path[i].0, path[i + 1].1 - path[i].1) {
+            waypoints.push(tile_center(&path[i]));
+        }
+    }
+    waypoints
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::fixture_world;
+    use crate::types::FactorioTile;
+
+    use super::*;
+
+    #[test]
+    fn test_walk_path() {
+        let world = fixture_world();
+        let pathfinder = WalkPathfinder::new(world.entity_graph.clone());
+        let start = Position::new(30.5, 40.5);
+        let goal = Position::new(50.5, 40.5);
+        // the water from 38,38 to 43,43 is in the way
+        let waypoints = pathfinder.find_path(&start, &goal, 1.).unwrap();
+        assert!(waypoints.len() > 1);
+        assert!(calculate_distance(waypoints.last().unwrap(), &goal) <= 1.);
+        for waypoint in &waypoints {
+            let pos: Pos = waypoint.into();
+            assert!(pos.0 < 38 || pos.0 > 42 || pos.1 < 38 || pos.1 > 42);
+        }
+        let distance = pathfinder.walk_distance(&start, &goal, 1.);
+        assert!(distance > 19. && distance < 26.);
+        assert!(pathfinder.find_path(&start, &start, 1.).unwrap().is_empty());
+
+        // a wall of water from top to bottom of the search area blocks every path
+        let tiles: Vec<FactorioTile> = (-40..120)
+            .map(|y| FactorioTile {
+                name: "water".into(),
+                position: Position::new(45., y as f64),
+                player_collidable: true,
+                color: None,
+            })
+            .collect();
+        world.entity_graph.add_tiles(tiles, None).unwrap();
+        // still cached
+        assert!(pathfinder.find_path(&start, &goal, 1.).is_some());
+        pathfinder.invalidate(&add_to_rect(
+            &Rect::from_wh(1., 1.),
+            &Position::new(500., 500.),
+        ));
+        assert!(pathfinder.find_path(&start, &goal, 1.).is_some());
+        pathfinder.invalidate(&add_to_rect(
+            &Rect::from_wh(1., 160.),
+            &Position::new(45.5, 40.),
+        ));
+        assert!(pathfinder.find_path(&start, &goal, 1.).is_none());
+    }
+
+    #[test]
+    fn test_is_explored() {
+        let world = fixture_world();
+        let pathfinder = WalkPathfinder::new(world.entity_graph.clone());
+        // only the chunk with the water from 38,38 to 43,43 has tiles
+        let start = Position::new(33.5, 33.5);
+        assert!(pathfinder.is_explored(&start, &[Position::new(60.5, 33.5)]));
+        assert!(!pathfinder.is_explored(&start, &[Position::new(70.5, 33.5)]));
+        assert!(!pathfinder.is_explored(
+            &start,
+            &[Position::new(33.5, 20.5), Position::new(40.5, 40.5)]
+        ));
+    }
+}
@@ -1,14 +1,37 @@
-use crate::factorio::entity_graph::EntityGraph;
+use crate::factorio::belt_router::route_belts;
+use crate::factorio::blueprint_builder::BlueprintBuilder;
+use crate::factorio::blueprint_transform::{mirror_blueprint, rotate_blueprint, tile_blueprint};
+use crate::factorio::circuit_graph::CircuitGraph;
+use crate::factorio::defense::plan_perimeter;
+use crate::factorio::entity_graph::{EntityGraph, QuadTreeRect};
 use crate::factorio::flow_graph::FlowGraph;
+use crate::factorio::free_space::find_free_space;
+use crate::factorio::outpost::plan_mining_outpost;
+use crate::factorio::pipe_router::route_pipe;
+use crate::factorio::production_block::plan_production_block;
+use crate::factorio::rail_graph::RailGraph;
+use crate::factorio::tech_tree::{ResearchPlan, TechTree};
+use crate::factorio::util::{
+    add_to_rect, add_to_rect_turned, blueprint_build_area, calculate_distance, pad_rect,
+};
+use crate::factorio::walk_pathfinder::{WalkPathfinder, CHARACTER_SIZE};
+use crate::factorio::world_query::WorldQuery;
+use crate::num_traits::FromPrimitive;
 use crate::types::{
-    FactorioEntity, FactorioEntityPrototype, FactorioForce, FactorioGraphic, FactorioItemPrototype,
-    FactorioPlayer, FactorioRecipe, FactorioTile, PlayerChangedDistanceEvent,
-    PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent,
+    BeltRoute, BlueprintConflict, BlueprintValidation, ChunkPollution, CombinatorSettings,
+    DefensePerimeter, Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype,
+    FactorioForce, FactorioGraphic, FactorioGroundItem, FactorioItemPrototype, FactorioPlayer,
+    FactorioRecipe, FactorioTile, FreeSpaceQuery, InventoryResponse, MiningOutpost,
+    PerimeterRequest, PlayerChangedDistanceEvent, PlayerChangedMainInventoryEvent,
+    PlayerChangedPositionEvent, Pos, Position, ProductionBlock, Rect, ResearchProgressEvent,
+    ResourcePatch, ResourcePatchInfo,
 };
 use async_std::sync::Mutex;
 use dashmap::DashMap;
+use factorio_blueprint::{BlueprintCodec, Container};
 use image::RgbaImage;
 use rlua::{Context, Table};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 pub struct FactorioWorld {
@@ -22,9 +45,16 @@ pub struct FactorioWorld {
     pub actions: DashMap<u32, String>,
     pub path_requests: DashMap<u32, String>,
     pub next_action_id: Mutex<u32>,
+    pub ground_items: DashMap<Pos, Vec<FactorioGroundItem>>,
+    pub enemy_units: DashMap<Pos, Vec<FactorioEntity>>,
+    // pollution per chunk, keyed by its left top
+    pub pollution: DashMap<Pos, f64>,
 
     pub entity_graph: Arc<EntityGraph>,
     pub flow_graph: Arc<FlowGraph>,
+    pub circuit_graph: Arc<CircuitGraph>,
+    pub rail_graph: Arc<RailGraph>,
+    pub walk_pathfinder: Arc<WalkPathfinder>,
 }
 
 impl FactorioWorld {
@@ -60,6 +90,7 @@ impl FactorioWorld {
             let existing_player = self.players.get(&event.player_id).unwrap();
             FactorioPlayer {
                 player_id: event.player_id,
+                force: event.force.clone(),
                 position: existing_player.position.clone(),
                 main_inventory: existing_player.main_inventory.clone(),
                 build_distance: event.build_distance,
@@ -72,6 +103,7 @@ impl FactorioWorld {
         } else {
             FactorioPlayer {
                 player_id: event.player_id,
+                force: event.force.clone(),
                 build_distance: event.build_distance,
                 reach_distance: event.reach_distance,
                 drop_item_distance: event.drop_item_distance,
@@ -90,6 +122,7 @@ impl FactorioWorld {
             let existing_player = self.players.get(&event.player_id).unwrap();
             FactorioPlayer {
                 player_id: event.player_id,
+                force: existing_player.force.clone(),
                 position: event.position,
                 main_inventory: existing_player.main_inventory.clone(),
                 build_distance: existing_player.build_distance,
@@ -116,21 +149,448 @@ impl FactorioWorld {
         Ok(())
     }
 
-    pub fn on_some_entity_updated(&self, _entity: FactorioEntity) -> anyhow::Result<()> {
-        // TODO: update entity direction
+    pub fn update_research_progress(&self, event: ResearchProgressEvent) -> anyhow::Result<()> {
+        let mut force = self
+            .forces
+            .get_mut(&event.force_name)
+            .ok_or_else(|| anyhow!("force {} not found", event.force_name))?;
+        force.current_research = event.current_research;
+        force.research_progress = event.research_progress;
+        force.research_queue = event.research_queue;
+        Ok(())
+    }
+
+    pub fn research_finished(&self, force_name: &str, technology: &str) -> anyhow::Result<()> {
+        let mut force = self
+            .forces
+            .get_mut(force_name)
+            .ok_or_else(|| anyhow!("force {} not found", force_name))?;
+        match force.technologies.get_mut(technology) {
+            Some(technology) => technology.researched = true,
+            None => return Err(anyhow!("technology {} not found", technology)),
+        }
+        if force.current_research.as_deref() == Some(technology) {
+            force.current_research = None;
+            force.research_progress = None;
+        }
+        if let Some(queue) = force.research_queue.as_mut() {
+            queue.retain(|name| name != technology);
+        }
+        Ok(())
+    }
+
+    pub fn research_plan(&self, force_name: &str, target: &str) -> anyhow::Result<ResearchPlan> {
+        let force = self
+            .forces
+            .get(force_name)
+            .ok_or_else(|| anyhow!("force {} not found", force_name))?;
+        let plan = TechTree::new(&force).plan(target)?;
+        Ok(plan)
+    }
+
+    /// rotated, rewired or reconfigured entities are replaced as a whole
+    pub fn on_some_entity_updated(&self, entity: FactorioEntity) -> anyhow::Result<()> {
+        self.entity_graph.remove(&entity)?;
+        self.circuit_graph.remove(&entity.position);
+        self.circuit_graph.add(&entity)?;
+        self.rail_graph.remove(&entity);
+        self.rail_graph.add(&entity);
+        self.walk_pathfinder.invalidate(&entity.bounding_box);
+        self.entity_graph.add(vec![entity], None)?;
         Ok(())
     }
 
     pub fn on_some_entity_created(&self, entity: FactorioEntity) -> anyhow::Result<()> {
+        self.circuit_graph.add(&entity)?;
+        self.rail_graph.add(&entity);
+        self.walk_pathfinder.invalidate(&entity.bounding_box);
         self.entity_graph.add(vec![entity], None)?;
         Ok(())
     }
 
     pub fn on_some_entity_deleted(&self, entity: FactorioEntity) -> anyhow::Result<()> {
         self.entity_graph.remove(&entity)?;
+        self.circuit_graph.remove(&entity.position);
+        self.rail_graph.remove(&entity);
+        self.walk_pathfinder.invalidate(&entity.bounding_box);
         Ok(())
     }
 
+    /// settings of the combinator at position
+    pub fn combinator_at(&self, position: &Position) -> Option<CombinatorSettings> {
+        self.entity_graph
+            .entity_at(position)
+            .and_then(|entity_id| self.entity_graph.entity_by_id(entity_id))
+            .and_then(|entity| entity.combinator)
+    }
+
+    /// overlays a blueprint onto the known world, direction turns the blueprint clockwise
+    pub fn validate_blueprint(
+        &self,
+        blueprint: &str,
+        position: &Position,
+        direction: u8,
+    ) -> anyhow::Result<BlueprintValidation> {
+        let blueprint_entities = match BlueprintCodec::decode_string(blueprint) {
+            Ok(Container::Blueprint(blueprint)) => blueprint.entities,
+            Ok(_) => bail!("blueprint books are not supported"),
+            Err(err) => bail!("failed to decode blueprint: {:?}", err),
+        };
+        let turn = match Direction::from_u8(direction) {
+            Some(turn) if direction % 2 == 0 => turn,
+            _ => bail!("blueprints can only be turned in 90 degree steps"),
+        };
+        let center = blueprint_build_area(self.entity_prototypes.clone(), blueprint).center();
+        let mut entities: Vec<FactorioEntity> = vec![];
+        let mut conflicts: Vec<BlueprintConflict> = vec![];
+        let mut warnings: Vec<BlueprintConflict> = vec![];
+        let mut obstacles: Vec<FactorioEntity> = vec![];
+        let mut items: BTreeMap<String, u32> = BTreeMap::new();
+        for blueprint_entity in blueprint_entities {
+            let blueprint_position: Position = blueprint_entity.position.into();
+            let entity_position = position.add(
+                &Position::new(
+                    blueprint_position.x() - center.x(),
+                    blueprint_position.y() - center.y(),
+                )
+                .turn(turn),
+            );
+            let prototype = match self.entity_prototypes.get(&blueprint_entity.name) {
+                Some(prototype) => prototype.value().clone(),
+                None => {
+                    conflicts.push(BlueprintConflict {
+                        entity_name: blueprint_entity.name.clone(),
+                        position: entity_position,
+                        reason: "unknown entity".into(),
+                    });
+                    continue;
+                }
+            };
+            let entity_direction = (blueprint_entity.direction.unwrap_or(0) + direction) % 8;
+            let entity = FactorioEntity {
+                name: blueprint_entity.name.clone(),
+                entity_type: prototype.entity_type.clone(),
+                bounding_box: add_to_rect_turned(
+                    &prototype.collision_box,
+                    &entity_position,
+                    Direction::from_u8(entity_direction).unwrap(),
+                ),
+                position: entity_position,
+                direction: entity_direction,
+                recipe: blueprint_entity.recipe.clone(),
+                ..Default::default()
+            };
+            let conflict = |reason: String| BlueprintConflict {
+                entity_name: entity.name.clone(),
+                position: entity.position.clone(),
+                reason,
+            };
+
+            let query: QuadTreeRect = entity.bounding_box.clone().into();
+            let mut others: Vec<FactorioEntity> = self
+                .entity_graph
+                .inner_tree()
+                .query(query)
+                .into_iter()
+                .map(|(other, _, _)| other.clone())
+                .collect();
+            // rails are only known to the rail graph, a curved rail reaches 4 tiles from its position
+            for rail in self
+                .rail_graph
+                .entities_in(&pad_rect(&entity.bounding_box, 4., 4., 4., 4.))
+            {
+                if rail.bounding_box.intersects(&entity.bounding_box) {
+                    others.push(rail);
+                }
+            }
+            let mut exists = false;
+            // rects of everything already reported so the blocked tree only adds what is left
+            let mut known: Vec<QuadTreeRect> = vec![];
+            for other in others {
+                known.push(other.bounding_box.clone().into());
+                if other.name == entity.name && other.position == entity.position {
+                    exists = true;
+                } else {
+                    conflicts.push(conflict(format!(
+                        "blocked by {} at {}",
+                        other.name, other.position
+                    )));
+                }
+            }
+            let mut on_tile = false;
+            for (tile, rect, _) in self.entity_graph.tile_tree().query(query) {
+                if tile.player_collidable {
+                    known.push(rect);
+                    if !on_tile && entity.entity_type != EntityType::OffshorePump.to_string() {
+                        conflicts.push(conflict(format!("on {} at {}", tile.name, tile.position)));
+                        on_tile = true;
+                    }
+                }
+            }
+            // cliffs, walls, turrets and whatever else is not part of the entity graph,
+            // minable trees and rocks are reported as obstacles instead
+            for (minable, rect, _) in self.entity_graph.blocked_tree().query(query) {
+                if !*minable && !known.contains(&rect) {
+                    let center = rect.center();
+                    conflicts.push(conflict(format!(
+                        "blocked at {}",
+                        Position::new(center.x as f64, center.y as f64)
+                    )));
+                }
+            }
+            let mut resources: Vec<String> = self
+                .entity_graph
+                .resource_tree()
+                .query(entity.bounding_box.clone().into())
+                .iter()
+                .map(|(resource, _, _)| (*resource).clone())
+                .collect();
+            resources.sort();
+            resources.dedup();
+            if entity.entity_type == EntityType::MiningDrill.to_string() {
+                if resources.is_empty() {
+                    conflicts.push(conflict("no resources under mining drill".into()));
+                }
+            } else if !resources.is_empty() {
+                warnings.push(conflict(format!("covers {}", resources.join(", "))));
+            }
+            for obstacle in self.entity_graph.obstacles_in(&entity.bounding_box) {
+                if !obstacles.contains(&obstacle) {
+                    obstacles.push(obstacle);
+                }
+            }
+            if !exists {
+                let (item_name, count) = self.item_to_place(&entity.name);
+                *items.entry(item_name).or_insert(0) += count;
+            }
+            entities.push(entity);
+        }
+        Ok(BlueprintValidation {
+            valid: conflicts.is_empty(),
+            entities,
+            conflicts,
+            warnings,
+            obstacles,
+            items,
+        })
+    }
+
+    /// patches of a resource, best first, with the time until the drills on them mined them out
+    pub fn resource_patches(&self, resource_name: &str) -> Vec<ResourcePatchInfo> {
+        self.entity_graph
+            .resource_patches(resource_name)
+            .iter()
+            .map(|patch| {
+                let area = Rect::new(
+                    &patch.rect.left_top.add(&Position::new(-2., -2.)),
+                    &patch.rect.right_bottom.add(&Position::new(3., 3.)),
+                );
+                let drills: Vec<FactorioEntity> = self
+                    .entity_graph
+                    .inner_tree()
+                    .query(area.into())
+                    .into_iter()
+                    .filter(|(entity, _, _)| {
+                        entity.entity_type == EntityType::MiningDrill.to_string()
+                    })
+                    .map(|(entity, _, _)| entity.clone())
+                    .collect();
+                patch.info(patch.depletion_seconds(&drills, &self.entity_prototypes))
+            })
+            .collect()
+    }
+
+    /// drills with belts or chests on the patch of the resource closest to the position
+    pub fn mining_outpost(
+        &self,
+        resource_name: &str,
+        near: &Position,
+        drill_name: &str,
+    ) -> anyhow::Result<MiningOutpost> {
+        let patches = self.entity_graph.resource_patches(resource_name);
+        let distance = |patch: &&ResourcePatch| {
+            patch
+                .elements
+                .iter()
+                .map(|element| calculate_distance(element, near))
+                .fold(f64::MAX, f64::min)
+        };
+        let patch = patches
+            .iter()
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .ok_or_else(|| anyhow!("no {} found", resource_name))?;
+        plan_mining_outpost(&self.entity_graph, patch, drill_name)
+    }
+
+    /// the collision box of the entity at the position
+    fn placement_rect(
+        &self,
+        name: &str,
+        position: &Position,
+        direction: Direction,
+    ) -> anyhow::Result<Rect> {
+        let prototype = self
+            .entity_prototypes
+            .get(name)
+            .ok_or_else(|| anyhow!("entity prototype {} not found", name))?;
+        Ok(add_to_rect_turned(
+            &prototype.collision_box,
+            position,
+            direction,
+        ))
+    }
+
+    /// whether the entity fits at the position without hitting buildings, rails, trees, water or
+    /// the player when one is given
+    pub fn can_place(
+        &self,
+        name: &str,
+        position: &Position,
+        direction: Direction,
+        player_id: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        let rect = self.placement_rect(name, position, direction)?;
+        if !self
+            .entity_graph
+            .blocked_tree()
+            .query(rect.clone().into())
+            .is_empty()
+        {
+            return Ok(false);
+        }
+        // same rail lookup as validate_blueprint
+        if self
+            .rail_graph
+            .entities_in(&pad_rect(&rect, 4., 4., 4., 4.))
+            .iter()
+            .any(|rail| rail.bounding_box.intersects(&rect))
+        {
+            return Ok(false);
+        }
+        if let Some(player_id) = player_id {
+            let player = self
+                .players
+                .get(&player_id)
+                .ok_or_else(|| anyhow!("player {} not found", player_id))?;
+            if rect.intersects(&character_rect(&player.position)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// where the player can stand to place the entity: the current position if it is in build
+    /// distance and out of the way, else the closest free tile center in build distance
+    pub fn standing_position(
+        &self,
+        player_id: u32,
+        name: &str,
+        position: &Position,
+        direction: Direction,
+    ) -> anyhow::Result<Position> {
+        let rect = self.placement_rect(name, position, direction)?;
+        let player = self
+            .players
+            .get(&player_id)
+            .ok_or_else(|| anyhow!("player {} not found", player_id))?
+            .clone();
+        let build_distance = player.build_distance as f64;
+        if calculate_distance(&player.position, position) <= build_distance
+            && !rect.intersects(&character_rect(&player.position))
+        {
+            return Ok(player.position);
+        }
+        let blocked_tree = self.entity_graph.blocked_tree();
+        let center: Pos = position.into();
+        let reach = build_distance.ceil() as i32;
+        let mut best: Option<(f64, Position)> = None;
+        for y in center.1 - reach..=center.1 + reach {
+            for x in center.0 - reach..=center.0 + reach {
+                let candidate = Position::new(x as f64 + 0.5, y as f64 + 0.5);
+                if calculate_distance(&candidate, position) > build_distance {
+                    continue;
+                }
+                let character = character_rect(&candidate);
+                if rect.intersects(&character)
+                    || !blocked_tree.query(character.clone().into()).is_empty()
+                {
+                    continue;
+                }
+                let walk = calculate_distance(&player.position, &candidate);
+                if best.as_ref().map_or(true, |(distance, _)| walk < *distance) {
+                    best = Some((walk, candidate));
+                }
+            }
+        }
+        best.map(|(_, candidate)| candidate).ok_or_else(|| {
+            anyhow!(
+                "no free tile to stand on within {} of {}",
+                build_distance,
+                position
+            )
+        })
+    }
+
+    /// nearest free area for a building block, see `FreeSpaceQuery` for the options
+    pub fn free_space(&self, query: &FreeSpaceQuery) -> anyhow::Result<Rect> {
+        find_free_space(&self.entity_graph, query)
+    }
+
+    /// furnace or assembler columns fed from the input belt positions
+    pub fn production_block(
+        &self,
+        recipe_name: &str,
+        rate: f64,
+        inputs: &[Position],
+        machine_name: Option<&str>,
+    ) -> anyhow::Result<ProductionBlock> {
+        plan_production_block(&self.entity_graph, recipe_name, rate, inputs, machine_name)
+    }
+
+    /// wall and turret ring around the area, see `PerimeterRequest` for the options
+    pub fn defense_perimeter(
+        &self,
+        request: &PerimeterRequest,
+    ) -> anyhow::Result<DefensePerimeter> {
+        plan_perimeter(&self.entity_graph, request)
+    }
+
+    /// captures all buildings inside the area, including walls, turrets and rails which are not
+    /// in the entity graph
+    pub fn export_blueprint(&self, area: &Rect, label: &str) -> anyhow::Result<String> {
+        let mut entities: Vec<FactorioEntity> = self
+            .entity_graph
+            .inner_tree()
+            .query(area.clone().into())
+            .into_iter()
+            .map(|(entity, _, _)| entity.clone())
+            .collect();
+        entities.extend(self.entity_graph.blockers_in(area));
+        entities.retain(|entity| area.contains(&entity.position));
+        let mut builder = BlueprintBuilder::new(label);
+        builder
+            .add_entities(&entities)
+            .add_entities(&self.rail_graph.entities_in(area));
+        if builder.is_empty() {
+            bail!("no entities found in {:?}", area);
+        }
+        builder.build()
+    }
+
+    /// item and count needed to build an entity
+    pub fn item_to_place(&self, entity_name: &str) -> (String, u32) {
+        if entity_name == EntityName::CurvedRail.to_string() {
+            return (EntityName::Rail.to_string(), 4);
+        }
+        let item_name = self
+            .item_prototypes
+            .iter()
+            .find(|item| item.place_result == entity_name)
+            .map(|item| item.name.clone())
+            .unwrap_or_else(|| entity_name.into());
+        (item_name, 1)
+    }
+
     pub fn player_changed_main_inventory(
         &self,
         event: PlayerChangedMainInventoryEvent,
@@ -139,6 +599,7 @@ impl FactorioWorld {
             let existing_player = self.players.get(&event.player_id).unwrap();
             FactorioPlayer {
                 player_id: event.player_id,
+                force: existing_player.force.clone(),
                 position: existing_player.position.clone(),
                 main_inventory: event.main_inventory,
                 build_distance: existing_player.build_distance,
@@ -175,15 +636,141 @@ impl FactorioWorld {
 
     pub fn update_chunk_tiles(&self, tiles: Vec<FactorioTile>) -> anyhow::Result<()> {
         self.entity_graph.add_tiles(tiles, None)?; // FIXME: add clear rect from chunk_position
+        self.walk_pathfinder.clear();
         Ok(())
     }
 
     #[allow(clippy::map_clone)]
     pub fn update_chunk_entities(&self, entities: Vec<FactorioEntity>) -> anyhow::Result<()> {
+        let (items, entities): (Vec<FactorioEntity>, Vec<FactorioEntity>) = entities
+            .into_iter()
+            .partition(|e| e.entity_type == EntityType::ItemEntity.to_string());
+        for item in items {
+            if let Some(output_inventory) = item.output_inventory.as_ref() {
+                for (name, count) in output_inventory {
+                    self.add_ground_item(FactorioGroundItem {
+                        name: name.clone(),
+                        count: *count,
+                        position: item.position.clone(),
+                    });
+                }
+            }
+        }
+        for entity in &entities {
+            self.circuit_graph.add(entity)?;
+            self.rail_graph.add(entity);
+        }
         self.entity_graph.add(entities, None)?; // FIXME: add clear rect
+        self.walk_pathfinder.clear();
+        Ok(())
+    }
+
+    pub fn on_entity_inventory_changed(&self, inventory: InventoryResponse) -> anyhow::Result<()> {
+        self.entity_graph.update_inventory(inventory)
+    }
+
+    pub fn update_ground_items(
+        &self,
+        rect: Rect,
+        items: Vec<FactorioGroundItem>,
+    ) -> anyhow::Result<()> {
+        remove_inside(&self.ground_items, &rect);
+        for item in items {
+            self.add_ground_item(item);
+        }
+        Ok(())
+    }
+
+    fn add_ground_item(&self, item: FactorioGroundItem) {
+        let pos: Pos = (&item.position).into();
+        match self.ground_items.get_mut(&pos) {
+            Some(mut items) => items.push(item),
+            None => {
+                self.ground_items.insert(pos, vec![item]);
+            }
+        }
+    }
+
+    pub fn update_enemy_units(&self, rect: Rect, units: Vec<FactorioEntity>) -> anyhow::Result<()> {
+        remove_inside(&self.enemy_units, &rect);
+        for unit in units {
+            let pos: Pos = (&unit.position).into();
+            match self.enemy_units.get_mut(&pos) {
+                Some(mut units) => units.push(unit),
+                None => {
+                    self.enemy_units.insert(pos, vec![unit]);
+                }
+            }
+        }
         Ok(())
     }
 
+    pub fn update_pollution(
+        &self,
+        rect: Rect,
+        pollution: Vec<ChunkPollution>,
+    ) -> anyhow::Result<()> {
+        remove_inside(&self.pollution, &rect);
+        for chunk in pollution {
+            self.pollution
+                .insert((&chunk.position).into(), chunk.amount);
+        }
+        Ok(())
+    }
+
+    pub fn enemy_units_near(&self, position: &Position, radius: f64) -> Vec<FactorioEntity> {
+        let mut units: Vec<FactorioEntity> = vec![];
+        for entry in self.enemy_units.iter() {
+            for unit in entry.value() {
+                if calculate_distance(&unit.position, position) <= radius {
+                    units.push(unit.clone());
+                }
+            }
+        }
+        units
+    }
+
+    /// chunks with pollution inside the rect, biters attack where the cloud reaches a spawner
+    pub fn pollution_in(&self, rect: &Rect) -> Vec<ChunkPollution> {
+        let mut chunks: Vec<ChunkPollution> = self
+            .pollution
+            .iter()
+            .filter(|entry| {
+                let chunk = Rect::new(
+                    &Position::new(entry.key().0 as f64, entry.key().1 as f64),
+                    &Position::new(entry.key().0 as f64 + 32., entry.key().1 as f64 + 32.),
+                );
+                chunk.intersects(rect)
+            })
+            .map(|entry| ChunkPollution {
+                position: Position::new(entry.key().0 as f64, entry.key().1 as f64),
+                amount: *entry.value(),
+            })
+            .collect();
+        chunks.sort_by(|a, b| {
+            (a.position.y(), a.position.x())
+                .partial_cmp(&(b.position.y(), b.position.x()))
+                .unwrap()
+        });
+        chunks
+    }
+
+    pub fn ground_items_near(&self, position: &Position, radius: f64) -> Vec<FactorioGroundItem> {
+        let mut items: Vec<FactorioGroundItem> = vec![];
+        for entry in self.ground_items.iter() {
+            for item in entry.value() {
+                if calculate_distance(&item.position, position) <= radius {
+                    items.push(item.clone());
+                }
+            }
+        }
+        items
+    }
+
+    pub fn query(&self, query: &WorldQuery) -> Vec<FactorioEntity> {
+        query.execute(&self.entity_graph)
+    }
+
     pub fn import(&self, world: Arc<FactorioWorld>) -> anyhow::Result<()> {
         for player in world.players.iter() {
             self.players.insert(player.player_id, player.clone());
@@ -202,7 +789,21 @@ impl FactorioWorld {
         for force in world.forces.iter() {
             self.forces.insert(force.name.clone(), force.clone());
         }
-        self.entity_graph.connect()?;
+        for entry in world.ground_items.iter() {
+            self.ground_items
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        for entry in world.enemy_units.iter() {
+            self.enemy_units
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        for entry in world.pollution.iter() {
+            self.pollution.insert(entry.key().clone(), *entry.value());
+        }
+        self.entity_graph.import(&world.entity_graph)?;
+        self.circuit_graph.import(&world.circuit_graph)?;
+        self.rail_graph.import(&world.rail_graph)?;
+        self.walk_pathfinder.clear();
         Ok(())
     }
 
@@ -218,6 +819,7 @@ impl FactorioWorld {
             Arc::new(DashMap::new());
         let entity_graph = Arc::new(EntityGraph::new(entity_prototypes.clone(), recipes.clone()));
         let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+        let walk_pathfinder = Arc::new(WalkPathfinder::new(entity_graph.clone()));
         FactorioWorld {
             image_cache,
             players,
@@ -229,8 +831,14 @@ impl FactorioWorld {
             actions: DashMap::new(),
             path_requests: DashMap::new(),
             next_action_id: Mutex::new(1),
+            ground_items: DashMap::new(),
+            enemy_units: DashMap::new(),
+            pollution: DashMap::new(),
             entity_graph,
             flow_graph,
+            circuit_graph: Arc::new(CircuitGraph::new()),
+            rail_graph: Arc::new(RailGraph::new()),
+            walk_pathfinder,
         }
     }
 }
@@ -238,6 +846,22 @@ impl FactorioWorld {
 unsafe impl Send for FactorioWorld {}
 unsafe impl Sync for FactorioWorld {}
 
+fn character_rect(position: &Position) -> Rect {
+    add_to_rect(&Rect::from_wh(CHARACTER_SIZE, CHARACTER_SIZE), position)
+}
+
+/// drops every tile of `map` inside `rect` before a fresh scan of that rect is added
+fn remove_inside<T>(map: &DashMap<Pos, T>, rect: &Rect) {
+    let left_top: Pos = (&rect.left_top).into();
+    let right_bottom: Pos = (&rect.right_bottom).into();
+    map.retain(|pos, _| {
+        pos.0 < left_top.0
+            || pos.0 >= right_bottom.0
+            || pos.1 < left_top.1
+            || pos.1 >= right_bottom.1
+    });
+}
+
 pub fn create_lua_world(ctx: Context, _world: Arc<FactorioWorld>) -> rlua::Result<Table> {
     let map_table = ctx.create_table()?;
 
@@ -261,7 +885,7 @@ pub fn create_lua_world(ctx: Context, _world: Arc<FactorioWorld>) -> rlua::Resul
         )?,
     )?;
 
-    let world = _world;
+    let world = _world.clone();
     map_table.set(
         "inventory",
         ctx.create_function(move |_ctx, (player_id, item_name): (u32, String)| {
@@ -275,5 +899,655 @@ pub fn create_lua_world(ctx: Context, _world: Arc<FactorioWorld>) -> rlua::Resul
         })?,
     )?;
 
+    let world = _world.clone();
+    map_table.set(
+        "inventoryAt",
+        ctx.create_function(move |ctx, position: String| {
+            let position: Position = position
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            match world.entity_graph.inventory_at(&position) {
+                Some(inventory) => Ok(rlua_serde::to_value(ctx, inventory)),
+                None => Err(rlua::Error::RuntimeError("entity not found".into())),
+            }
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "needingFuel",
+        ctx.create_function(move |ctx, min_count: u32| {
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.entity_graph.entities_needing_fuel(min_count),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "withOutput",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.entity_graph.entities_with_output(),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "groundItems",
+        ctx.create_function(move |ctx, (position, radius): (String, f64)| {
+            let position: Position = position
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.ground_items_near(&position, radius),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "electricNetworks",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.entity_graph.electric_networks(),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "flowAnalysis",
+        ctx.create_function(move |ctx, ()| {
+            world
+                .flow_graph
+                .update()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, world.flow_graph.analyze()))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "unpowered",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.entity_graph.unpowered_entities(),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "isPowered",
+        ctx.create_function(
+            move |_ctx, (name, position, direction): (String, String, u8)| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let prototype = world
+                    .entity_prototypes
+                    .get(&name)
+                    .ok_or_else(|| rlua::Error::RuntimeError("prototype not found".into()))?;
+                let entity = FactorioEntity {
+                    name: name.clone(),
+                    entity_type: prototype.entity_type.clone(),
+                    bounding_box: add_to_rect_turned(
+                        &prototype.collision_box,
+                        &position,
+                        Direction::from_u8(direction).unwrap_or(Direction::North),
+                    ),
+                    position,
+                    direction,
+                    ..Default::default()
+                };
+                drop(prototype);
+                Ok(world.entity_graph.is_powered(&entity))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "validateBlueprint",
+        ctx.create_function(
+            move |ctx, (blueprint, position, direction): (String, String, Option<u8>)| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let validation = world
+                    .validate_blueprint(&blueprint, &position, direction.unwrap_or(0))
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(ctx, validation))
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "rotateBlueprint",
+        ctx.create_function(move |_ctx, (blueprint, direction): (String, u8)| {
+            rotate_blueprint(&blueprint, direction)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+        })?,
+    )?;
+
+    map_table.set(
+        "mirrorBlueprint",
+        ctx.create_function(move |_ctx, (blueprint, vertical): (String, Option<bool>)| {
+            mirror_blueprint(&blueprint, vertical.unwrap_or(false))
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "tileBlueprint",
+        ctx.create_function(
+            move |_ctx, (blueprint, count, direction, grid): (String, u32, u8, Option<u32>)| {
+                let direction = Direction::from_u8(direction).ok_or_else(|| {
+                    rlua::Error::RuntimeError(format!("invalid direction {}", direction))
+                })?;
+                tile_blueprint(
+                    world.entity_prototypes.clone(),
+                    &blueprint,
+                    count,
+                    direction,
+                    grid.unwrap_or(1),
+                )
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "walkPath",
+        ctx.create_function(
+            move |ctx, (from, to, radius): (String, String, Option<f64>)| {
+                let from: Position = from
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let to: Position = to
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(
+                    ctx,
+                    world
+                        .walk_pathfinder
+                        .find_path(&from, &to, radius.unwrap_or(1.)),
+                ))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "routeBelts",
+        ctx.create_function(move |ctx, route: rlua::Value| {
+            let route: BeltRoute = rlua_serde::from_value(route)?;
+            let entities = route_belts(&world.entity_graph, &route)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, entities))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "routePipe",
+        ctx.create_function(
+            move |ctx,
+                  (from, from_fluidbox, to, to_fluidbox): (
+                rlua::Value,
+                Option<usize>,
+                rlua::Value,
+                Option<usize>,
+            )| {
+                let from: FactorioEntity = rlua_serde::from_value(from)?;
+                let to: FactorioEntity = rlua_serde::from_value(to)?;
+                let entities = route_pipe(
+                    &world.entity_graph,
+                    &from,
+                    from_fluidbox.unwrap_or(0),
+                    &to,
+                    to_fluidbox.unwrap_or(0),
+                )
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(ctx, entities))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "resourcePatches",
+        ctx.create_function(move |ctx, name: String| {
+            Ok(rlua_serde::to_value(ctx, world.resource_patches(&name)))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "miningOutpost",
+        ctx.create_function(
+            move |ctx, (resource_name, near, drill_name): (String, String, Option<String>)| {
+                let near: Position = near
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let outpost = world
+                    .mining_outpost(
+                        &resource_name,
+                        &near,
+                        &drill_name.unwrap_or_else(|| EntityName::ElectricMiningDrill.to_string()),
+                    )
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(ctx, outpost))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "canPlace",
+        ctx.create_function(
+            move |_ctx,
+                  (name, position, direction, player_id): (
+                String,
+                String,
+                u8,
+                Option<u32>,
+            )| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let direction = Direction::from_u8(direction).ok_or_else(|| {
+                    rlua::Error::RuntimeError(format!("invalid direction {}", direction))
+                })?;
+                world
+                    .can_place(&name, &position, direction, player_id)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "standingPosition",
+        ctx.create_function(
+            move |ctx, (player_id, name, position, direction): (u32, String, String, u8)| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let direction = Direction::from_u8(direction).ok_or_else(|| {
+                    rlua::Error::RuntimeError(format!("invalid direction {}", direction))
+                })?;
+                let standing = world
+                    .standing_position(player_id, &name, &position, direction)
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(ctx, standing))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "findFreeSpace",
+        ctx.create_function(move |ctx, query: rlua::Value| {
+            let query: FreeSpaceQuery = rlua_serde::from_value(query)?;
+            let rect = world
+                .free_space(&query)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, rect))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "productionBlock",
+        ctx.create_function(
+            move |ctx,
+                  (recipe_name, rate, inputs, machine_name): (
+                String,
+                f64,
+                Vec<String>,
+                Option<String>,
+            )| {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| input.parse())
+                    .collect::<anyhow::Result<Vec<Position>>>()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                let block = world
+                    .production_block(&recipe_name, rate, &inputs, machine_name.as_deref())
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(ctx, block))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "enemyBases",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(ctx, world.entity_graph.enemy_bases()))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "enemyUnitsNear",
+        ctx.create_function(move |ctx, (position, radius): (String, f64)| {
+            let position: Position = position
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(
+                ctx,
+                world.enemy_units_near(&position, radius),
+            ))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "pollution",
+        ctx.create_function(move |ctx, area: String| {
+            let area: Rect = area
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, world.pollution_in(&area)))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "defensePerimeter",
+        ctx.create_function(move |ctx, request: rlua::Value| {
+            let request: PerimeterRequest = rlua_serde::from_value(request)?;
+            let perimeter = world
+                .defense_perimeter(&request)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, perimeter))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "exportBlueprint",
+        ctx.create_function(move |_ctx, (area, label): (String, Option<String>)| {
+            let area: Rect = area
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            world
+                .export_blueprint(&area, &label.unwrap_or_default())
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+        })?,
+    )?;
+
+    map_table.set(
+        "buildBlueprint",
+        ctx.create_function(
+            move |_ctx, (entities, label): (rlua::Value, Option<String>)| {
+                let entities: Vec<FactorioEntity> = rlua_serde::from_value(entities)?;
+                BlueprintBuilder::new(&label.unwrap_or_default())
+                    .add_entities(&entities)
+                    .build()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "railNetwork",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(ctx, world.rail_graph.network()))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "railRoute",
+        ctx.create_function(move |ctx, (from, to): (String, String)| {
+            let route = world
+                .rail_graph
+                .route(&from, &to)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, route))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "circuitNetworks",
+        ctx.create_function(move |ctx, ()| {
+            Ok(rlua_serde::to_value(ctx, world.circuit_graph.networks()))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "circuitNetworkAt",
+        ctx.create_function(
+            move |ctx, (position, wire, circuit_id): (String, String, Option<u8>)| {
+                let position: Position = position
+                    .parse()
+                    .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+                Ok(rlua_serde::to_value(
+                    ctx,
+                    world
+                        .circuit_graph
+                        .network_at(&position, &wire, circuit_id.unwrap_or(1)),
+                ))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "combinatorAt",
+        ctx.create_function(move |ctx, position: String| {
+            let position: Position = position
+                .parse()
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, world.combinator_at(&position)))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "researchPlan",
+        ctx.create_function(move |ctx, target: String| {
+            let plan = world
+                .research_plan("player", &target)
+                .map_err(|err| rlua::Error::RuntimeError(format!("{:?}", err)))?;
+            Ok(rlua_serde::to_value(ctx, plan))
+        })?,
+    )?;
+
+    let world = _world;
+    map_table.set(
+        "query",
+        ctx.create_function(move |ctx, query: rlua::Value| {
+            let query: WorldQuery = rlua_serde::from_value(query)?;
+            Ok(rlua_serde::to_value(ctx, world.query(&query)))
+        })?,
+    )?;
+
     Ok(map_table)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::blueprint_transform::decode_blueprint_json;
+    use crate::factorio::tests::fixture_world;
+
+    use super::*;
+
+    const SPLITTERS: &str = "0eNqd0u+KwyAMAPB3yWd3TK/q5quM42i3MITWimbHleK7n64clK1lf74ZMb8kkhGa9oI+WEdgRrDH3kUwhxGiPbu6LXc0eAQDlrADBq7uShR9a4kwQGJg3Ql/wfDEHqZRqF30faBNgy3NkkX6YoCOLFmcGrgGw7e7dE0uY/iawcD3Maf1rlTN1EbxD8lgAKPzIZc42YDH6YEoPd7I4n6oBXP7b4rH4uczotytiGpBrJ6fXu7n0y9Y8h1L3P5ktSCrF2S9KquyCte1MbPlZPCDIU5fvuOVrvZaab5VUqX0B2ef55s=";
+
+    #[test]
+    fn test_validate_blueprint() {
+        let world = fixture_world();
+        let on_ore = world
+            .validate_blueprint(SPLITTERS, &Position::new(0., 0.), 0)
+            .unwrap();
+        assert!(on_ore.valid);
+        assert_eq!(on_ore.entities.len(), 6);
+        assert!(!on_ore.warnings.is_empty());
+        assert_eq!(on_ore.items.get("transport-belt"), Some(&4));
+        assert_eq!(on_ore.items.get("splitter"), Some(&2));
+
+        let in_water = world
+            .validate_blueprint(SPLITTERS, &Position::new(40., 40.), 2)
+            .unwrap();
+        assert!(!in_water.valid);
+
+        let in_forest = world
+            .validate_blueprint(SPLITTERS, &Position::new(-20., -20.), 0)
+            .unwrap();
+        assert!(in_forest.valid);
+        assert!(!in_forest.obstacles.is_empty());
+
+        world.rail_graph.add(&FactorioEntity::new_straight_rail(
+            &Position::new(1., 1.),
+            Direction::North,
+        ));
+        let on_rail = world
+            .validate_blueprint(SPLITTERS, &Position::new(0., 0.), 0)
+            .unwrap();
+        assert!(!on_rail.valid);
+        assert!(on_rail
+            .conflicts
+            .iter()
+            .any(|conflict| conflict.reason.contains("straight-rail")));
+
+        let cliff_position = Position::new(-20., -20.);
+        world
+            .entity_graph
+            .add(
+                vec![FactorioEntity {
+                    name: "cliff".into(),
+                    entity_type: EntityType::Cliff.to_string(),
+                    bounding_box: add_to_rect(&Rect::from_wh(4., 4.), &cliff_position),
+                    position: cliff_position.clone(),
+                    ..Default::default()
+                }],
+                None,
+            )
+            .unwrap();
+        let on_cliff = world
+            .validate_blueprint(SPLITTERS, &cliff_position, 0)
+            .unwrap();
+        assert!(!on_cliff.valid);
+
+        assert!(world
+            .validate_blueprint(SPLITTERS, &Position::new(0., 0.), 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_export_blueprint() {
+        let world = fixture_world();
+        let wall_position = Position::new(20.5, -19.5);
+        world
+            .entity_graph
+            .add(
+                vec![
+                    FactorioEntity::new_transport_belt(
+                        &Position::new(20.5, -20.5),
+                        Direction::North,
+                    ),
+                    FactorioEntity {
+                        name: EntityName::StoneWall.to_string(),
+                        entity_type: EntityType::Wall.to_string(),
+                        bounding_box: add_to_rect(&Rect::from_wh(0.8, 0.8), &wall_position),
+                        position: wall_position,
+                        ..Default::default()
+                    },
+                ],
+                None,
+            )
+            .unwrap();
+        let area = Rect::new(&Position::new(18., -22.), &Position::new(23., -18.));
+        let blueprint = world.export_blueprint(&area, "wall").unwrap();
+        let value = decode_blueprint_json(&blueprint).unwrap();
+        let names: Vec<&str> = value["blueprint"]["entities"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entity| entity["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["transport-belt", "stone-wall"]);
+    }
+
+    #[test]
+    fn test_standing_position() {
+        let world = fixture_world();
+        let furnace = EntityName::StoneFurnace.to_string();
+        let target = Position::new(10., 10.);
+        world.players.insert(
+            1,
+            FactorioPlayer {
+                player_id: 1,
+                position: target.clone(),
+                build_distance: 10,
+                ..Default::default()
+            },
+        );
+        assert!(world
+            .can_place(&furnace, &target, Direction::North, None)
+            .unwrap());
+        assert!(!world
+            .can_place(&furnace, &target, Direction::North, Some(1))
+            .unwrap());
+        assert!(!world
+            .can_place(&furnace, &Position::new(40., 40.), Direction::North, None)
+            .unwrap());
+        assert!(world
+            .can_place("no-such-entity", &target, Direction::North, None)
+            .is_err());
+        let on_rail = Position::new(10., 14.);
+        assert!(world
+            .can_place(&furnace, &on_rail, Direction::North, None)
+            .unwrap());
+        world.rail_graph.add(&FactorioEntity::new_straight_rail(
+            &Position::new(11., 15.),
+            Direction::North,
+        ));
+        assert!(!world
+            .can_place(&furnace, &on_rail, Direction::North, None)
+            .unwrap());
+
+        let standing = world
+            .standing_position(1, &furnace, &target, Direction::North)
+            .unwrap();
+        assert!(calculate_distance(&standing, &target) < 2.);
+        world
+            .player_changed_position(PlayerChangedPositionEvent {
+                player_id: 1,
+                position: standing,
+            })
+            .unwrap();
+        assert!(world
+            .can_place(&furnace, &target, Direction::North, Some(1))
+            .unwrap());
+
+        // too far away, walk only as far as needed
+        world
+            .player_changed_position(PlayerChangedPositionEvent {
+                player_id: 1,
+                position: Position::new(30., 10.),
+            })
+            .unwrap();
+        let standing = world
+            .standing_position(1, &furnace, &target, Direction::North)
+            .unwrap();
+        assert!(calculate_distance(&standing, &target) <= 10.);
+        assert!(standing.x() > 19.);
+    }
+}
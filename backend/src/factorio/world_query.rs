@@ -0,0 +1,274 @@
+use std::str::FromStr;
+
+use aabb_quadtree::ItemId;
+use petgraph::graph::NodeIndex;
+
+use crate::factorio::entity_graph::{EntityGraph, EntityQuadTree};
+use crate::factorio::util::calculate_distance;
+use crate::types::{EntityType, FactorioEntity, Position, Rect};
+
+/// filter over the entities known to the local `EntityGraph`.
+/// all given fields must match, relationship filters (`drops_into`/`picks_from`)
+/// follow the edges of the entity graph so `connect()` must have run before.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldQuery {
+    pub name: Option<String>,
+    pub entity_type: Option<String>,
+    pub area: Option<Rect>,
+    pub position: Option<Position>,
+    pub radius: Option<f64>,
+    pub force: Option<String>,
+    pub direction: Option<u8>,
+    pub drops_into: Option<Box<WorldQuery>>,
+    pub picks_from: Option<Box<WorldQuery>>,
+    pub limit: Option<usize>,
+}
+
+impl WorldQuery {
+    pub fn new() -> WorldQuery {
+        WorldQuery::default()
+    }
+    pub fn with_name(mut self, name: &str) -> WorldQuery {
+        self.name = Some(name.into());
+        self
+    }
+    pub fn with_type(mut self, entity_type: EntityType) -> WorldQuery {
+        self.entity_type = Some(entity_type.to_string());
+        self
+    }
+    pub fn with_type_str(mut self, entity_type: &str) -> WorldQuery {
+        self.entity_type = Some(entity_type.into());
+        self
+    }
+    pub fn with_area(mut self, area: Rect) -> WorldQuery {
+        self.area = Some(area);
+        self
+    }
+    pub fn with_radius(mut self, position: Position, radius: f64) -> WorldQuery {
+        self.position = Some(position);
+        self.radius = Some(radius);
+        self
+    }
+    pub fn with_force(mut self, force: &str) -> WorldQuery {
+        self.force = Some(force.into());
+        self
+    }
+    pub fn with_direction(mut self, direction: u8) -> WorldQuery {
+        self.direction = Some(direction);
+        self
+    }
+    pub fn drops_into(mut self, query: WorldQuery) -> WorldQuery {
+        self.drops_into = Some(Box::new(query));
+        self
+    }
+    pub fn picks_from(mut self, query: WorldQuery) -> WorldQuery {
+        self.picks_from = Some(Box::new(query));
+        self
+    }
+    pub fn with_limit(mut self, limit: usize) -> WorldQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// checks only the attributes of the entity itself, not its relationships
+    pub fn matches_entity(&self, entity: &FactorioEntity) -> bool {
+        if let Some(name) = &self.name {
+            if entity.name != *name {
+                return false;
+            }
+        }
+        if let Some(entity_type) = &self.entity_type {
+            if entity.entity_type != *entity_type {
+                return false;
+            }
+        }
+        if let Some(area) = &self.area {
+            if !area.contains(&entity.position) {
+                return false;
+            }
+        }
+        if let Some(position) = &self.position {
+            if calculate_distance(position, &entity.position) > self.radius.unwrap_or(0.5) {
+                return false;
+            }
+        }
+        if let Some(force) = &self.force {
+            if entity.force.as_ref() != Some(force) {
+                return false;
+            }
+        }
+        if let Some(direction) = self.direction {
+            if entity.direction != direction {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn execute(&self, graph: &EntityGraph) -> Vec<FactorioEntity> {
+        let tree = graph.inner_tree();
+        let candidates: Vec<ItemId> = match &self.area {
+            Some(area) => tree
+                .query(area.clone().into())
+                .iter()
+                .map(|(_, _, item_id)| *item_id)
+                .collect(),
+            None => match (&self.position, self.radius) {
+                (Some(position), Some(radius)) => tree
+                    .query(
+                        Rect::new(
+                            &Position::new(position.x() - radius, position.y() - radius),
+                            &Position::new(position.x() + radius, position.y() + radius),
+                        )
+                        .into(),
+                    )
+                    .iter()
+                    .map(|(_, _, item_id)| *item_id)
+                    .collect(),
+                _ => {
+                    let inner = graph.inner_graph();
+                    let item_ids: Vec<ItemId> = inner
+                        .node_indices()
+                        .filter_map(|node_index| {
+                            inner
+                                .node_weight(node_index)
+                                .and_then(|node| node.entity_id)
+                        })
+                        .collect();
+                    item_ids
+                }
+            },
+        };
+        let mut results: Vec<FactorioEntity> = vec![];
+        for item_id in candidates {
+            if let Some(entity) = tree.get(item_id) {
+                if self.matches_entity(entity) && self.matches_relations(graph, &tree, item_id) {
+                    results.push(entity.clone());
+                }
+            }
+        }
+        results.sort_by(|a, b| {
+            a.position
+                .y()
+                .partial_cmp(&b.position.y())
+                .unwrap()
+                .then(a.position.x().partial_cmp(&b.position.x()).unwrap())
+        });
+        if let Some(limit) = self.limit {
+            results.truncate(limit);
+        }
+        results
+    }
+
+    fn matches_relations(
+        &self,
+        graph: &EntityGraph,
+        tree: &EntityQuadTree,
+        item_id: ItemId,
+    ) -> bool {
+        if self.drops_into.is_none() && self.picks_from.is_none() {
+            return true;
+        }
+        let node_index = match graph.node_by_id(&item_id) {
+            Some(node_index) => node_index,
+            None => return false,
+        };
+        if let Some(drops_into) = &self.drops_into {
+            let targets = graph.edges_directed(node_index, petgraph::Direction::Outgoing);
+            if !drops_into.matches_any_node(graph, tree, &targets) {
+                return false;
+            }
+        }
+        if let Some(picks_from) = &self.picks_from {
+            let inner = graph.inner_graph();
+            let sources: Vec<NodeIndex> = inner
+                .neighbors_directed(node_index, petgraph::Direction::Incoming)
+                .collect();
+            drop(inner);
+            if !picks_from.matches_any_node(graph, tree, &sources) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn matches_any_node(
+        &self,
+        graph: &EntityGraph,
+        tree: &EntityQuadTree,
+        nodes: &[NodeIndex],
+    ) -> bool {
+        nodes.iter().any(|node_index| {
+            graph
+                .node_weight(*node_index)
+                .and_then(|node| node.entity_id)
+                .map(|entity_id| {
+                    tree.get(entity_id)
+                        .map(|entity| {
+                            self.matches_entity(entity)
+                                && self.matches_relations(graph, tree, entity_id)
+                        })
+                        .unwrap_or(false)
+                })
+                .unwrap_or(false)
+        })
+    }
+}
+
+impl FromStr for WorldQuery {
+    type Err = anyhow::Error;
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(str)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factorio::tests::entity_graph_from;
+    use crate::num_traits::ToPrimitive;
+    use crate::types::Direction;
+
+    use super::*;
+
+    #[test]
+    fn test_inserters_into_furnaces() {
+        let graph = entity_graph_from(vec![
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::East),
+            FactorioEntity::new_transport_belt(&Position::new(1.5, 0.5), Direction::East),
+            FactorioEntity::new_transport_belt(&Position::new(2.5, 0.5), Direction::East),
+            FactorioEntity::new_inserter(&Position::new(0.5, 1.5), Direction::North),
+            FactorioEntity::new_inserter(&Position::new(2.5, 1.5), Direction::North),
+            FactorioEntity::new_stone_furnace(&Position::new(1., 3.), Direction::North),
+            FactorioEntity::new_transport_belt(&Position::new(2.5, 2.5), Direction::East),
+        ])
+        .unwrap();
+        let query = WorldQuery::new()
+            .with_type(EntityType::Inserter)
+            .with_area(Rect::from_wh(10., 10.))
+            .drops_into(WorldQuery::new().with_type(EntityType::Furnace));
+        let results = query.execute(&graph);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].position, Position::new(0.5, 1.5));
+
+        let query = WorldQuery::new()
+            .with_type(EntityType::TransportBelt)
+            .with_direction(Direction::East.to_u8().unwrap());
+        assert_eq!(query.execute(&graph).len(), 4);
+        // the limit keeps the first entities in reading order
+        let results = query.with_limit(2).execute(&graph);
+        assert_eq!(
+            results
+                .iter()
+                .map(|e| e.position.clone())
+                .collect::<Vec<_>>(),
+            vec![Position::new(0.5, 0.5), Position::new(1.5, 0.5)]
+        );
+
+        let query = WorldQuery::new()
+            .with_type(EntityType::Inserter)
+            .picks_from(WorldQuery::new().with_name("transport-belt"))
+            .with_limit(1);
+        assert_eq!(query.execute(&graph).len(), 1);
+    }
+}
@@ -82,7 +82,10 @@ pub struct PlayerLeftMessage {
 }
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct ResearchCompletedMessage {}
+pub struct ResearchCompletedMessage {
+    pub force_name: String,
+    pub technology: String,
+}
 
 #[derive(Message, Serialize)]
 #[rtype(result = "()")]
@@ -181,6 +184,7 @@ impl Handler<PlayerDistanceChangedMessage> for FactorioWebSocketServer {
             "updatePlayerDistance",
             serde_json::to_value(PlayerChangedDistanceEvent {
                 player_id: msg.player.player_id,
+                force: msg.player.force.clone(),
                 build_distance: msg.player.build_distance,
                 reach_distance: msg.player.reach_distance,
                 drop_item_distance: msg.player.drop_item_distance,
@@ -210,8 +214,8 @@ impl Handler<PlayerLeftMessage> for FactorioWebSocketServer {
 impl Handler<ResearchCompletedMessage> for FactorioWebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, _msg: ResearchCompletedMessage, _: &mut Context<Self>) {
-        self.broadcast(json!(["researchCompleted",]));
+    fn handle(&mut self, msg: ResearchCompletedMessage, _: &mut Context<Self>) {
+        self.broadcast(json!(["researchCompleted", msg.force_name, msg.technology]));
     }
 }
 
@@ -6,7 +6,8 @@ use clap::{App, Arg};
 use factorio_bot_backend::factorio::planner::start_factorio_and_plan_graph;
 use factorio_bot_backend::factorio::process_control::start_factorio;
 use factorio_bot_backend::factorio::rcon::{FactorioRcon, RconSettings};
-use factorio_bot_backend::factorio::roll_best_seed::{roll_seed, RollSeedLimit};
+use factorio_bot_backend::factorio::roll_best_seed::{roll_seed, seed_db_path, RollSeedLimit};
+use factorio_bot_backend::factorio::seed_db::{map_hash, SeedDb};
 use factorio_bot_backend::factorio::ws::FactorioWebSocketServer;
 use factorio_bot_backend::web::server::start_webserver;
 
@@ -78,8 +79,35 @@ async fn main() -> anyhow::Result<()> {
                         .default_value("1")
                         .help("number of clients to plan for"),
                 )
+                .arg(
+                    Arg::with_name("scoring")
+                        .long("scoring")
+                        .value_name("scoring")
+                        .help("use weights from [seed_score.<scoring>] instead of [seed_score]"),
+                )
                 .about("roll good seed for given map-exchange-string based on heuristics"),
         )
+        .subcommand(
+            App::new("seeds")
+                .about("show seeds scored by roll-seed")
+                .subcommand(
+                    App::new("list")
+                        .args(&seed_filter_args())
+                        .about("all rolls in the order they were scored"),
+                )
+                .subcommand(
+                    App::new("top")
+                        .args(&seed_filter_args())
+                        .arg(
+                            Arg::with_name("count")
+                                .short("n")
+                                .long("count")
+                                .default_value("10")
+                                .help("how many seeds to show"),
+                        )
+                        .about("best scored rolls first"),
+                ),
+        )
         .subcommand(
             App::new("plan")
                 .arg(
@@ -214,6 +242,7 @@ async fn main() -> anyhow::Result<()> {
             matches.value_of("parallel").unwrap().parse()?,
             matches.value_of("name").unwrap().into(),
             matches.value_of("clients").unwrap().parse()?,
+            matches.value_of("scoring").map(|scoring| scoring.into()),
         )
         .await?
         {
@@ -221,6 +250,52 @@ async fn main() -> anyhow::Result<()> {
         } else {
             eprintln!("no seed found");
         }
+    } else if let Some(matches) = matches.subcommand_matches("seeds") {
+        let workspace_path: String = settings.get("workspace_path")?;
+        let seed_db = SeedDb::open(&seed_db_path(&workspace_path))?;
+        let (rolls, matches) = match matches.subcommand() {
+            ("list", Some(matches)) => (
+                seed_db.list(
+                    matches.value_of("map").map(map_hash).as_deref(),
+                    matches.value_of("name"),
+                ),
+                matches,
+            ),
+            ("top", Some(matches)) => (
+                seed_db.top(
+                    matches.value_of("map").map(map_hash).as_deref(),
+                    matches.value_of("name"),
+                    matches.value_of("count").unwrap().parse()?,
+                ),
+                matches,
+            ),
+            _ => {
+                eprintln!("Missing required Sub Command: list or top");
+                std::process::exit(1);
+            }
+        };
+        for roll in rolls {
+            println!(
+                "{}\t{}\t{}\t{}\t{:.1}s{}",
+                roll.seed,
+                roll.score,
+                roll.plan_name,
+                roll.map_hash,
+                roll.duration_ms as f64 / 1000.,
+                if let Some(error) = &roll.error {
+                    format!("\tfailed: {}", error)
+                } else if roll.missing.is_empty() {
+                    String::new()
+                } else {
+                    format!("\tmissing {}", roll.missing.join(", "))
+                }
+            );
+            if matches.is_present("verbose") {
+                for (name, value) in &roll.parts {
+                    println!("\t{}: {}", name, value);
+                }
+            }
+        }
     } else if let Some(matches) = matches.subcommand_matches("plan") {
         let _graph = start_factorio_and_plan_graph(
             settings,
@@ -237,3 +312,20 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn seed_filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("map")
+            .long("map")
+            .value_name("map")
+            .help("only seeds rolled for given map exchange string"),
+        Arg::with_name("name")
+            .long("name")
+            .value_name("name")
+            .help("only seeds rolled with given plan"),
+        Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .help("show the score of every heuristic"),
+    ]
+}
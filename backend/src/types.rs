@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -13,7 +13,9 @@ use serde_json::Value;
 use typescript_definitions::TypeScriptify;
 
 use crate::factorio::entity_graph::QuadTreeRect;
-use crate::factorio::util::{add_to_rect, add_to_rect_turned, calculate_distance, rect_floor_ceil};
+use crate::factorio::util::{
+    add_to_rect, add_to_rect_turned, calculate_distance, rect_fields, rect_floor, rect_floor_ceil,
+};
 use crate::num_traits::FromPrimitive;
 
 pub type FactorioInventory = HashMap<String, u32>;
@@ -45,6 +47,25 @@ pub struct FactorioBlueprintInfo {
     pub data: Value,
 }
 
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlueprintConflict {
+    pub entity_name: String,
+    pub position: Position,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlueprintValidation {
+    pub valid: bool,
+    pub entities: Vec<FactorioEntity>, // blueprint entities at their final position
+    pub conflicts: Vec<BlueprintConflict>,
+    pub warnings: Vec<BlueprintConflict>, // e.g. covering ore with something else than a miner
+    pub obstacles: Vec<FactorioEntity>,   // trees and rocks to mine first
+    pub items: BTreeMap<String, u32>,     // entities which already exist are not counted
+}
+
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct FactorioIngredient {
@@ -66,6 +87,7 @@ pub struct FactorioProduct {
 #[serde(rename_all = "camelCase")]
 pub struct FactorioPlayer {
     pub player_id: u32,
+    pub force: String,
     pub position: Position,
     pub main_inventory: Box<BTreeMap<String, u32>>,
     pub build_distance: u32,          // for place_entity
@@ -80,6 +102,7 @@ impl Default for FactorioPlayer {
     fn default() -> Self {
         FactorioPlayer {
             player_id: 0,
+            force: String::from("player"),
             position: Position::default(),
             main_inventory: Box::new(BTreeMap::new()),
             build_distance: 10,
@@ -105,9 +128,172 @@ pub struct InventoryResponse {
     pub name: String,
     pub position: Position,
     pub output_inventory: Box<Option<BTreeMap<String, u32>>>,
+    pub input_inventory: Box<Option<BTreeMap<String, u32>>>,
     pub fuel_inventory: Box<Option<BTreeMap<String, u32>>>,
 }
 
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FactorioGroundItem {
+    pub name: String,
+    pub count: u32,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowEdgeSaturation {
+    pub from_name: String,
+    pub from_position: Position,
+    pub to_name: String,
+    pub to_position: Position,
+    // items per second
+    pub rate: f64,
+    // rate / capacity of the weakest entity, above 1 means the edge cannot carry the flow
+    pub saturation: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarvedMachine {
+    pub name: String,
+    pub position: Position,
+    // items per second
+    pub input_rate: f64,
+    pub required_rate: f64,
+    pub utilization: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowAnalysis {
+    pub edges: Vec<FlowEdgeSaturation>,
+    pub bottlenecks: Vec<FlowEdgeSaturation>,
+    pub starved: Vec<StarvedMachine>,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElectricNetwork {
+    pub id: u32,
+    pub poles: Vec<Position>,
+    pub generators: Vec<Position>,
+    pub consumers: Vec<Position>,
+    // watts
+    pub generation_capacity: f64,
+    pub consumption: f64,
+    // generation_capacity / consumption, capped to 1
+    pub satisfaction: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FactorioWireConnection {
+    pub wire: String, // red or green
+    pub target_name: String,
+    pub target_position: Position,
+    // combinators have separate input (1) and output (2) connection points
+    pub source_circuit_id: u8,
+    pub target_circuit_id: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalId {
+    pub signal_type: String, // item, fluid or virtual
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitSignal {
+    pub signal: SignalId,
+    pub count: i32,
+}
+
+impl rlua::ToLua<'_> for CircuitSignal {
+    fn to_lua(self, lua: Context) -> rlua::Result<rlua::Value> {
+        rlua_serde::to_value(lua, self)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CombinatorSettings {
+    pub operation: Option<String>, // arithmetic operation or decider comparator
+    pub first_signal: Option<SignalId>,
+    pub second_signal: Option<SignalId>,
+    pub constant: Option<i32>, // used instead of second_signal
+    pub output_signal: Option<SignalId>,
+    pub copy_count_from_input: Option<bool>, // only decider-combinator
+    pub constants: Option<Vec<CircuitSignal>>, // only constant-combinator
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RailSegment {
+    pub id: u32,
+    pub rails: Vec<Position>,
+    pub length: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RailBlock {
+    pub id: u32,
+    pub rails: Vec<Position>,
+    pub signals: Vec<Position>,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainStation {
+    pub name: String,
+    pub position: Position,
+    pub rail: Option<Position>, // rail the train stops on
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RailNetwork {
+    pub segments: Vec<RailSegment>,
+    pub blocks: Vec<RailBlock>,
+    pub stations: Vec<TrainStation>,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RailRoute {
+    pub from: String,
+    pub to: String,
+    pub rails: Vec<Position>,
+    pub length: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainScheduleRecord {
+    pub station: String,
+    pub wait_condition: String, // time, full, empty or inactivity
+    pub ticks: Option<u32>,     // only time and inactivity
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitConnector {
+    pub entity_name: String,
+    pub position: Position,
+    pub circuit_id: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitNetwork {
+    pub id: u32,
+    pub wire: String,
+    pub connectors: Vec<CircuitConnector>,
+}
+
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ChunkPosition {
@@ -124,7 +310,9 @@ impl From<&Pos> for ChunkPosition {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(
+    Debug, Clone, PartialEq, PartialOrd, TypeScriptify, Serialize, Deserialize, Hash, Eq, Ord,
+)]
 #[serde(rename_all = "camelCase")]
 pub struct Position {
     pub x: Box<R64>,
@@ -306,6 +494,12 @@ impl Rect {
             && position.y() > self.left_top.y()
             && position.y() < self.right_bottom.y()
     }
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.left_top.x() < other.right_bottom.x()
+            && other.left_top.x() < self.right_bottom.x()
+            && self.left_top.y() < other.right_bottom.y()
+            && other.left_top.y() < self.right_bottom.y()
+    }
 
     pub fn from_wh(width: f64, height: f64) -> Rect {
         Rect {
@@ -404,6 +598,7 @@ pub struct FactorioTechnology {
     pub order: String,
     pub level: u32,
     pub valid: bool,
+    pub unlocks_recipes: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
@@ -416,6 +611,16 @@ pub struct FactorioForce {
     // Progress of current research, as a number in range [0, 1].
     pub research_progress: Option<Box<R64>>,
     pub technologies: Box<BTreeMap<String, FactorioTechnology>>,
+    pub research_queue: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResearchProgressEvent {
+    pub force_name: String,
+    pub current_research: Option<String>,
+    pub research_progress: Option<Box<R64>>,
+    pub research_queue: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
@@ -497,6 +702,14 @@ pub struct FactorioEntityPrototype {
     pub crafting_speed: Box<Option<R64>>,
     pub max_underground_distance: Box<Option<u8>>,
     pub fluidbox_prototypes: Box<Option<Vec<FactorioFluidBoxPrototype>>>,
+    pub supply_area_distance: Box<Option<R64>>, // only type = electric-pole
+    pub max_wire_distance: Box<Option<R64>>,    // only type = electric-pole
+    pub electric_energy_usage: Box<Option<R64>>, // watts, only electric consumers
+    pub max_energy_production: Box<Option<R64>>, // watts, only generators
+    pub belt_speed: Box<Option<R64>>,           // tiles per tick, only belts
+    pub inserter_rotation_speed: Box<Option<R64>>, // revolutions per tick, only inserters
+    pub base_productivity: Box<Option<R64>>,    // only crafting machines and mining drills
+    pub mining_drill_radius: Box<Option<R64>>,  // only mining drills
 }
 
 #[derive(Debug, Clone, Default, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
@@ -510,11 +723,17 @@ pub struct FactorioEntity {
     pub drop_position: Option<Position>,
     pub pickup_position: Option<Position>, // only type = inserter
     pub output_inventory: Box<Option<BTreeMap<String, u32>>>,
+    pub input_inventory: Box<Option<BTreeMap<String, u32>>>,
     pub fuel_inventory: Box<Option<BTreeMap<String, u32>>>,
     pub amount: Option<u32>,        // only type = resource
     pub recipe: Option<String>,     // only CraftingMachines
     pub ghost_name: Option<String>, // only type = entity-ghost
     pub ghost_type: Option<String>, // only type = entity-ghost
+    pub force: Option<String>,
+    pub wire_connections: Option<Vec<FactorioWireConnection>>, // only red and green wires
+    pub combinator: Option<CombinatorSettings>,                // only combinators
+    pub station_name: Option<String>,                          // only type = train-stop
+    pub belt_to_ground_type: Option<String>, // only type = underground-belt, input or output
 }
 
 impl rlua::ToLua<'_> for FactorioEntity {
@@ -646,6 +865,67 @@ impl FactorioEntity {
             ..Default::default()
         }
     }
+    pub fn new_assembling_machine(
+        position: &Position,
+        direction: Direction,
+        recipe: &str,
+    ) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::AssemblingMachine1.to_string(),
+            entity_type: EntityType::AssemblingMachine.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(2.8, 2.8), &position, direction),
+            direction: direction.to_u8().unwrap(),
+            recipe: Some(recipe.into()),
+            ..Default::default()
+        }
+    }
+    pub fn new_straight_rail(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::StraightRail.to_string(),
+            entity_type: EntityType::StraightRail.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect(&Rect::from_wh(2., 2.), &position),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+    pub fn new_curved_rail(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::CurvedRail.to_string(),
+            entity_type: EntityType::CurvedRail.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(4., 8.), &position, direction),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+    pub fn new_small_electric_pole(position: &Position) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::SmallElectricPole.to_string(),
+            entity_type: EntityType::ElectricPole.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect(&Rect::from_wh(0.3, 0.3), &position),
+            ..Default::default()
+        }
+    }
+    pub fn new_steam_engine(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::SteamEngine.to_string(),
+            entity_type: EntityType::Generator.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(2.5, 4.7), &position, direction),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// spawners and worms, units are not kept in the entity graph
+    pub fn is_enemy(&self) -> bool {
+        self.force.as_deref() == Some("enemy")
+            && (self.entity_type == EntityType::UnitSpawner.to_string()
+                || self.entity_type == EntityType::Turret.to_string())
+    }
 
     pub fn is_minable(&self) -> bool {
         self.entity_type == EntityType::Tree.to_string()
@@ -692,15 +972,30 @@ pub enum EntityName {
     CopperPlate,
     IronPlate,
     Steel,
+    FirearmMagazine,
 
     // entities
     StoneFurnace,
+    #[strum(serialize = "assembling-machine-1")]
+    AssemblingMachine1,
     Inserter,
     BurnerMiningDrill,
     TransportBelt,
+    UndergroundBelt,
     Splitter,
     ElectricMiningDrill,
     Pumpjack,
+    SmallElectricPole,
+    SteamEngine,
+    Rail,
+    StraightRail,
+    CurvedRail,
+    RailSignal,
+    RailChainSignal,
+    TrainStop,
+    Locomotive,
+    StoneWall,
+    GunTurret,
 }
 
 #[derive(EnumString, Display, Debug, PartialEq, Clone)]
@@ -714,6 +1009,7 @@ pub enum EntityType {
     Resource,
     SimpleEntity,
     Tree,
+    Cliff,
     Inserter,
     MiningDrill,
     Furnace,
@@ -727,7 +1023,31 @@ pub enum EntityType {
     FlyingText,
     StraightRail,
     CurvedRail,
+    RailSignal,
+    RailChainSignal,
+    TrainStop,
+    Locomotive,
+    CargoWagon,
+    FluidWagon,
     Fish,
+    ItemEntity,
+    Character,
+    EntityGhost,
+    ElectricPole,
+    ConstantCombinator,
+    ArithmeticCombinator,
+    DeciderCombinator,
+    Generator,
+    SolarPanel,
+    Accumulator,
+    Unit,
+    UnitSpawner,
+    Turret,
+    AmmoTurret,
+    ElectricTurret,
+    FluidTurret,
+    Wall,
+    Gate,
 }
 
 impl EntityType {
@@ -745,6 +1065,7 @@ pub struct ResourcePatch {
     pub id: u32,
     pub rect: Rect,
     pub elements: Vec<Position>,
+    pub amounts: Vec<u32>, // same order as elements
 }
 
 impl ResourcePatch {
@@ -752,6 +1073,114 @@ impl ResourcePatch {
         let field: Vec<Pos> = self.elements.iter().map(|e| e.into()).collect();
         field.contains(&pos)
     }
+
+    pub fn total_amount(&self) -> u64 {
+        self.amounts.iter().map(|amount| *amount as u64).sum()
+    }
+
+    /// center of the patch weighted by amount, falls back to the tile center if amounts are unknown
+    pub fn centroid(&self) -> Position {
+        let total = self.total_amount() as f64;
+        let (mut x, mut y) = (0., 0.);
+        for (element, amount) in self.elements.iter().zip(&self.amounts) {
+            let weight = if total > 0. {
+                *amount as f64 / total
+            } else {
+                1. / self.elements.len() as f64
+            };
+            x += (element.x() + 0.5) * weight;
+            y += (element.y() + 0.5) * weight;
+        }
+        Position::new(x, y)
+    }
+
+    /// share of the bounding rect covered with ore, elements are the top left tile corners
+    pub fn fill_ratio(&self) -> f64 {
+        let area = (self.rect.width() + 1.) * (self.rect.height() + 1.);
+        if area > 0. {
+            (self.elements.len() as f64 / area).min(1.)
+        } else {
+            0.
+        }
+    }
+
+    /// 1 for a square, close to 0 for long or ragged patches.
+    /// the perimeter counts tile edges without neighbouring ore
+    pub fn compactness(&self) -> f64 {
+        let field: HashSet<Pos> = self.elements.iter().map(|e| e.into()).collect();
+        let mut perimeter = 0;
+        for pos in &field {
+            for (dx, dy) in &[(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                if !field.contains(&Pos(pos.0 + dx, pos.1 + dy)) {
+                    perimeter += 1;
+                }
+            }
+        }
+        if perimeter == 0 {
+            return 0.;
+        }
+        (16. * field.len() as f64 / (perimeter * perimeter) as f64).min(1.)
+    }
+
+    /// prefers rich and compact patches over the biggest tile count
+    pub fn score(&self) -> f64 {
+        (self.total_amount() as f64).sqrt() * self.compactness()
+    }
+
+    /// seconds until the given drills have mined out the patch, none if no drill covers it.
+    /// drills count with their whole rate as soon as one tile below them is part of the patch
+    pub fn depletion_seconds(
+        &self,
+        drills: &[FactorioEntity],
+        entity_prototypes: &DashMap<String, FactorioEntityPrototype>,
+    ) -> Option<f64> {
+        let field: HashSet<Pos> = self.elements.iter().map(|e| e.into()).collect();
+        let mining_time = entity_prototypes
+            .get(&self.name)
+            .and_then(|prototype| prototype.mining_time.map(|t| t.to_f64().unwrap()))
+            .unwrap_or(1.);
+        let mut rate = 0.;
+        for drill in drills {
+            let covers_patch = rect_fields(&rect_floor(&drill.bounding_box))
+                .iter()
+                .any(|p| field.contains(&p.into()));
+            if !covers_patch {
+                continue;
+            }
+            if let Some(prototype) = entity_prototypes.get(&drill.name) {
+                let mining_speed = prototype
+                    .mining_speed
+                    .map(|s| s.to_f64().unwrap())
+                    .unwrap_or(0.);
+                let productivity = prototype
+                    .base_productivity
+                    .map(|p| p.to_f64().unwrap())
+                    .unwrap_or(0.);
+                // https://wiki.factorio.com/Mining
+                rate += mining_speed / mining_time * (1. + productivity);
+            }
+        }
+        if rate > 0. {
+            Some(self.total_amount() as f64 / rate)
+        } else {
+            None
+        }
+    }
+
+    pub fn info(&self, depletion_seconds: Option<f64>) -> ResourcePatchInfo {
+        ResourcePatchInfo {
+            name: self.name.clone(),
+            id: self.id,
+            rect: self.rect.clone(),
+            tiles: self.elements.len() as u32,
+            amount: self.total_amount(),
+            centroid: self.centroid(),
+            fill_ratio: self.fill_ratio(),
+            compactness: self.compactness(),
+            score: self.score(),
+            depletion_seconds,
+        }
+    }
     pub fn find_free_rect(
         &self,
         width: u32,
@@ -803,6 +1232,115 @@ impl ResourcePatch {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiningOutpost {
+    pub resource_name: String,
+    pub drill_name: String,
+    pub drills: u32,
+    pub entities: Vec<FactorioEntity>,
+    pub outputs: Vec<Position>, // last belt of every drill row, empty when drills fill chests
+    pub cost: BTreeMap<String, u32>, // items needed to build all entities
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductionBlock {
+    pub recipe_name: String,
+    pub machine_name: String,
+    pub machines: u32,
+    pub rate: f64, // items per second of the main product with all machines running
+    pub entities: Vec<FactorioEntity>,
+    pub inputs: Vec<Position>,       // first input belt of every column
+    pub outputs: Vec<Position>,      // last output belt of every column
+    pub cost: BTreeMap<String, u32>, // items needed to build all entities
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeltRoute {
+    pub from: Position,
+    pub from_direction: u8,
+    pub to: Position,
+    pub to_direction: u8, // direction of the last belt, points into the target belt when merging
+    pub lanes: Option<u32>, // default 1, lanes after the first follow on its right side
+    pub belt_name: Option<String>, // default transport-belt
+    pub underground_name: Option<String>, // default underground-belt
+    pub merge: Option<bool>, // sideload into the belt in front of each lane end
+    pub avoid_resources: Option<bool>,
+    pub belt_cost: Option<u32>,        // default 10 per belt tile
+    pub turn_cost: Option<u32>,        // default 2 per turn
+    pub underground_cost: Option<u32>, // default 50 per underground pair
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpaceQuery {
+    pub width: u32,
+    pub height: u32,
+    pub near: Position,
+    pub margin: Option<u32>, // free tiles around the area, default 0
+    pub avoid_resources: Option<bool>, // default true
+    pub require_resource: Option<String>, // every tile has to hold this resource
+    pub align_to_chunk: Option<bool>, // left top on a 32 tile chunk border
+    pub max_distance: Option<u32>, // default 128 tiles
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPollution {
+    pub position: Position, // left top of the chunk
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnemyBase {
+    pub center: Position,
+    pub rect: Rect,
+    pub spawners: u32,
+    pub worms: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerimeterRequest {
+    pub area: Rect,
+    pub margin: Option<u32>, // tiles between the area and the turrets, default 2
+    pub wall_thickness: Option<u32>, // default 2
+    pub turret_spacing: Option<u32>, // tiles between turrets along the wall, default 8
+    pub turret_name: Option<String>, // default gun-turret
+    pub wall_name: Option<String>, // default stone-wall
+    pub ammo_name: Option<String>, // default firearm-magazine
+    pub ammo_per_turret: Option<u32>, // default 20
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefensePerimeter {
+    pub turrets: u32,
+    pub walls: u32,
+    pub entities: Vec<FactorioEntity>,
+    pub obstacles: Vec<FactorioEntity>, // trees and rocks to mine first
+    pub cost: BTreeMap<String, u32>,    // items needed to build all entities
+    pub ammo: BTreeMap<String, u32>,    // to fill every turret
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcePatchInfo {
+    pub name: String,
+    pub id: u32,
+    pub rect: Rect,
+    pub tiles: u32,
+    pub amount: u64,
+    pub centroid: Position,
+    pub fill_ratio: f64,
+    pub compactness: f64,
+    pub score: f64,
+    pub depletion_seconds: Option<f64>, // with the drills already built on the patch
+}
+
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct FactorioResult {
@@ -827,6 +1365,7 @@ pub struct PlaceEntitiesResult {
 #[serde(rename_all = "camelCase")]
 pub struct PlayerChangedDistanceEvent {
     pub player_id: u32,
+    pub force: String,
     pub build_distance: u32,
     pub reach_distance: u32,
     pub drop_item_distance: u32,
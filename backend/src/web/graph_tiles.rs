@@ -195,6 +195,13 @@ pub async fn flow_graph_tiles(
     let base_x = bounding_box.left_top.x();
     let base_y = bounding_box.left_top.y();
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
+    let starved: Vec<Position> = world
+        .flow_graph
+        .analyze()
+        .starved
+        .into_iter()
+        .map(|machine| machine.position)
+        .collect();
     for (entity, rect, _id) in world
         .entity_graph
         .inner_tree()
@@ -206,11 +213,20 @@ pub async fn flow_graph_tiles(
                     draw_hollow_rect_mut(
                         &mut buffer,
                         draw_rect,
-                        image::Rgba([3u8, 169u8, 244u8, 255u8]),
+                        if starved.contains(&entity.position) {
+                            image::Rgba([156u8, 39u8, 176u8, 255u8])
+                        } else {
+                            image::Rgba([3u8, 169u8, 244u8, 255u8])
+                        },
                     );
                     let graph = world.flow_graph.inner_graph();
+                    let flow_node = graph.node_weight(node_id).unwrap();
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Outgoing) {
                         if let Some(node) = graph.node_weight(edge.target()) {
+                            let saturation =
+                                world
+                                    .flow_graph
+                                    .edge_saturation(edge.weight(), flow_node, node);
                             draw_arrow_mut(
                                 &mut buffer,
                                 (
@@ -221,13 +237,17 @@ pub async fn flow_graph_tiles(
                                     ((node.position.x() - base_x) * scaling_factor) as f32,
                                     ((node.position.y() - base_y) * scaling_factor) as f32,
                                 ),
-                                image::Rgba([76u8, 175u8, 80u8, 255u8]),
+                                saturation_color(saturation),
                                 scaling_factor / 10.,
                             );
                         }
                     }
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Incoming) {
                         if let Some(node) = graph.node_weight(edge.source()) {
+                            let saturation =
+                                world
+                                    .flow_graph
+                                    .edge_saturation(edge.weight(), node, flow_node);
                             draw_arrow_mut(
                                 &mut buffer,
                                 (
@@ -238,7 +258,7 @@ pub async fn flow_graph_tiles(
                                     ((entity.position.x() - base_x) * scaling_factor) as f32,
                                     ((entity.position.y() - base_y) * scaling_factor) as f32,
                                 ),
-                                image::Rgba([76u8, 175u8, 80u8, 255u8]),
+                                saturation_color(saturation),
                                 scaling_factor / 10.,
                             );
                         }
@@ -259,6 +279,16 @@ pub async fn flow_graph_tiles(
         .body(build_image_body(buffer)))
 }
 
+/// green for edges with spare capacity over yellow and orange to red for overloaded ones
+fn saturation_color(saturation: Option<f64>) -> image::Rgba<u8> {
+    match saturation {
+        Some(saturation) if saturation > 1. => image::Rgba([244u8, 67u8, 54u8, 255u8]),
+        Some(saturation) if saturation > 0.8 => image::Rgba([255u8, 152u8, 0u8, 255u8]),
+        Some(saturation) if saturation > 0.5 => image::Rgba([255u8, 235u8, 59u8, 255u8]),
+        _ => image::Rgba([76u8, 175u8, 80u8, 255u8]),
+    }
+}
+
 pub fn tile_boundaries(z: i32, x: i32, y: i32) -> Rect {
     // one chunk is 32x32 positions big
     let map_size_chunks = 32f64; // map must be a certain size
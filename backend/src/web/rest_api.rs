@@ -1,13 +1,20 @@
 use crate::error::ActixAnyhowError;
+use crate::factorio::blueprint_transform::decode_blueprint_json;
 use crate::factorio::planner::Planner;
+use crate::factorio::rail_graph::rail_path;
 use crate::factorio::rcon::FactorioRcon;
-use crate::factorio::util::blueprint_build_area;
+use crate::factorio::tech_tree::ResearchPlan;
+use crate::factorio::util::{add_to_rect_turned, blueprint_build_area};
 use crate::factorio::world::FactorioWorld;
+use crate::factorio::world_query::WorldQuery;
 use crate::num_traits::FromPrimitive;
 use crate::types::{
-    AreaFilter, Direction, FactorioBlueprintInfo, FactorioEntity, FactorioEntityPrototype,
-    FactorioForce, FactorioItemPrototype, FactorioPlayer, FactorioRecipe, FactorioTile,
-    InventoryResponse, PlaceEntitiesResult, PlaceEntityResult, Position, RequestEntity,
+    AreaFilter, BeltRoute, BlueprintValidation, CircuitNetwork, CircuitSignal, DefensePerimeter,
+    Direction, ElectricNetwork, EnemyBase, EntityName, FactorioBlueprintInfo, FactorioEntity,
+    FactorioEntityPrototype, FactorioForce, FactorioItemPrototype, FactorioPlayer, FactorioRecipe,
+    FactorioTile, FlowAnalysis, FreeSpaceQuery, InventoryResponse, MiningOutpost, PerimeterRequest,
+    PlaceEntitiesResult, PlaceEntityResult, Position, ProductionBlock, RailNetwork, RailRoute,
+    Rect, RequestEntity, ResourcePatchInfo, TrainScheduleRecord,
 };
 use actix_web::web;
 use actix_web::web::{Json, Path as PathInfo};
@@ -55,6 +62,162 @@ pub async fn find_entities(
     ))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryQueryParams {
+    area: Option<String>,
+    position: Option<String>,
+    radius: Option<f64>,
+    name: Option<String>,
+    entity_type: Option<String>,
+    force: Option<String>,
+    direction: Option<u8>,
+    drops_into: Option<String>,
+    picks_from: Option<String>,
+    limit: Option<usize>,
+}
+
+// #[get("/query?<area>&<position>&<radius>&<name>&<entity_type>&<force>&<direction>&<drops_into>&<picks_from>&<limit>")]
+pub async fn query(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<QueryQueryParams>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    let query = WorldQuery {
+        name: info.name.clone(),
+        entity_type: info.entity_type.clone(),
+        area: match &info.area {
+            Some(area) => Some(area.parse()?),
+            None => None,
+        },
+        position: match &info.position {
+            Some(position) => Some(position.parse()?),
+            None => None,
+        },
+        radius: info.radius,
+        force: info.force.clone(),
+        direction: info.direction,
+        // relationships are given as entity type like dropsInto=furnace
+        drops_into: info
+            .drops_into
+            .as_ref()
+            .map(|entity_type| Box::new(WorldQuery::new().with_type_str(entity_type))),
+        picks_from: info
+            .picks_from
+            .as_ref()
+            .map(|entity_type| Box::new(WorldQuery::new().with_type_str(entity_type))),
+        limit: info.limit,
+    };
+    Ok(Json(world.query(&query)))
+}
+
+// #[get("/electricNetworks")]
+pub async fn electric_networks(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<ElectricNetwork>>, ActixAnyhowError> {
+    Ok(Json(world.entity_graph.electric_networks()))
+}
+
+// #[get("/circuitNetworks")]
+pub async fn circuit_networks(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<CircuitNetwork>>, ActixAnyhowError> {
+    Ok(Json(world.circuit_graph.networks()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitSignalsQueryParams {
+    name: String,
+    position: String,
+    wire: String,
+    circuit_id: Option<u8>,
+}
+// #[get("/circuitSignals?<name>&<position>&<wire>&<circuit_id>")]
+pub async fn circuit_signals(
+    rcon: web::Data<Arc<FactorioRcon>>,
+    info: actix_web::web::Query<CircuitSignalsQueryParams>,
+) -> Result<Json<Vec<CircuitSignal>>, ActixAnyhowError> {
+    Ok(Json(
+        rcon.circuit_signals(
+            &info.name,
+            &info.position.parse()?,
+            &info.wire,
+            info.circuit_id.unwrap_or(1),
+        )
+        .await?,
+    ))
+}
+
+// #[get("/railNetwork")]
+pub async fn rail_network(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<RailNetwork>, ActixAnyhowError> {
+    Ok(Json(world.rail_graph.network()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RailRouteQueryParams {
+    from: String,
+    to: String,
+}
+// #[get("/railRoute?<from>&<to>")]
+pub async fn rail_route(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<RailRouteQueryParams>,
+) -> Result<Json<RailRoute>, ActixAnyhowError> {
+    Ok(Json(world.rail_graph.route(&info.from, &info.to)?))
+}
+
+// #[get("/planRails?<from>&<to>")]
+pub async fn plan_rails(
+    info: actix_web::web::Query<RailRouteQueryParams>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    Ok(Json(rail_path(&info.from.parse()?, &info.to.parse()?)?))
+}
+
+// #[get("/<player_id>/placeRails?<from>&<to>")]
+pub async fn place_rails(
+    path: PathInfo<u32>,
+    info: actix_web::web::Query<RailRouteQueryParams>,
+    rcon: web::Data<Arc<FactorioRcon>>,
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    let rails = rail_path(&info.from.parse()?, &info.to.parse()?)?;
+    Ok(Json(rcon.place_rails(&world, *path, &rails).await?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainScheduleQueryParams {
+    position: String,
+}
+// #[post("/trainSchedule?<position>", format = "application/json", data = "<records>")]
+pub async fn set_train_schedule(
+    rcon: web::Data<Arc<FactorioRcon>>,
+    records: Json<Vec<TrainScheduleRecord>>,
+    info: actix_web::web::Query<TrainScheduleQueryParams>,
+) -> Result<Json<Value>, ActixAnyhowError> {
+    rcon.set_train_schedule(&info.position.parse()?, &records.into_inner())
+        .await?;
+    Ok(Json(json!({"status": "ok"})))
+}
+
+// #[get("/flowAnalysis")]
+pub async fn flow_analysis(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<FlowAnalysis>, ActixAnyhowError> {
+    world.flow_graph.update()?;
+    Ok(Json(world.flow_graph.analyze()))
+}
+
+// #[get("/unpoweredEntities")]
+pub async fn unpowered_entities(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    Ok(Json(world.entity_graph.unpowered_entities()))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlanPathQueryParams {
@@ -89,6 +252,48 @@ pub async fn plan_path(
     ))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteBeltsQueryParams {
+    from_position: String,
+    from_direction: u8,
+    to_position: String,
+    to_direction: u8,
+    lanes: Option<u32>,
+    belt_name: Option<String>,
+    underground_name: Option<String>,
+    merge: Option<bool>,
+    avoid_resources: Option<bool>,
+    belt_cost: Option<u32>,
+    turn_cost: Option<u32>,
+    underground_cost: Option<u32>,
+}
+
+// #[get("/routeBelts?<fromPosition>&<fromDirection>&<toPosition>&<toDirection>&<lanes>&<beltName>&<undergroundName>&<merge>&<avoidResources>&<beltCost>&<turnCost>&<undergroundCost>")]
+pub async fn route_belts(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<RouteBeltsQueryParams>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    let route = BeltRoute {
+        from: info.from_position.parse()?,
+        from_direction: info.from_direction,
+        to: info.to_position.parse()?,
+        to_direction: info.to_direction,
+        lanes: info.lanes,
+        belt_name: info.belt_name.clone(),
+        underground_name: info.underground_name.clone(),
+        merge: info.merge,
+        avoid_resources: info.avoid_resources,
+        belt_cost: info.belt_cost,
+        turn_cost: info.turn_cost,
+        underground_cost: info.underground_cost,
+    };
+    Ok(Json(crate::factorio::belt_router::route_belts(
+        &world.entity_graph,
+        &route,
+    )?))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FindTilesQueryParams {
@@ -395,6 +600,21 @@ pub async fn add_research(
     Ok(Json(json!({"status": "ok"})))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResearchPlanQueryParams {
+    tech: String,
+    force: Option<String>,
+}
+// #[get("/researchPlan?<tech>&<force>")]
+pub async fn research_plan(
+    info: actix_web::web::Query<ResearchPlanQueryParams>,
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<ResearchPlan>, ActixAnyhowError> {
+    let force_name = info.force.clone().unwrap_or_else(|| "player".into());
+    Ok(Json(world.research_plan(&force_name, &info.tech)?))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StoreMapDataQueryParams {
@@ -422,6 +642,25 @@ pub async fn retrieve_map_data(
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateBlueprintQueryParams {
+    blueprint: String,
+    position: String,
+    direction: Option<u8>,
+}
+// #[get("/validateBlueprint?<blueprint>&<position>&<direction>")]
+pub async fn validate_blueprint(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<ValidateBlueprintQueryParams>,
+) -> Result<Json<BlueprintValidation>, ActixAnyhowError> {
+    Ok(Json(world.validate_blueprint(
+        &info.blueprint,
+        &info.position.parse()?,
+        info.direction.unwrap_or(0),
+    )?))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlaceBlueprintQueryParams {
@@ -441,6 +680,23 @@ pub async fn place_blueprint(
     info: actix_web::web::Query<PlaceBlueprintQueryParams>,
 ) -> Result<Json<PlaceEntitiesResult>, ActixAnyhowError> {
     let player_id = *path;
+    let position: Position = info.position.parse()?;
+    let force_build = info.force_build.unwrap_or(false);
+    if !force_build {
+        let validation =
+            world.validate_blueprint(&info.blueprint, &position, info.direction.unwrap_or(0))?;
+        if !validation.valid {
+            let conflicts: Vec<String> = validation
+                .conflicts
+                .iter()
+                .map(|c| format!("{} at {}: {}", c.entity_name, c.position, c.reason))
+                .collect();
+            return Err(ActixAnyhowError::from(anyhow!(
+                "blueprint does not fit: {}",
+                conflicts.join(", ")
+            )));
+        }
+    }
     let inventory_player_ids: Vec<u32> = match info.inventory_player_ids.as_ref() {
         Some(inventory_player_ids) => inventory_player_ids
             .split(',')
@@ -452,9 +708,9 @@ pub async fn place_blueprint(
         .place_blueprint(
             player_id,
             info.blueprint.clone(),
-            &info.position.parse()?,
+            &position,
             info.direction.unwrap_or(0),
-            info.force_build.unwrap_or(false),
+            force_build,
             info.only_ghosts.unwrap_or(false),
             inventory_player_ids,
             &world,
@@ -562,6 +818,165 @@ pub async fn parse_blueprint(
     Ok(Json(response))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportBlueprintQueryParams {
+    area: String,
+    label: Option<String>,
+}
+
+// #[get("/exportBlueprint?<area>&<label>")]
+pub async fn export_blueprint(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<ExportBlueprintQueryParams>,
+) -> Result<Json<FactorioBlueprintInfo>, ActixAnyhowError> {
+    let label = info.label.clone().unwrap_or_default();
+    let blueprint = world.export_blueprint(&info.area.parse()?, &label)?;
+    let rect = blueprint_build_area(world.entity_prototypes.clone(), &blueprint);
+    Ok(Json(FactorioBlueprintInfo {
+        rect: rect.clone(),
+        label,
+        width: rect.width() as u16,
+        height: rect.height() as u16,
+        data: decode_blueprint_json(&blueprint)?,
+        blueprint,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcePatchesQueryParams {
+    name: String,
+}
+
+// #[get("/resourcePatches?<name>")]
+pub async fn resource_patches(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<ResourcePatchesQueryParams>,
+) -> Result<Json<Vec<ResourcePatchInfo>>, ActixAnyhowError> {
+    Ok(Json(world.resource_patches(&info.name)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiningOutpostQueryParams {
+    name: String,
+    position: String,
+    drill_name: Option<String>,
+}
+
+// #[get("/miningOutpost?<name>&<position>&<drillName>")]
+pub async fn mining_outpost(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<MiningOutpostQueryParams>,
+) -> Result<Json<MiningOutpost>, ActixAnyhowError> {
+    let drill_name = info
+        .drill_name
+        .clone()
+        .unwrap_or_else(|| EntityName::ElectricMiningDrill.to_string());
+    Ok(Json(world.mining_outpost(
+        &info.name,
+        &info.position.parse()?,
+        &drill_name,
+    )?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpaceQueryParams {
+    width: u32,
+    height: u32,
+    position: String,
+    margin: Option<u32>,
+    avoid_resources: Option<bool>,
+    require_resource: Option<String>,
+    align_to_chunk: Option<bool>,
+    max_distance: Option<u32>,
+}
+
+// #[get("/findFreeSpace?<width>&<height>&<position>&<margin>&<avoidResources>&<requireResource>&<alignToChunk>&<maxDistance>")]
+pub async fn find_free_space(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<FreeSpaceQueryParams>,
+) -> Result<Json<Rect>, ActixAnyhowError> {
+    let query = FreeSpaceQuery {
+        width: info.width,
+        height: info.height,
+        near: info.position.parse()?,
+        margin: info.margin,
+        avoid_resources: info.avoid_resources,
+        require_resource: info.require_resource.clone(),
+        align_to_chunk: info.align_to_chunk,
+        max_distance: info.max_distance,
+    };
+    Ok(Json(world.free_space(&query)?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductionBlockQueryParams {
+    recipe_name: String,
+    rate: f64,
+    inputs: String,
+    machine_name: Option<String>,
+}
+
+// #[get("/productionBlock?<recipeName>&<rate>&<inputs>&<machineName>")]
+pub async fn production_block(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<ProductionBlockQueryParams>,
+) -> Result<Json<ProductionBlock>, ActixAnyhowError> {
+    let inputs = info
+        .inputs
+        .split(';')
+        .map(|input| input.parse())
+        .collect::<anyhow::Result<Vec<Position>>>()?;
+    Ok(Json(world.production_block(
+        &info.recipe_name,
+        info.rate,
+        &inputs,
+        info.machine_name.as_deref(),
+    )?))
+}
+
+// #[get("/enemyBases")]
+pub async fn enemy_bases(
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<EnemyBase>>, ActixAnyhowError> {
+    Ok(Json(world.entity_graph.enemy_bases()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefensePerimeterQueryParams {
+    area: String,
+    margin: Option<u32>,
+    wall_thickness: Option<u32>,
+    turret_spacing: Option<u32>,
+    turret_name: Option<String>,
+    wall_name: Option<String>,
+    ammo_name: Option<String>,
+    ammo_per_turret: Option<u32>,
+}
+
+// #[get("/defensePerimeter?<area>&<margin>&<wallThickness>&<turretSpacing>&<turretName>&<wallName>&<ammoName>&<ammoPerTurret>")]
+pub async fn defense_perimeter(
+    world: web::Data<Arc<FactorioWorld>>,
+    info: actix_web::web::Query<DefensePerimeterQueryParams>,
+) -> Result<Json<DefensePerimeter>, ActixAnyhowError> {
+    let request = PerimeterRequest {
+        area: info.area.parse()?,
+        margin: info.margin,
+        wall_thickness: info.wall_thickness,
+        turret_spacing: info.turret_spacing,
+        turret_name: info.turret_name.clone(),
+        wall_name: info.wall_name.clone(),
+        ammo_name: info.ammo_name.clone(),
+        ammo_per_turret: info.ammo_per_turret,
+    };
+    Ok(Json(world.defense_perimeter(&request)?))
+}
+
 // #[get("/recipes")]
 pub async fn all_recipes(
     world: web::Data<Arc<FactorioWorld>>,
@@ -675,6 +1090,52 @@ pub async fn find_offshore_pump_placement_options(
     ))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanPumpToBoilerQueryParams {
+    boiler_position: String,
+    boiler_direction: u8,
+    pump_direction: u8,
+}
+// #[get("/planPumpToBoiler?<boilerPosition>&<boilerDirection>&<pumpDirection>")]
+pub async fn plan_pump_to_boiler(
+    info: actix_web::web::Query<PlanPumpToBoilerQueryParams>,
+    rcon: web::Data<Arc<FactorioRcon>>,
+    world: web::Data<Arc<FactorioWorld>>,
+) -> Result<Json<Vec<FactorioEntity>>, ActixAnyhowError> {
+    let position: Position = info.boiler_position.parse()?;
+    let direction = Direction::from_u8(info.boiler_direction)
+        .ok_or_else(|| anyhow!("invalid boiler direction {}", info.boiler_direction))?;
+    let pump_direction = Direction::from_u8(info.pump_direction)
+        .ok_or_else(|| anyhow!("invalid pump direction {}", info.pump_direction))?;
+    // an existing boiler or one which is yet to be built
+    let boiler = match world
+        .entity_graph
+        .entity_at(&position)
+        .and_then(|id| world.entity_graph.entity_by_id(id))
+    {
+        Some(boiler) => boiler,
+        None => {
+            let prototype = world
+                .entity_prototypes
+                .get("boiler")
+                .ok_or_else(|| anyhow!("boiler prototype not found"))?;
+            FactorioEntity {
+                name: prototype.name.clone(),
+                entity_type: prototype.entity_type.clone(),
+                bounding_box: add_to_rect_turned(&prototype.collision_box, &position, direction),
+                position,
+                direction: info.boiler_direction,
+                ..Default::default()
+            }
+        }
+    };
+    Ok(Json(
+        rcon.plan_pump_to_boiler(&world, &boiler, pump_direction)
+            .await?,
+    ))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlanQueryParams {
@@ -744,7 +1205,6 @@ pub async fn plans() -> Result<Json<Vec<String>>, ActixAnyhowError> {
 pub async fn web_entity_graph(
     world: web::Data<Arc<FactorioWorld>>,
 ) -> Result<String, ActixAnyhowError> {
-    world.entity_graph.connect()?;
     let dot = world.entity_graph.graphviz_dot_condensed();
     Ok(dot)
 }
@@ -758,7 +1218,6 @@ pub async fn web_task_graph(
 pub async fn web_flow_graph(
     world: web::Data<Arc<FactorioWorld>>,
 ) -> Result<String, ActixAnyhowError> {
-    world.entity_graph.connect()?;
     world.flow_graph.update()?;
     let dot = world.flow_graph.graphviz_dot_condensed();
     Ok(dot)
@@ -97,6 +97,47 @@ pub async fn start_webserver(
                 web::resource("/api/findEntities")
                     .route(web::get().to(crate::web::rest_api::find_entities)),
             )
+            .service(web::resource("/api/query").route(web::get().to(crate::web::rest_api::query)))
+            .service(
+                web::resource("/api/electricNetworks")
+                    .route(web::get().to(crate::web::rest_api::electric_networks)),
+            )
+            .service(
+                web::resource("/api/validateBlueprint")
+                    .route(web::get().to(crate::web::rest_api::validate_blueprint)),
+            )
+            .service(
+                web::resource("/api/railNetwork")
+                    .route(web::get().to(crate::web::rest_api::rail_network)),
+            )
+            .service(
+                web::resource("/api/railRoute")
+                    .route(web::get().to(crate::web::rest_api::rail_route)),
+            )
+            .service(
+                web::resource("/api/planRails")
+                    .route(web::get().to(crate::web::rest_api::plan_rails)),
+            )
+            .service(
+                web::resource("/api/trainSchedule")
+                    .route(web::post().to(crate::web::rest_api::set_train_schedule)),
+            )
+            .service(
+                web::resource("/api/circuitNetworks")
+                    .route(web::get().to(crate::web::rest_api::circuit_networks)),
+            )
+            .service(
+                web::resource("/api/circuitSignals")
+                    .route(web::get().to(crate::web::rest_api::circuit_signals)),
+            )
+            .service(
+                web::resource("/api/flowAnalysis")
+                    .route(web::get().to(crate::web::rest_api::flow_analysis)),
+            )
+            .service(
+                web::resource("/api/unpoweredEntities")
+                    .route(web::get().to(crate::web::rest_api::unpowered_entities)),
+            )
             .service(
                 web::resource("/api/runPlan").route(web::get().to(crate::web::rest_api::run_plan)),
             )
@@ -142,6 +183,10 @@ pub async fn start_webserver(
                 web::resource("/api/{player_id}/playerInfo")
                     .route(web::get().to(crate::web::rest_api::player_info)),
             )
+            .service(
+                web::resource("/api/{player_id}/placeRails")
+                    .route(web::get().to(crate::web::rest_api::place_rails)),
+            )
             .service(
                 web::resource("/api/{player_id}/placeEntity")
                     .route(web::get().to(crate::web::rest_api::place_entity)),
@@ -186,6 +231,10 @@ pub async fn start_webserver(
                 web::resource("/api/addResearch")
                     .route(web::get().to(crate::web::rest_api::add_research)),
             )
+            .service(
+                web::resource("/api/researchPlan")
+                    .route(web::get().to(crate::web::rest_api::research_plan)),
+            )
             .service(
                 web::resource("/api/storeMapData")
                     .route(web::post().to(crate::web::rest_api::store_map_data)),
@@ -210,6 +259,42 @@ pub async fn start_webserver(
                 web::resource("/api/parseBlueprint")
                     .route(web::get().to(crate::web::rest_api::parse_blueprint)),
             )
+            .service(
+                web::resource("/api/planPumpToBoiler")
+                    .route(web::get().to(crate::web::rest_api::plan_pump_to_boiler)),
+            )
+            .service(
+                web::resource("/api/routeBelts")
+                    .route(web::get().to(crate::web::rest_api::route_belts)),
+            )
+            .service(
+                web::resource("/api/resourcePatches")
+                    .route(web::get().to(crate::web::rest_api::resource_patches)),
+            )
+            .service(
+                web::resource("/api/exportBlueprint")
+                    .route(web::get().to(crate::web::rest_api::export_blueprint)),
+            )
+            .service(
+                web::resource("/api/miningOutpost")
+                    .route(web::get().to(crate::web::rest_api::mining_outpost)),
+            )
+            .service(
+                web::resource("/api/findFreeSpace")
+                    .route(web::get().to(crate::web::rest_api::find_free_space)),
+            )
+            .service(
+                web::resource("/api/productionBlock")
+                    .route(web::get().to(crate::web::rest_api::production_block)),
+            )
+            .service(
+                web::resource("/api/enemyBases")
+                    .route(web::get().to(crate::web::rest_api::enemy_bases)),
+            )
+            .service(
+                web::resource("/api/defensePerimeter")
+                    .route(web::get().to(crate::web::rest_api::defense_perimeter)),
+            )
             .service(
                 web::resource("/api/recipes")
                     .route(web::get().to(crate::web::rest_api::all_recipes)),
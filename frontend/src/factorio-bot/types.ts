@@ -119,21 +119,41 @@ export type FactorioBlueprint = {
 export type FactorioFluidBoxPrototype = { pipeConnections: FactorioFluidBoxConnection [] | null; productionType: string };
 export type FactorioFluidBoxConnection = { maxUndergroundDistance: number | null; connectionType: string; positions: Position [] };
 export type FactorioBlueprintInfo = { label: string; blueprint: string; width: number; height: number; rect: Rect; data: object };
-export type PlayerChangedDistanceEvent = { playerId: number; buildDistance: number; reachDistance: number; dropItemDistance: number; itemPickupDistance: number; lootPickupDistance: number; resourceReachDistance: number };
+export type PlayerChangedDistanceEvent = { playerId: number; force: string; buildDistance: number; reachDistance: number; dropItemDistance: number; itemPickupDistance: number; lootPickupDistance: number; resourceReachDistance: number };
 export type PlayerChangedPositionEvent = { playerId: number; position: Position };
 export type PlayerChangedMainInventoryEvent = { playerId: number; mainInventory: { [key: string]: number } };
 export type PlayerLeftEvent = { playerId: number };
 export type RequestEntity = { name: string; position: Position };
 export type FactorioTile = { name: string; playerCollidable: boolean; position: Position };
-export type FactorioTechnology = { name: string; enabled: boolean; upgrade: boolean; researched: boolean; prerequisites: string [] | null; researchUnitIngredients: FactorioIngredient []; researchUnitCount: number; researchUnitEnergy: number; order: string; level: number; valid: boolean };
-export type FactorioForce = { name: string; forceId: number; currentResearch: string | null; researchProgress: number | null; technologies: { [key: string]: FactorioTechnology } };
-export type InventoryResponse = { name: string; position: Position; outputInventory: { [key: string]: number } | null; fuelInventory: { [key: string]: number } | null };
+export type FactorioTechnology = { name: string; enabled: boolean; upgrade: boolean; researched: boolean; prerequisites: string [] | null; researchUnitIngredients: FactorioIngredient []; researchUnitCount: number; researchUnitEnergy: number; order: string; level: number; valid: boolean; unlocksRecipes: string [] | null };
+export type FactorioForce = { name: string; forceId: number; currentResearch: string | null; researchProgress: number | null; technologies: { [key: string]: FactorioTechnology }; researchQueue: string [] | null };
+export type ResearchProgressEvent = { forceName: string; currentResearch: string | null; researchProgress: number | null; researchQueue: string [] | null };
+export type InventoryResponse = { name: string; position: Position; outputInventory: { [key: string]: number } | null; inputInventory: { [key: string]: number } | null; fuelInventory: { [key: string]: number } | null };
+export type FactorioGroundItem = { name: string; count: number; position: Position };
+export type FlowEdgeSaturation = { fromName: string; fromPosition: Position; toName: string; toPosition: Position; rate: number; saturation: number };
+export type StarvedMachine = { name: string; position: Position; inputRate: number; requiredRate: number; utilization: number };
+export type FlowAnalysis = { edges: FlowEdgeSaturation []; bottlenecks: FlowEdgeSaturation []; starved: StarvedMachine [] };
+export type FactorioWireConnection = { wire: string; targetName: string; targetPosition: Position; sourceCircuitId: number; targetCircuitId: number };
+export type SignalId = { signalType: string; name: string };
+export type CircuitSignal = { signal: SignalId; count: number };
+export type CombinatorSettings = { operation: string | null; firstSignal: SignalId | null; secondSignal: SignalId | null; constant: number | null; outputSignal: SignalId | null; copyCountFromInput: boolean | null; constants: CircuitSignal [] | null };
+export type CircuitConnector = { entityName: string; position: Position; circuitId: number };
+export type CircuitNetwork = { id: number; wire: string; connectors: CircuitConnector [] };
+export type BlueprintConflict = { entityName: string; position: Position; reason: string };
+export type BlueprintValidation = { valid: boolean; entities: FactorioEntity []; conflicts: BlueprintConflict []; warnings: BlueprintConflict []; obstacles: FactorioEntity []; items: { [key: string]: number } };
+export type RailSegment = { id: number; rails: Position []; length: number };
+export type RailBlock = { id: number; rails: Position []; signals: Position [] };
+export type TrainStation = { name: string; position: Position; rail: Position | null };
+export type RailNetwork = { segments: RailSegment []; blocks: RailBlock []; stations: TrainStation [] };
+export type RailRoute = { from: string; to: string; rails: Position []; length: number };
+export type TrainScheduleRecord = { station: string; waitCondition: string; ticks: number | null };
+export type ElectricNetwork = { id: number; poles: Position []; generators: Position []; consumers: Position []; generationCapacity: number; consumption: number; satisfaction: number };
 export type FactorioRecipe = { name: string; valid: boolean; enabled: boolean; category: string; ingredients: FactorioIngredient [] | null; products: FactorioProduct []; hidden: boolean; energy: number; order: string; group: string; subgroup: string };
 export type PlaceEntityResult = { player: FactorioPlayer; entity: FactorioEntity };
 export type PlaceEntitiesResult = { player: FactorioPlayer; entities: FactorioEntity [] };
 export type FactorioIngredient = { name: string; ingredientType: string; amount: number };
 export type FactorioProduct = { name: string; productType: string; amount: number; probability: number };
-export type FactorioPlayer = { playerId: number; position: Position; mainInventory: { [key: string]: number };
+export type FactorioPlayer = { playerId: number; force: string; position: Position; mainInventory: { [key: string]: number };
  buildDistance: number; reachDistance: number; dropItemDistance: number; itemPickupDistance: number; lootPickupDistance: number; resourceReachDistance: number };
 export type ChunkPosition = { x: number; y: number };
 export type Position = { x: number; y: number };
@@ -142,7 +162,16 @@ export type FactorioChunk = { entities: FactorioEntity []; tiles: FactorioTile [
 export type ChunkObject = { name: string; position: Position; direction: string; boundingBox: Rect; outputInventory: { [key: string]: number } | null; fuelInventory: { [key: string]: number } | null };
 export type ChunkResource = { name: string; position: Position };
 export type FactorioGraphic = { entityName: string; imagePath: string; width: number; height: number };
-export type FactorioEntity = { name: string; entityType: string; position: Position; boundingBox: Rect; direction: number; dropPosition: Position | null; pickupPosition: Position | null; outputInventory: { [key: string]: number } | null; fuelInventory: { [key: string]: number } | null; amount: number | null; recipe: string | null; ghostName: string | null; ghostType: string | null };
-export type FactorioEntityPrototype = { name: string; entityType: string; collisionMask: string [] | null; collisionBox: Rect; mineResult: { [key: string]: number } | null; miningTime: number | null; miningSpeed: number | null; craftingSpeed: number | null; fluidboxPrototypes: FactorioFluidBoxPrototype [] | null };
+export type FactorioEntity = { name: string; entityType: string; position: Position; boundingBox: Rect; direction: number; dropPosition: Position | null; pickupPosition: Position | null; outputInventory: { [key: string]: number } | null; inputInventory: { [key: string]: number } | null; fuelInventory: { [key: string]: number } | null; amount: number | null; recipe: string | null; ghostName: string | null; ghostType: string | null; force: string | null; wireConnections: FactorioWireConnection [] | null; combinator: CombinatorSettings | null; stationName: string | null; beltToGroundType: string | null };
+export type FactorioEntityPrototype = { name: string; entityType: string; collisionMask: string [] | null; collisionBox: Rect; mineResult: { [key: string]: number } | null; miningTime: number | null; miningSpeed: number | null; craftingSpeed: number | null; fluidboxPrototypes: FactorioFluidBoxPrototype [] | null; supplyAreaDistance: number | null; maxWireDistance: number | null; electricEnergyUsage: number | null; maxEnergyProduction: number | null; beltSpeed: number | null; inserterRotationSpeed: number | null; baseProductivity: number | null; miningDrillRadius: number | null };
 export type FactorioItemPrototype = { name: string; itemType: string; stackSize: number; fuelValue: number; placeResult: string; group: string; subgroup: string };
+export type MiningOutpost = { resourceName: string; drillName: string; drills: number; entities: FactorioEntity []; outputs: Position []; cost: { [key: string]: number } };
+export type FreeSpaceQuery = { width: number; height: number; near: Position; margin: number | null; avoidResources: boolean | null; requireResource: string | null; alignToChunk: boolean | null; maxDistance: number | null };
+export type ProductionBlock = { recipeName: string; machineName: string; machines: number; rate: number; entities: FactorioEntity []; inputs: Position []; outputs: Position []; cost: { [key: string]: number } };
+export type BeltRoute = { from: Position; fromDirection: number; to: Position; toDirection: number; lanes: number | null; beltName: string | null; undergroundName: string | null; merge: boolean | null; avoidResources: boolean | null; beltCost: number | null; turnCost: number | null; undergroundCost: number | null };
+export type ChunkPollution = { position: Position; amount: number };
+export type EnemyBase = { center: Position; rect: Rect; spawners: number; worms: number };
+export type PerimeterRequest = { area: Rect; margin: number | null; wallThickness: number | null; turretSpacing: number | null; turretName: string | null; wallName: string | null; ammoName: string | null; ammoPerTurret: number | null };
+export type DefensePerimeter = { turrets: number; walls: number; entities: FactorioEntity []; obstacles: FactorioEntity []; cost: { [key: string]: number }; ammo: { [key: string]: number } };
+export type ResourcePatchInfo = { name: string; id: number; rect: Rect; tiles: number; amount: number; centroid: Position; fillRatio: number; compactness: number; score: number; depletionSeconds: number | null };
 export type FactorioResult = { success: boolean; output: string [] };
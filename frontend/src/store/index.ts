@@ -208,6 +208,7 @@ export default new Vuex.Store({
             const playerId = event.playerId.toString();
             const player: FactorioPlayer = {...(state.players[playerId] ? state.players[playerId] : {
                     playerId: event.playerId,
+                    force: 'player',
                     position: {x: 0, y: 0},
                     mainInventory: {},
                     buildDistance: 0,
@@ -223,6 +224,7 @@ export default new Vuex.Store({
             const playerId = event.playerId.toString();
             const player: FactorioPlayer = {...(state.players[playerId] ? state.players[playerId] : {
                     playerId: event.playerId,
+                    force: 'player',
                     position: {x: 0, y: 0},
                     mainInventory: {},
                     buildDistance: 0,
@@ -238,6 +240,7 @@ export default new Vuex.Store({
                 itemPickupDistance: event.itemPickupDistance,
                 lootPickupDistance: event.lootPickupDistance,
                 resourceReachDistance: event.resourceReachDistance,
+                force: event.force,
             };
             Vue.set(state.players, playerId, player)
         },
@@ -219,6 +219,7 @@ function on_init()
 	global.pathfinding = {}
 	global.pathfinding.map = {}
 	global.n_clients = 1
+	global.tracked_inventories = {}
 end
 
 function pos_str(pos)
@@ -674,6 +675,19 @@ function on_tick(event)
 		writeout_initial_stuff()
 	end
 
+	if event.tick % 60 == 0 then
+		writeout_inventory_changes(event.tick)
+		writeout_ground_items(event.tick)
+	end
+
+	if event.tick % 300 == 0 then
+		writeout_research_progress(event.tick)
+	end
+
+	if event.tick % 600 == 0 then
+		writeout_enemies(event.tick)
+	end
+
 	if event.tick % 120 == 0 then
 		local who = "?"
 		if client_local_data.whoami then who = client_local_data.whoami end
@@ -905,12 +919,106 @@ function writeout_entities(tick, surface, area)
 	for idx, ent in pairs(surface.find_entities(area)) do
 		if ent.type ~= "character" and area.left_top.x <= ent.position.x and ent.position.x < area.right_bottom.x and area.left_top.y <= ent.position.y and ent.position.y < area.right_bottom.y then
 			table.insert(objects, serialize_entity(ent))
+			track_inventory(ent)
 		end
 	end
 	writeout(tick, "entities", header .. game.table_to_json(objects))
 	line=nil
 end
 
+-- entities whose inventories are streamed to the bot on change:
+-- chests, furnaces, assemblers and everything that burns fuel like burner drills or boilers
+function is_inventory_tracked(ent)
+	if ent.unit_number == nil then
+		return false
+	end
+	return ent.type == "container" or ent.type == "logistic-container" or ent.type == "furnace"
+		or ent.type == "assembling-machine" or ent.type == "lab"
+		or (ent.type == "mining-drill" and ent.burner ~= nil)
+		or (ent.type == "boiler" and ent.burner ~= nil)
+end
+
+function track_inventory(ent)
+	if global.tracked_inventories == nil then
+		global.tracked_inventories = {}
+	end
+	if is_inventory_tracked(ent) then
+		global.tracked_inventories[ent.unit_number] = {entity = ent, last = nil}
+	end
+end
+
+function untrack_inventory(ent)
+	if global.tracked_inventories ~= nil and ent.unit_number ~= nil then
+		global.tracked_inventories[ent.unit_number] = nil
+	end
+end
+
+function writeout_inventory_changes(tick)
+	if global.tracked_inventories == nil then
+		return
+	end
+	for unit_number, tracked in pairs(global.tracked_inventories) do
+		if tracked.entity.valid then
+			local json = game.table_to_json(serialize_entity_inventory(tracked.entity))
+			if json ~= tracked.last then
+				tracked.last = json
+				writeout(tick, "on_entity_inventory_changed", json)
+			end
+		else
+			global.tracked_inventories[unit_number] = nil
+		end
+	end
+end
+
+-- ground items have no events, so we rescan the area around every player
+function writeout_ground_items(tick)
+	local radius = 32
+	for idx, player in pairs(game.players) do
+		if player.connected and player.character then
+			local x = math.floor(player.position.x)
+			local y = math.floor(player.position.y)
+			local area = {left_top={x=x-radius, y=y-radius}, right_bottom={x=x+radius, y=y+radius}}
+			local items = {}
+			for _, ent in pairs(player.surface.find_entities_filtered{area=area, type="item-entity"}) do
+				table.insert(items, serialize_ground_item(ent))
+			end
+			local line = aabb_str(area) .. ":" .. game.table_to_json(items)
+			if global.p[idx] ~= nil and global.p[idx].last_ground_items ~= line then
+				global.p[idx].last_ground_items = line
+				writeout(tick, "ground_items", line)
+			end
+		end
+	end
+end
+
+-- biters move around and pollution spreads without events, so both are rescanned as well.
+-- spawners and worms are static and come with the chunk entities
+function writeout_enemies(tick)
+	local radius = 128
+	for idx, player in pairs(game.players) do
+		if player.connected and player.character then
+			local x = math.floor(player.position.x / 32) * 32
+			local y = math.floor(player.position.y / 32) * 32
+			local area = {left_top={x=x-radius, y=y-radius}, right_bottom={x=x+radius, y=y+radius}}
+			local units = {}
+			for _, ent in pairs(player.surface.find_entities_filtered{area=area, type="unit", force="enemy"}) do
+				table.insert(units, serialize_entity(ent))
+			end
+			writeout(tick, "enemy_units", aabb_str(area) .. ":" .. game.table_to_json(units))
+			local pollution = {}
+			for chunk_x = x - radius, x + radius - 32, 32 do
+				for chunk_y = y - radius, y + radius - 32, 32 do
+					local amount = player.surface.get_pollution({chunk_x, chunk_y})
+					if amount > 0 then
+						table.insert(pollution, {position={x=chunk_x, y=chunk_y}, amount=amount})
+					end
+				end
+			end
+			writeout(tick, "pollution", aabb_str(area) .. ":" .. game.table_to_json(pollution))
+		end
+	end
+end
+
 function writeout(tick, key, value)
 	print("§"..tick.."§"..key.."§"..tostring(value))
 end
@@ -1004,6 +1112,7 @@ function on_some_entity_created(event)
 	end
 
 	writeout(event.tick, "on_some_entity_created", game.table_to_json(serialize_entity(ent)))
+	track_inventory(ent)
 
 --	if ent.type == "pipe" or ent.type == "pipe-to-ground" or ent.type == "wall" or ent.type == "heat-pipe" then -- HACK to semi-correctly assign an orientation to pipes etc
 --		-- need to write out neighboring entities as well, because they might have changed their orientation by this event
@@ -1031,6 +1140,7 @@ function on_some_entity_deleted(event)
 		return
 	end
 	writeout(event.tick, "on_some_entity_deleted", game.table_to_json(serialize_entity(ent)))
+	untrack_inventory(ent)
 
 --	-- we can't do this now, because the entity still exists at this point. instead, we schedule the writeout for the next tick
 --
@@ -1149,6 +1259,7 @@ function on_player_changed_distance(event)
 	for idx, player in pairs(game.players) do
 		writeout(event.tick, "on_player_changed_distance", game.table_to_json({
 			playerId = idx,
+			force = player.force.name,
 			buildDistance = player.build_distance,
 			reachDistance = player.reach_distance,
 			dropItemDistance = player.drop_item_distance,
@@ -1162,10 +1273,21 @@ end
 function on_research_finished(event)
 	writeout_recipes()
 	on_player_changed_distance(event)
-	writeout(event.tick, "on_research_finished", "")
+	writeout(event.tick, "on_research_finished", event.research.force.name .. " " .. event.research.name)
 	writeout_forces()
 end
 
+function on_research_started(event)
+	writeout(event.tick, "research_progress", game.table_to_json(serialize_research_progress(event.research.force)))
+end
+
+function writeout_research_progress(tick)
+	local force = game.forces["player"]
+	if force.current_research ~= nil then
+		writeout(tick, "research_progress", game.table_to_json(serialize_research_progress(force)))
+	end
+end
+
 function on_player_main_inventory_changed(event)
 	local tick = event.tick
 	local player_idx = event.player_index
@@ -1190,6 +1312,9 @@ script.on_event(defines.events.on_biter_base_built, on_some_entity_created) --en
 script.on_event(defines.events.on_built_entity, on_some_entity_created) --created_entity
 script.on_event(defines.events.on_robot_built_entity, on_some_entity_created) --created_entity
 script.on_event(defines.events.on_player_rotated_entity, on_some_entity_updated) --entity
+script.on_event(defines.events.on_gui_closed, function (event) if event.entity ~= nil then on_some_entity_updated(event) end end) --entity
+script.on_event(defines.events.on_entity_renamed, on_some_entity_updated) --entity
+script.on_event(defines.events.on_entity_settings_pasted, function (event) on_some_entity_updated({tick=event.tick, entity=event.destination}) end) --destination
 script.on_event(defines.events.on_built_entity, on_some_entity_created) --entity
 
 script.on_event(defines.events.on_entity_died, on_some_entity_deleted) --entity
@@ -1199,6 +1324,7 @@ script.on_event(defines.events.on_resource_depleted, on_some_entity_deleted) --e
 
 script.on_event(defines.events.on_script_path_request_finished, on_script_path_request_finished)
 script.on_event(defines.events.on_research_finished, on_research_finished)
+script.on_event(defines.events.on_research_started, on_research_started)
 
 script.on_event(defines.events.on_player_main_inventory_changed, on_player_main_inventory_changed)
 script.on_event(defines.events.on_player_changed_position, on_player_changed_position)
@@ -1376,6 +1502,134 @@ function rcon_remove_from_inventory(player_id, entity_name, entity_pos, inventor
 	end
 end
 
+function rcon_connect_wire(player_id, wire, from_name, from_pos, from_circuit_id, to_name, to_pos, to_circuit_id)
+	local player = game.players[player_id]
+	local from = player.surface.find_entity(from_name, from_pos)
+	local to = player.surface.find_entity(to_name, to_pos)
+	if from == nil or to == nil then
+		complain("cannot connect "..wire.." wire between "..from_name.." at "..pos_str(from_pos).." and "..to_name.." at "..pos_str(to_pos))
+		return
+	end
+	local wire_item = wire.."-wire"
+	if player.get_item_count(wire_item) < 1 then
+		complain("player #"..player_id.." has no "..wire_item)
+		return
+	end
+	local connected = from.connect_neighbour({wire=defines.wire_type[wire], target_entity=to, source_circuit_id=from_circuit_id, target_circuit_id=to_circuit_id})
+	if not connected then
+		complain("failed to connect "..wire.." wire between "..from_name.." at "..pos_str(from_pos).." and "..to_name.." at "..pos_str(to_pos))
+		return
+	end
+	player.remove_item({name=wire_item, count=1})
+	-- wires do not raise any events
+	on_some_entity_updated({tick=game.tick, entity=from})
+	on_some_entity_updated({tick=game.tick, entity=to})
+end
+
+function signal_id_from_json(signal)
+	if signal == nil then
+		return nil
+	end
+	return {type=signal.signalType, name=signal.name}
+end
+
+function rcon_set_combinator(name, pos, json)
+	local entity = game.surfaces[1].find_entity(name, pos)
+	if entity == nil then
+		complain("cannot set combinator "..name.." at "..pos_str(pos))
+		return
+	end
+	local settings = game.json_to_table(json)
+	local control_behavior = entity.get_or_create_control_behavior()
+	if entity.type == "constant-combinator" then
+		for index, constant in pairs(settings.constants or {}) do
+			control_behavior.set_signal(index, {signal=signal_id_from_json(constant.signal), count=constant.count})
+		end
+	elseif entity.type == "arithmetic-combinator" then
+		control_behavior.parameters = {parameters = {
+			first_signal = signal_id_from_json(settings.firstSignal),
+			second_signal = signal_id_from_json(settings.secondSignal),
+			second_constant = settings.constant,
+			operation = settings.operation,
+			output_signal = signal_id_from_json(settings.outputSignal)
+		}}
+	elseif entity.type == "decider-combinator" then
+		control_behavior.parameters = {parameters = {
+			first_signal = signal_id_from_json(settings.firstSignal),
+			second_signal = signal_id_from_json(settings.secondSignal),
+			constant = settings.constant,
+			comparator = settings.operation,
+			output_signal = signal_id_from_json(settings.outputSignal),
+			copy_count_from_input = settings.copyCountFromInput
+		}}
+	else
+		complain(name.." at "..pos_str(pos).." is no combinator")
+		return
+	end
+	on_some_entity_updated({tick=game.tick, entity=entity})
+end
+
+function rcon_circuit_signals(name, pos, wire, circuit_id)
+	local entity = game.surfaces[1].find_entity(name, pos)
+	if entity == nil then
+		complain("cannot find "..name.." at "..pos_str(pos))
+		return
+	end
+	local result = {}
+	local network = entity.get_circuit_network(defines.wire_type[wire], circuit_id)
+	if network ~= nil and network.signals ~= nil then
+		for _, signal in pairs(network.signals) do
+			table.insert(result, {signal={signalType=signal.signal.type, name=signal.signal.name}, count=signal.count})
+		end
+	end
+	rcon.print(game.table_to_json(result))
+end
+
+function rcon_place_rail(player_id, name, pos, direction)
+	local player = game.players[player_id]
+	local surface = player.surface
+	-- curved rails are built from 4 rail items
+	local count = 1
+	if name == "curved-rail" then count = 4 end
+
+	if player.get_item_count("rail") < count then
+		complain("cannot place "..name.." because the player '"..player.name.."' does not have "..count.."x rail")
+		return
+	end
+	if not surface.can_place_entity{name=name, position=pos, direction=direction, force=player.force, build_check_type=defines.build_check_type.manual} then
+		complain("cannot place "..name.." at "..pos_str(pos).." because surface.can_place_entity said 'no'")
+		return
+	end
+
+	player.remove_item({name="rail", count=count})
+	local result = surface.create_entity{name=name, position=pos, direction=direction, force=player.force, player=player}
+	if result == nil then
+		complain("placing "..name.." failed, surface.create_entity returned nil :(")
+	else
+		on_some_entity_created({tick=game.tick, entity=result})
+		rcon.print(game.table_to_json(serialize_entity(result)))
+	end
+end
+
+function rcon_set_train_schedule(pos, json)
+	local locomotives = game.surfaces[1].find_entities_filtered{type="locomotive", position=pos, radius=3}
+	if #locomotives == 0 then
+		complain("no locomotive found at "..pos_str(pos))
+		return
+	end
+	local records = {}
+	for _, record in pairs(game.json_to_table(json)) do
+		local wait_condition = {type=record.waitCondition, compare_type="or"}
+		if record.ticks ~= nil then
+			wait_condition.ticks = record.ticks
+		end
+		table.insert(records, {station=record.station, wait_conditions={wait_condition}})
+	end
+	local train = locomotives[1].train
+	train.schedule = {current=1, records=records}
+	train.manual_mode = false
+end
+
 function rcon_whoami(who)
 	if client_local_data.whoami == nil then
 		client_local_data.whoami = who
@@ -1439,19 +1693,7 @@ function rcon_inventory_contents_at(positions)
 	for k,v in pairs(positions) do
 		local entity = surface.find_entity(v.name, v.position)
 		if entity ~= nil then
-			local rec = {}
-			local output_inventory = entity.get_output_inventory()
-			if output_inventory ~= nil then
-				rec.outputInventory = output_inventory.get_contents()
-			else
-				rec.outputInventory = nil
-			end
-			local fuel_inventory = entity.get_fuel_inventory()
-			if fuel_inventory ~= nil then
-				rec.fuelInventory = fuel_inventory.get_contents()
-			else
-				rec.fuelInventory = nil
-			end
+			local rec = serialize_entity_inventory(entity)
 			rec.name = v.name
 			rec.position = v.position
 			table.insert(result, rec)
@@ -1817,5 +2059,10 @@ remote.add_interface("botbridge", {
 	async_request_path=rcon_async_request_path,
 	action_start_walk_waypoints=rcon_action_start_walk_waypoints,
 	action_start_mining=rcon_action_start_mining,
-	action_start_crafting=rcon_action_start_crafting
+	action_start_crafting=rcon_action_start_crafting,
+	connect_wire=rcon_connect_wire,
+	set_combinator=rcon_set_combinator,
+	circuit_signals=rcon_circuit_signals,
+	place_rail=rcon_place_rail,
+	set_train_schedule=rcon_set_train_schedule
 })
@@ -84,6 +84,7 @@ function serialize_player(player)
     )
     local main_inventory = player.get_main_inventory()
     record.mainInventory = main_inventory.get_contents()
+    record.force = player.force.name
     return record
 end
 
@@ -109,6 +110,32 @@ function serialize_force(force)
         technologies[v.name] = serialize_technology(v)
     end
     record.technologies = technologies
+    record.researchQueue = serialize_research_queue(force)
+    return record
+end
+
+function serialize_research_queue(force)
+    local research_queue = nil
+    if force.research_queue ~= nil then
+        for _, v in pairs(force.research_queue) do
+            if research_queue == nil then
+                research_queue = {}
+            end
+            table.insert(research_queue, v.name)
+        end
+    end
+    return research_queue
+end
+
+function serialize_research_progress(force)
+    local record = {
+        forceName = force.name,
+        researchProgress = force.research_progress,
+        researchQueue = serialize_research_queue(force)
+    }
+    if force.current_research ~= nil then
+        record.currentResearch = force.current_research.name
+    end
     return record
 end
 
@@ -164,8 +191,18 @@ function serialize_technology(technology)
         end
         table.insert(prerequisites, v.name)
     end
+    local unlocks_recipes = nil
+    for _, effect in pairs(technology.effects) do
+        if effect.type == "unlock-recipe" then
+            if unlocks_recipes == nil then
+                unlocks_recipes = {}
+            end
+            table.insert(unlocks_recipes, effect.recipe)
+        end
+    end
     record.researchUnitIngredients = ingredients
     record.prerequisites = prerequisites
+    record.unlocksRecipes = unlocks_recipes
     return record
 end
 
@@ -206,6 +243,21 @@ function serialize_entity_prototype(entity)
     record.miningSpeed = entity.mining_speed
     record.craftingSpeed = entity.crafting_speed
     record.mineResult = mine_result
+    if entity.type == "electric-pole" then
+        record.supplyAreaDistance = entity.supply_area_distance
+        record.maxWireDistance = entity.max_wire_distance
+    end
+    -- energy values are given per tick, we want watts
+    if entity.electric_energy_source_prototype ~= nil and entity.energy_usage ~= nil and entity.energy_usage > 0 then
+        record.electricEnergyUsage = entity.energy_usage * 60
+    end
+    if entity.max_energy_production ~= nil and entity.max_energy_production > 0 then
+        record.maxEnergyProduction = entity.max_energy_production * 60
+    end
+    record.beltSpeed = entity.belt_speed
+    record.inserterRotationSpeed = entity.inserter_rotation_speed
+    record.baseProductivity = entity.base_productivity
+    record.miningDrillRadius = entity.mining_drill_radius
     if fluidbox_found then
         record.fluidboxPrototypes = fluidbox_prototypes
     end
@@ -218,6 +270,9 @@ end
 function serialize_entity(entity)
     local record = table_properties(entity, {"name", "direction", "type", "position", "drop_position"}, {type = "entityType", drop_position = "dropPosition"})
     record.boundingBox = table_properties(entity.bounding_box, {"left_top", "right_bottom"}, {left_top = "leftTop", right_bottom = "rightBottom"})
+    if entity.force ~= nil then
+        record.force = entity.force.name
+    end
     local output_inventory = entity.get_output_inventory()
     if output_inventory ~= nil then
         record.outputInventory = output_inventory.get_contents()
@@ -226,11 +281,17 @@ function serialize_entity(entity)
     if fuel_inventory ~= nil then
         record.fuelInventory = fuel_inventory.get_contents()
     end
+    local input_inventory = get_input_inventory(entity)
+    if input_inventory ~= nil then
+        record.inputInventory = input_inventory.get_contents()
+    end
 
     if entity.type == "resource" then
         record.amount = entity.amount
     elseif entity.type == "inserter" then
         record.pickupPosition = entity.pickup_position
+    elseif entity.type == "item-entity" then
+        record.outputInventory = {[entity.stack.name] = entity.stack.count}
     elseif entity.type == "entity-ghost" then
         record.ghostName = entity.ghost_name
         record.ghostType = entity.ghost_type
@@ -245,10 +306,117 @@ function serialize_entity(entity)
         if recipe ~= nil then
             record.recipe = recipe.name
         end
+    elseif entity.type == "constant-combinator" or entity.type == "arithmetic-combinator" or entity.type == "decider-combinator" then
+        record.combinator = serialize_combinator(entity)
+    elseif entity.type == "train-stop" then
+        record.stationName = entity.backer_name
+    elseif entity.type == "underground-belt" then
+        record.beltToGroundType = entity.belt_to_ground_type
+    end
+    record.wireConnections = serialize_wire_connections(entity)
+    return record
+end
+
+function serialize_wire_connections(entity)
+    local definitions = entity.circuit_connection_definitions
+    if definitions == nil or #definitions == 0 then
+        return nil
+    end
+    local wires = {}
+    for _, definition in pairs(definitions) do
+        table.insert(wires, {
+            wire = definition.wire == defines.wire_type.red and "red" or "green",
+            targetName = definition.target_entity.name,
+            targetPosition = definition.target_entity.position,
+            sourceCircuitId = definition.source_circuit_id,
+            targetCircuitId = definition.target_circuit_id
+        })
+    end
+    return wires
+end
+
+function serialize_signal_id(signal)
+    if signal == nil or signal.name == nil then
+        return nil
+    end
+    return {signalType = signal.type, name = signal.name}
+end
+
+function serialize_combinator(entity)
+    local control_behavior = entity.get_control_behavior()
+    if control_behavior == nil then
+        return nil
+    end
+    local parameters = control_behavior.parameters
+    if parameters ~= nil and parameters.parameters ~= nil then
+        parameters = parameters.parameters
+    end
+    if parameters == nil then
+        return nil
+    end
+    local record = {}
+    if entity.type == "constant-combinator" then
+        local constants = {}
+        for _, parameter in pairs(parameters) do
+            if parameter.signal ~= nil and parameter.signal.name ~= nil then
+                table.insert(constants, {signal = serialize_signal_id(parameter.signal), count = parameter.count})
+            end
+        end
+        if #constants > 0 then
+            record.constants = constants
+        end
+        return record
+    end
+    record.firstSignal = serialize_signal_id(parameters.first_signal)
+    record.secondSignal = serialize_signal_id(parameters.second_signal)
+    record.outputSignal = serialize_signal_id(parameters.output_signal)
+    if entity.type == "arithmetic-combinator" then
+        record.operation = parameters.operation
+        record.constant = parameters.second_constant
+    else
+        record.operation = parameters.comparator
+        record.constant = parameters.constant
+        record.copyCountFromInput = parameters.copy_count_from_input
+    end
+    return record
+end
+
+function get_input_inventory(entity)
+    if entity.type == "furnace" then
+        return entity.get_inventory(defines.inventory.furnace_source)
+    elseif entity.type == "assembling-machine" then
+        return entity.get_inventory(defines.inventory.assembling_machine_input)
+    elseif entity.type == "lab" then
+        return entity.get_inventory(defines.inventory.lab_input)
+    end
+    return nil
+end
+
+function serialize_entity_inventory(entity)
+    local record = table_properties(entity, {"name", "position"})
+    local output_inventory = entity.get_output_inventory()
+    if output_inventory ~= nil then
+        record.outputInventory = output_inventory.get_contents()
+    end
+    local input_inventory = get_input_inventory(entity)
+    if input_inventory ~= nil then
+        record.inputInventory = input_inventory.get_contents()
+    end
+    local fuel_inventory = entity.get_fuel_inventory()
+    if fuel_inventory ~= nil then
+        record.fuelInventory = fuel_inventory.get_contents()
     end
     return record
 end
 
+function serialize_ground_item(entity)
+    return {
+        name = entity.stack.name,
+        count = entity.stack.count,
+        position = entity.position
+    }
+end
+
 function serialize_tile(tile)
     local record = table_properties(tile, {"name", "position"})
     record.playerCollidable = tile.collides_with('player-layer')